@@ -0,0 +1,186 @@
+//! Handlers for [actix-web](https://actix.rs).
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::http::header::{SEC_WEBSOCKET_PROTOCOL, UPGRADE};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::handler::{Protocols, Subscription};
+use crate::{Context, GatewayServer, RequestData, Response};
+
+/// Create a context from the actix request, it contains the headers and the peer address.
+pub fn context(req: &HttpRequest) -> Context {
+    let mut headers = http::HeaderMap::with_capacity(req.headers().len());
+    for (name, value) in req.headers() {
+        headers.append(name.clone(), value.clone());
+    }
+    Context::new(headers).with_peer_addr(req.peer_addr())
+}
+
+/// Convert a response of the gateway into an actix response.
+pub fn into_http_response(resp: Response) -> HttpResponse {
+    let status = if resp.is_request_error() {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::OK
+    };
+    let mut builder = HttpResponse::build(status);
+    for (name, value) in &resp.headers {
+        builder.append_header((name.as_str(), value.as_bytes()));
+    }
+    match serde_json::to_string(&resp) {
+        Ok(body) => builder.content_type("application/json").body(body),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+/// Request handler
+pub async fn graphql_request(
+    server: actix_web::web::Data<GatewayServer>,
+    request: actix_web::web::Json<RequestData>,
+    req: HttpRequest,
+) -> HttpResponse {
+    into_http_response(server.execute(request.into_inner(), context(&req)).await)
+}
+
+/// Handler of `GET` requests, register it instead of `graphql_subscription` to serve queries sent with `GET` too.
+/// Websocket upgrades start a subscription, other requests execute the operation of the query string.
+pub async fn graphql_get(
+    server: actix_web::web::Data<GatewayServer>,
+    req: HttpRequest,
+    payload: actix_web::web::Payload,
+) -> HttpResponse {
+    let upgrade = req.headers().get(UPGRADE).and_then(|header| header.to_str().ok());
+    if upgrade.is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) {
+        return graphql_subscription(server, req, payload).await;
+    }
+    let resp = match RequestData::from_query_string(req.query_string()) {
+        Ok(request) => server.execute(request, context(&req)).await,
+        Err(err) => Response::error(err.to_string()),
+    };
+    into_http_response(resp)
+}
+
+/// Subscription handler
+pub async fn graphql_subscription(
+    server: actix_web::web::Data<GatewayServer>,
+    req: HttpRequest,
+    payload: actix_web::web::Payload,
+) -> HttpResponse {
+    let ctx = context(&req);
+    start_subscription(&server, ctx, &req, payload).await
+}
+
+/// Start a websocket connection for subscriptions with a custom context.
+pub async fn start_subscription(
+    server: &GatewayServer,
+    ctx: Context,
+    req: &HttpRequest,
+    payload: actix_web::web::Payload,
+) -> HttpResponse {
+    let protocols = req.headers().get(SEC_WEBSOCKET_PROTOCOL).and_then(|header| header.to_str().ok());
+    let protocol = protocols
+        .and_then(|protocols| {
+            protocols.split(',').find_map(|p| Protocols::from_str(p.trim()).ok())
+        })
+        .unwrap_or(Protocols::SubscriptionsTransportWS);
+    if let Some((composed_schema, route_table)) = server.table.get().await {
+        let protocols = [protocol.sec_websocket_protocol()];
+        let subscription = Subscription::new(composed_schema, route_table, Arc::new(ctx), protocol, server.options.clone(), server.table.metrics());
+        return match actix_web_actors::ws::WsResponseBuilder::new(subscription, req, payload)
+            .protocols(&protocols)
+            .start() {
+            Ok(r) => r,
+            Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+        };
+    }
+    HttpResponse::InternalServerError().finish()
+}
+
+/// Liveness handler, register it as `/health`. It responds with `200` as long as the gateway process serves requests.
+pub async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness handler, register it as `/ready`. It responds with `503` until the schema has been composed,
+/// the body contains the reachability of every subgraph.
+pub async fn ready(server: actix_web::web::Data<GatewayServer>) -> HttpResponse {
+    let status = server.status().await;
+    let status_code = if status.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    HttpResponse::build(status_code).json(status)
+}
+
+/// Status handler, register it as `/status`. See `GatewayStatus`.
+pub async fn status(server: actix_web::web::Data<GatewayServer>) -> HttpResponse {
+    HttpResponse::Ok().json(server.status().await)
+}
+
+/// Prometheus exposition handler, register it as `/metrics` and pass `PrometheusMetrics` into app_data.
+#[cfg(feature = "prometheus")]
+pub async fn metrics(metrics: actix_web::web::Data<crate::prometheus::PrometheusMetrics>) -> HttpResponse {
+    match metrics.render() {
+        Ok(body) => HttpResponse::Ok().content_type(metrics.content_type()).body(body),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing::MockSource;
+
+    async fn server() -> GatewayServer {
+        let accounts = MockSource::new("accounts", "type Query { me: User } type User { id: ID! }")
+            .query_value("me", json!({ "id": "1" }));
+        let server = GatewayServer::builder().with_middleware_source(accounts).build();
+        server.update_schema().await.expect("composed schema");
+        server
+    }
+
+    #[actix_web::test]
+    async fn route_requests() {
+        let server = web::Data::new(server().await);
+        let app = test::init_service(
+            App::new()
+                .app_data(server)
+                .route("/", web::post().to(graphql_request))
+                .route("/", web::get().to(graphql_get)),
+        )
+            .await;
+
+        let req = test::TestRequest::post().uri("/").set_json(json!({ "query": "{ me { id } }" })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, json!({ "data": { "me": { "id": "1" } } }));
+
+        let req = test::TestRequest::get().uri("/?query=%7B%20me%20%7B%20id%20%7D%20%7D").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body, json!({ "data": { "me": { "id": "1" } } }));
+
+        let req = test::TestRequest::get().uri("/?query=mutation%20%7B%20logout%20%7D").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["errors"][0]["message"], "Mutations can only be sent with POST.");
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header(("upgrade", "websocket"))
+            .insert_header(("connection", "upgrade"))
+            .insert_header(("sec-websocket-version", "13"))
+            .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="))
+            .insert_header(("sec-websocket-protocol", "graphql-transport-ws"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(resp.headers().get(SEC_WEBSOCKET_PROTOCOL).unwrap(), "graphql-transport-ws");
+    }
+}
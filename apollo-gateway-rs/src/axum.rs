@@ -0,0 +1,195 @@
+//! Handlers for [axum](https://github.com/tokio-rs/axum).
+//!
+//! ```rust,ignore
+//! use std::sync::Arc;
+//! use axum::{routing::{get, post}, Router};
+//! use apollo_gateway_rs::axum::{graphql_get, graphql_request};
+//!
+//! let app = Router::new()
+//!     .route("/", post(graphql_request))
+//!     .route("/", get(graphql_get))
+//!     .with_state(Arc::new(gateway_server));
+//! ```
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, RawQuery, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use futures_util::{future, SinkExt, StreamExt};
+
+use crate::{Context, GatewayServer, Protocols, RequestData, Response};
+
+const SEC_WEBSOCKET_PROTOCOL: &str = "sec-websocket-protocol";
+
+/// Create a context from the request headers and the peer address.
+/// The peer address is only known if the app is served with `into_make_service_with_connect_info`.
+pub fn context(headers: HeaderMap, connect_info: Option<ConnectInfo<SocketAddr>>) -> Context {
+    Context::new(headers).with_peer_addr(connect_info.map(|ConnectInfo(addr)| addr))
+}
+
+/// Convert a response of the gateway into an axum response.
+pub fn into_http_response(resp: Response) -> axum::response::Response {
+    let status = if resp.is_request_error() {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::OK
+    };
+    let headers = resp.headers.clone();
+    (status, headers, Json(resp)).into_response()
+}
+
+/// Request handler
+pub async fn graphql_request(
+    State(server): State<Arc<GatewayServer>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(request): Json<RequestData>,
+) -> axum::response::Response {
+    into_http_response(server.execute(request, context(headers, connect_info)).await)
+}
+
+/// Handler of `GET` requests, register it instead of `graphql_subscription` to serve queries sent with `GET` too.
+/// Websocket upgrades start a subscription, other requests execute the operation of the query string.
+pub async fn graphql_get(
+    state: State<Arc<GatewayServer>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    upgrade: Option<WebSocketUpgrade>,
+    RawQuery(query): RawQuery,
+) -> axum::response::Response {
+    if let Some(upgrade) = upgrade {
+        return graphql_subscription(state, connect_info, headers, upgrade).await;
+    }
+    let State(server) = state;
+    let resp = match RequestData::from_query_string(query.as_deref().unwrap_or_default()) {
+        Ok(request) => server.execute(request, context(headers, connect_info)).await,
+        Err(err) => Response::error(err.to_string()),
+    };
+    into_http_response(resp)
+}
+
+/// Subscription handler
+pub async fn graphql_subscription(
+    State(server): State<Arc<GatewayServer>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> axum::response::Response {
+    let protocol = headers
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|protocols| {
+            protocols.split(',').find_map(|p| Protocols::from_str(p.trim()).ok())
+        })
+        .unwrap_or(Protocols::SubscriptionsTransportWS);
+    let ctx = Arc::new(context(headers, connect_info));
+    upgrade
+        .protocols([protocol.sec_websocket_protocol().to_string()])
+        .on_upgrade(move |socket| serve(server, ctx, protocol, socket))
+}
+
+/// Liveness handler, register it as `/health`. It responds with `200` as long as the gateway process serves requests.
+pub async fn health() -> axum::response::Response {
+    Json(serde_json::json!({ "status": "ok" })).into_response()
+}
+
+/// Readiness handler, register it as `/ready`. It responds with `503` until the schema has been composed,
+/// the body contains the reachability of every subgraph.
+pub async fn ready(State(server): State<Arc<GatewayServer>>) -> axum::response::Response {
+    let status = server.status().await;
+    let status_code = if status.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status_code, Json(status)).into_response()
+}
+
+/// Status handler, register it as `/status`. See `GatewayStatus`.
+pub async fn status(State(server): State<Arc<GatewayServer>>) -> axum::response::Response {
+    Json(server.status().await).into_response()
+}
+
+/// Prometheus exposition handler, register it as `/metrics` with an `Extension` layer of `PrometheusMetrics`.
+#[cfg(feature = "prometheus")]
+pub async fn metrics(
+    axum::Extension(metrics): axum::Extension<crate::prometheus::PrometheusMetrics>,
+) -> axum::response::Response {
+    match metrics.render() {
+        Ok(body) => ([(axum::http::header::CONTENT_TYPE, metrics.content_type())], body).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn serve(server: Arc<GatewayServer>, ctx: Arc<Context>, protocol: Protocols, socket: WebSocket) {
+    let (sink, stream) = socket.split();
+    let incoming = stream
+        .take_while(|message| future::ready(message.is_ok()))
+        .filter_map(|message| async move {
+            match message {
+                Ok(Message::Text(text)) => Some(text),
+                _ => None,
+            }
+        });
+    let outgoing = sink.with(|text: String| future::ok::<_, axum::Error>(Message::Text(text)));
+    futures_util::pin_mut!(incoming, outgoing);
+    server.serve_websocket(ctx, protocol, incoming, outgoing).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use axum::routing::get;
+    use axum::Router;
+    use serde_json::{json, Value};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    use super::*;
+    use crate::testing::MockSource;
+
+    async fn server() -> GatewayServer {
+        let accounts = MockSource::new("accounts", "type Query { me: User } type User { id: ID! }")
+            .query_value("me", json!({ "id": "1" }));
+        let server = GatewayServer::builder().with_middleware_source(accounts).build();
+        server.update_schema().await.expect("composed schema");
+        server
+    }
+
+    #[tokio::test]
+    async fn route_requests() {
+        let app = Router::new()
+            .route("/", get(graphql_get).post(graphql_request))
+            .with_state(Arc::new(server().await));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        let client = reqwest::Client::new();
+
+        let resp = client.post(format!("http://{addr}/")).json(&json!({ "query": "{ me { id } }" })).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body, json!({ "data": { "me": { "id": "1" } } }));
+
+        let resp = client.get(format!("http://{addr}/")).query(&[("query", "{ me { id } }")]).send().await.unwrap();
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body, json!({ "data": { "me": { "id": "1" } } }));
+
+        let resp = client.get(format!("http://{addr}/")).query(&[("query", "mutation { logout }")]).send().await.unwrap();
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["errors"][0]["message"], "Mutations can only be sent with POST.");
+
+        let mut req = format!("ws://{addr}/").into_client_request().unwrap();
+        req.headers_mut().insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static("graphql-transport-ws"));
+        let (mut socket, resp) = tokio_tungstenite::connect_async(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(resp.headers()[SEC_WEBSOCKET_PROTOCOL], "graphql-transport-ws");
+        socket.send(WsMessage::Text(json!({ "type": "connection_init" }).to_string())).await.unwrap();
+        let ack = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&ack).unwrap(), json!({ "type": "connection_ack" }));
+    }
+}
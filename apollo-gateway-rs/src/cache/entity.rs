@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use tokio::time::{Duration, Instant};
+use value::ConstValue;
+
+use crate::schema::{CacheScope, ComposedSchema};
+
+use super::{hash_parts, CacheEntry, CacheStorage, InMemoryCacheStorage};
+
+/// Capacity of the invalidation index of `EntityCache::new`.
+const DEFAULT_INDEX_CAPACITY: usize = 10_000;
+
+struct IndexedEntity {
+    entity_type: String,
+    representation: ConstValue,
+    expires_at: Instant,
+}
+
+/// Cache of entities resolved by `_entities` fetches.
+///
+/// The time to live of an entity is taken from `@cacheControl(maxAge:)` of the entity type,
+/// then from the configuration of the source and then from `EntityCache::default_max_age`.
+/// Entities with `PRIVATE` scope are never cached.
+///
+/// Invalidation works through an index kept in the gateway process,
+/// so entities cached by other gateway instances in a shared storage are not invalidated.
+/// The index is bounded by `EntityCache::index_capacity`, the least recently used entities are removed
+/// from the storage together with their index entries once it is full.
+/// Index entries of expired entities are removed when the entity is read.
+pub struct EntityCache {
+    storage: Arc<dyn CacheStorage>,
+    default_max_age: Option<u64>,
+    service_max_age: HashMap<String, u64>,
+    index_capacity: usize,
+    index: Mutex<LruCache<String, IndexedEntity>>,
+}
+
+impl EntityCache {
+    pub fn new(storage: impl CacheStorage) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            default_max_age: None,
+            service_max_age: Default::default(),
+            index_capacity: 0,
+            index: Mutex::new(LruCache::unbounded()),
+        }
+        .index_capacity(DEFAULT_INDEX_CAPACITY)
+    }
+
+    /// Entity cache with `InMemoryCacheStorage`, the index has the same capacity as the storage.
+    pub fn in_memory(capacity: usize) -> Self {
+        Self::new(InMemoryCacheStorage::new(capacity)).index_capacity(capacity)
+    }
+
+    /// Maximum number of cached entities which can be invalidated. Default is 10000.
+    pub fn index_capacity(mut self, index_capacity: usize) -> Self {
+        if let Some(capacity) = NonZeroUsize::new(index_capacity) {
+            self.index.get_mut().unwrap_or_else(|err| err.into_inner()).resize(capacity);
+        }
+        Self {
+            index_capacity,
+            ..self
+        }
+    }
+
+    /// Time to live in seconds of entities which have no other configuration.
+    pub fn default_max_age(self, default_max_age: u64) -> Self {
+        Self {
+            default_max_age: Some(default_max_age),
+            ..self
+        }
+    }
+
+    /// Time to live in seconds of entities fetched from the service.
+    /// Overrides `RemoteGraphQLDataSource::entity_cache_max_age` of the source.
+    pub fn service_max_age(mut self, service: impl Into<String>, max_age: u64) -> Self {
+        self.service_max_age.insert(service.into(), max_age);
+        self
+    }
+
+    pub(crate) fn set_service_max_age_if_absent(&mut self, service: &str, max_age: u64) {
+        self.service_max_age.entry(service.to_string()).or_insert(max_age);
+    }
+
+    /// Returns `None` if entities of the type fetched from the service must not be cached.
+    pub(crate) fn max_age(&self, schema: &ComposedSchema, service: &str, entity_type: &str) -> Option<u64> {
+        let cache_control = schema.types.get(entity_type).and_then(|ty| ty.cache_control);
+        if cache_control.and_then(|cache_control| cache_control.scope) == Some(CacheScope::Private) {
+            return None;
+        }
+        cache_control
+            .and_then(|cache_control| cache_control.max_age)
+            .or_else(|| self.service_max_age.get(service).copied())
+            .or(self.default_max_age)
+            .filter(|max_age| *max_age > 0)
+    }
+
+    pub(crate) fn key(&self, service: &str, entity_type: &str, query: &str, representation: &ConstValue) -> String {
+        let representation = serde_json::to_string(representation).unwrap_or_default();
+        format!(
+            "entity:{}:{}:{}",
+            service,
+            entity_type,
+            hash_parts(&[query, representation.as_str()])
+        )
+    }
+
+    pub(crate) async fn get(&self, key: &str) -> Option<ConstValue> {
+        let entry = self.storage.get(key).await;
+        let mut index = self.index.lock().unwrap_or_else(|err| err.into_inner());
+        match &entry {
+            Some(_) => index.promote(key),
+            None => {
+                index.pop(key);
+            }
+        }
+        entry.map(|entry| entry.data)
+    }
+
+    pub(crate) async fn set(
+        &self,
+        key: String,
+        entity_type: &str,
+        representation: ConstValue,
+        data: ConstValue,
+        max_age: u64,
+    ) {
+        if self.index_capacity == 0 {
+            return;
+        }
+        let evicted = self
+            .index
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(key.clone(), IndexedEntity {
+                entity_type: entity_type.to_string(),
+                representation,
+                expires_at: Instant::now() + Duration::from_secs(max_age),
+            })
+            .map(|(evicted, _)| evicted)
+            .filter(|evicted| *evicted != key);
+        // An entity which is not indexed could not be invalidated anymore.
+        if let Some(evicted) = evicted {
+            self.storage.remove(&evicted).await;
+        }
+        self.storage.set(key, CacheEntry::new(data, max_age)).await;
+    }
+
+    /// Remove cached entities of the type which have the given key fields, e.g. `{"id": "1"}`.
+    /// Returns the number of removed entries.
+    pub async fn invalidate(&self, entity_type: &str, key_fields: &ConstValue) -> usize {
+        self.remove_indexed(|entity| {
+            entity.entity_type == entity_type && contains_fields(&entity.representation, key_fields)
+        })
+        .await
+    }
+
+    /// Remove all cached entities of the type. Returns the number of removed entries.
+    pub async fn invalidate_type(&self, entity_type: &str) -> usize {
+        self.remove_indexed(|entity| entity.entity_type == entity_type).await
+    }
+
+    /// Expired entities are removed as well, but they are not counted.
+    async fn remove_indexed(&self, predicate: impl Fn(&IndexedEntity) -> bool) -> usize {
+        let (keys, removed) = {
+            let now = Instant::now();
+            let mut index = self.index.lock().unwrap_or_else(|err| err.into_inner());
+            let keys = index
+                .iter()
+                .filter(|(_, entity)| predicate(entity))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            let removed = keys
+                .iter()
+                .filter_map(|key| index.pop(key))
+                .filter(|entity| entity.expires_at > now)
+                .count();
+            (keys, removed)
+        };
+        for key in &keys {
+            self.storage.remove(key).await;
+        }
+        removed
+    }
+}
+
+fn contains_fields(representation: &ConstValue, fields: &ConstValue) -> bool {
+    match (representation, fields) {
+        (ConstValue::Object(representation), ConstValue::Object(fields)) => fields.iter().all(|(name, value)| {
+            representation
+                .get(name)
+                .map(|field| contains_fields(field, value))
+                .unwrap_or_default()
+        }),
+        (representation, fields) => representation == fields,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn representation(id: &str, org: &str) -> ConstValue {
+        ConstValue::from_json(serde_json::json!({
+            "__typename": "User",
+            "id": id,
+            "organization": { "id": org }
+        }))
+        .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn invalidate_by_key_fields() {
+        let cache = EntityCache::in_memory(16);
+        for (id, org) in [("1", "a"), ("2", "a"), ("1", "b")] {
+            let key = cache.key("users", "User", "query", &representation(id, org));
+            cache.set(key, "User", representation(id, org), ConstValue::Null, 60).await;
+        }
+        let key_fields = ConstValue::from_json(serde_json::json!({ "id": "1" })).unwrap_or_default();
+        assert_eq!(cache.invalidate("User", &key_fields).await, 2);
+        assert!(cache.get(&cache.key("users", "User", "query", &representation("1", "a"))).await.is_none());
+        assert!(cache.get(&cache.key("users", "User", "query", &representation("2", "a"))).await.is_some());
+        assert_eq!(cache.invalidate_type("User").await, 1);
+    }
+
+    #[tokio::test]
+    async fn bound_the_index() {
+        let cache = EntityCache::new(InMemoryCacheStorage::new(16)).index_capacity(2);
+        let keys = [("1", "a"), ("2", "a"), ("3", "a")]
+            .into_iter()
+            .map(|(id, org)| cache.key("users", "User", "query", &representation(id, org)))
+            .collect::<Vec<_>>();
+        for (key, id) in keys.iter().zip(["1", "2", "3"]) {
+            cache.set(key.clone(), "User", representation(id, "a"), ConstValue::Null, 60).await;
+        }
+        assert_eq!(cache.index.lock().unwrap_or_else(|err| err.into_inner()).len(), 2);
+        assert!(cache.get(&keys[0]).await.is_none());
+        assert!(cache.get(&keys[1]).await.is_some());
+        assert_eq!(cache.invalidate_type("User").await, 2);
+        assert!(cache.get(&keys[2]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn remove_expired_entities_on_read() {
+        let cache = EntityCache::in_memory(16);
+        let key = cache.key("users", "User", "query", &representation("1", "a"));
+        cache.set(key.clone(), "User", representation("1", "a"), ConstValue::Null, 0).await;
+        assert_eq!(cache.index.lock().unwrap_or_else(|err| err.into_inner()).len(), 1);
+        assert!(cache.get(&key).await.is_none());
+        assert!(cache.index.lock().unwrap_or_else(|err| err.into_inner()).is_empty());
+        assert_eq!(cache.invalidate_type("User").await, 0);
+    }
+}
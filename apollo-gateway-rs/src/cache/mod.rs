@@ -0,0 +1,264 @@
+#![forbid(unsafe_code)]
+
+mod entity;
+mod policy;
+mod storage;
+
+use std::sync::Arc;
+
+use parser::types::ExecutableDocument;
+use sha2::{Digest, Sha256};
+use value::ConstValue;
+
+use crate::datasource::Context;
+use crate::planner::RequestData;
+use crate::schema::ComposedSchema;
+
+pub use crate::schema::CacheScope;
+pub use entity::EntityCache;
+pub use policy::{operation_cache_policy, CachePolicy};
+pub use storage::{CacheEntry, CacheStorage, InMemoryCacheStorage};
+
+type PrivateIdFn = dyn Fn(&Context) -> Option<String> + Send + Sync;
+
+/// Full-response cache for query operations.
+///
+/// Cacheability is computed from `@cacheControl(maxAge:, scope:)` hints of your subgraphs and
+/// from `Cache-Control` headers of subgraph responses. Responses with `PRIVATE` scope are cached
+/// only if the user can be identified, see `ResponseCache::private_id`.
+pub struct ResponseCache {
+    storage: Arc<dyn CacheStorage>,
+    default_max_age: u64,
+    private_id: Option<Arc<PrivateIdFn>>,
+}
+
+/// Keys of one operation in the response cache.
+pub struct CacheLookup {
+    hash: String,
+    private_id: Option<String>,
+    pub policy: CachePolicy,
+}
+
+impl CacheLookup {
+    fn key(&self, scope: CacheScope) -> Option<String> {
+        match scope {
+            CacheScope::Public => Some(format!("response:{}", self.hash)),
+            CacheScope::Private => self
+                .private_id
+                .as_ref()
+                .map(|private_id| format!("response:{}:{}", self.hash, hash_parts(&[private_id.as_str()]))),
+        }
+    }
+}
+
+impl ResponseCache {
+    pub fn new(storage: impl CacheStorage) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            default_max_age: 0,
+            private_id: None,
+        }
+    }
+
+    /// Response cache with `InMemoryCacheStorage`
+    pub fn in_memory(capacity: usize) -> Self {
+        Self::new(InMemoryCacheStorage::new(capacity))
+    }
+
+    /// `maxAge` of root fields and fields returning a composite type which have no `@cacheControl` hint. Default is 0.
+    pub fn default_max_age(self, default_max_age: u64) -> Self {
+        Self {
+            default_max_age,
+            ..self
+        }
+    }
+
+    /// Identify the user of the request to cache responses with `PRIVATE` scope.
+    pub fn private_id<F>(self, private_id: F) -> Self
+        where
+            F: Fn(&Context) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            private_id: Some(Arc::new(private_id)),
+            ..self
+        }
+    }
+
+    /// Identify the user of the request by the value of a request header.
+    pub fn private_id_header(self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        self.private_id(move |ctx| {
+            ctx.headers()
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string)
+        })
+    }
+
+    /// Returns `None` if the operation can not be cached.
+    pub(crate) fn lookup(
+        &self,
+        schema: &ComposedSchema,
+        document: &ExecutableDocument,
+        request: &RequestData,
+        ctx: &Context,
+    ) -> Option<CacheLookup> {
+        let policy = operation_cache_policy(schema, document, request.operation.as_deref(), self.default_max_age)?;
+        if policy.max_age_or(self.default_max_age) == 0 {
+            return None;
+        }
+        let private_id = self.private_id.as_ref().and_then(|private_id| private_id(ctx));
+        if policy.scope == CacheScope::Private && private_id.is_none() {
+            return None;
+        }
+        let variables = serde_json::to_string(&request.variables).ok()?;
+        Some(CacheLookup {
+            hash: hash_parts(&[
+                request.query.as_str(),
+                request.operation.as_deref().unwrap_or_default(),
+                variables.as_str(),
+            ]),
+            private_id,
+            policy,
+        })
+    }
+
+    pub(crate) async fn get(&self, lookup: &CacheLookup) -> Option<CacheEntry> {
+        let mut scopes = vec![lookup.policy.scope];
+        // A subgraph could make a public operation private with its Cache-Control header.
+        if lookup.policy.scope == CacheScope::Public {
+            scopes.push(CacheScope::Private);
+        }
+        for key in scopes.into_iter().filter_map(|scope| lookup.key(scope)) {
+            if let Some(entry) = self.storage.get(&key).await {
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    pub(crate) async fn set(&self, lookup: &CacheLookup, policy: CachePolicy, data: ConstValue) {
+        let max_age = policy.max_age_or(self.default_max_age);
+        if max_age == 0 {
+            return;
+        }
+        if let Some(key) = lookup.key(policy.scope) {
+            let entry = CacheEntry {
+                scope: policy.scope,
+                ..CacheEntry::new(data, max_age)
+            };
+            self.storage.set(key, entry).await;
+        }
+    }
+
+    /// Value of the `Cache-Control` header of the client response.
+    pub(crate) fn cache_control(&self, policy: &CachePolicy) -> String {
+        policy.to_header(self.default_max_age)
+    }
+}
+
+pub(crate) fn hash_parts(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use value::Variables;
+
+    use crate::testing::MockSource;
+    use crate::GatewayServer;
+    use super::*;
+
+    const ACCOUNTS: &str = r#"
+        type Query {
+            user(id: ID!): User @cacheControl(maxAge: 60)
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    fn user_request(id: &str) -> RequestData {
+        RequestData::new("query($id: ID!) { user(id: $id) { name } }")
+            .variables(Variables::from_json(json!({ "id": id })))
+    }
+
+    #[test]
+    fn lookup_by_operation_and_variables() {
+        let schema = ComposedSchema::combine([("accounts".to_string(), parser::parse_schema(ACCOUNTS).unwrap())]).unwrap();
+        let cache = ResponseCache::in_memory(16);
+        let ctx = Context::default();
+        let lookup = |request: &RequestData| {
+            let document = parser::parse_query(&request.query).unwrap();
+            cache.lookup(&schema, &document, request, &ctx)
+        };
+
+        let first = lookup(&user_request("1")).expect("cacheable operation");
+        assert_eq!(first.policy.max_age, Some(60));
+        assert_eq!(lookup(&user_request("1")).map(|lookup| lookup.hash), Some(first.hash.clone()));
+        assert_ne!(lookup(&user_request("2")).map(|lookup| lookup.hash), Some(first.hash));
+        assert!(lookup(&RequestData::new("{ user(id: \"1\") { id } __typename }")).is_none());
+    }
+
+    #[tokio::test]
+    async fn cache_hits_and_misses() {
+        let schema = ComposedSchema::combine([("accounts".to_string(), parser::parse_schema(ACCOUNTS).unwrap())]).unwrap();
+        let cache = ResponseCache::in_memory(16);
+        let ctx = Context::default();
+        let lookup = |request: &RequestData| {
+            let document = parser::parse_query(&request.query).unwrap();
+            cache.lookup(&schema, &document, request, &ctx).expect("cacheable operation")
+        };
+
+        let first = lookup(&user_request("1"));
+        assert!(cache.get(&first).await.is_none());
+        let data = ConstValue::from_json(json!({ "user": { "name": "User 1" } })).unwrap();
+        cache.set(&first, first.policy, data.clone()).await;
+
+        let entry = cache.get(&lookup(&user_request("1"))).await.expect("cache hit");
+        assert_eq!(entry.data, data);
+        assert_eq!(entry.max_age, 60);
+        assert_eq!(entry.cache_control(), "max-age=60, public");
+        assert!(cache.get(&lookup(&user_request("2"))).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn serve_cached_responses() {
+        let accounts = MockSource::new("accounts", ACCOUNTS)
+            .query("user", |arguments| {
+                let id = arguments["id"].as_str().unwrap_or_default();
+                Ok(json!({ "id": id, "name": format!("User {}", id) }))
+            });
+        let server = GatewayServer::builder()
+            .with_middleware_source(accounts.clone())
+            .with_response_cache(ResponseCache::in_memory(16))
+            .build();
+        server.update_schema().await.expect("composed schema");
+
+        let miss = server.execute(user_request("1"), Default::default()).await;
+        assert!(miss.errors.is_empty(), "{:?}", miss.errors);
+        assert!(miss.headers.get("age").is_none());
+        assert_eq!(miss.headers.get("cache-control").and_then(|value| value.to_str().ok()), Some("max-age=60, public"));
+
+        let hit = server.execute(user_request("1"), Default::default()).await;
+        assert_eq!(hit.data, miss.data);
+        assert_eq!(hit.headers.get("age").and_then(|value| value.to_str().ok()), Some("0"));
+        assert_eq!(hit.headers.get("cache-control").and_then(|value| value.to_str().ok()), Some("max-age=60, public"));
+        assert_eq!(accounts.requests().len(), 1);
+
+        let other = server.execute(user_request("2"), Default::default()).await;
+        assert_eq!(
+            other.data.and_then(|data| data.into_json().ok()),
+            Some(json!({ "user": { "name": "User 2" } }))
+        );
+        assert_eq!(accounts.requests().len(), 2);
+    }
+}
@@ -0,0 +1,232 @@
+use parser::types::{DocumentOperations, ExecutableDocument, OperationDefinition, OperationType, Selection, SelectionSet};
+use parser::Positioned;
+
+use crate::schema::{CacheControl, CacheScope, ComposedSchema, MetaType};
+
+/// Cache policy of a response: the minimal `maxAge` of all the resolved fields and the most restrictive scope.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CachePolicy {
+    /// `None` means that nothing restricts the age of the response.
+    pub max_age: Option<u64>,
+    pub scope: CacheScope,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            scope: CacheScope::Public,
+        }
+    }
+}
+
+impl CachePolicy {
+    pub fn uncacheable() -> Self {
+        Self {
+            max_age: Some(0),
+            scope: CacheScope::Public,
+        }
+    }
+
+    pub fn restrict_max_age(&mut self, max_age: u64) {
+        self.max_age = Some(self.max_age.map_or(max_age, |current| current.min(max_age)));
+    }
+
+    pub fn restrict_scope(&mut self, scope: CacheScope) {
+        if scope == CacheScope::Private {
+            self.scope = CacheScope::Private;
+        }
+    }
+
+    pub fn restrict(&mut self, other: CachePolicy) {
+        if let Some(max_age) = other.max_age {
+            self.restrict_max_age(max_age);
+        }
+        self.restrict_scope(other.scope);
+    }
+
+    pub fn max_age_or(&self, default_max_age: u64) -> u64 {
+        self.max_age.unwrap_or(default_max_age)
+    }
+
+    /// Parse a `Cache-Control` header of a subgraph response.
+    pub fn from_header(value: &str) -> Self {
+        let mut policy = CachePolicy::default();
+        for directive in value.split(',').map(str::trim) {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "max-age" | "s-maxage" => {
+                    if let Some(max_age) = argument.and_then(|argument| argument.parse().ok()) {
+                        policy.restrict_max_age(max_age);
+                    }
+                }
+                "no-store" | "no-cache" => policy.restrict_max_age(0),
+                "private" => policy.restrict_scope(CacheScope::Private),
+                _ => {}
+            }
+        }
+        policy
+    }
+
+    /// Value of the `Cache-Control` header of the client response.
+    pub fn to_header(self, default_max_age: u64) -> String {
+        match self.max_age_or(default_max_age) {
+            0 => "no-store".to_string(),
+            max_age => match self.scope {
+                CacheScope::Public => format!("max-age={}, public", max_age),
+                CacheScope::Private => format!("max-age={}, private", max_age),
+            },
+        }
+    }
+}
+
+struct PolicyContext<'a> {
+    schema: &'a ComposedSchema,
+    document: &'a ExecutableDocument,
+    default_max_age: u64,
+}
+
+/// Compute the cache policy of a query operation from the `@cacheControl` hints of the composed schema.
+///
+/// Root fields and fields returning a composite type without a hint get `default_max_age`,
+/// scalar fields without a hint inherit the `maxAge` of their parent.
+/// Returns `None` for mutations and subscriptions.
+pub fn operation_cache_policy(
+    schema: &ComposedSchema,
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    default_max_age: u64,
+) -> Option<CachePolicy> {
+    let operation = find_operation(document, operation_name)?;
+    if operation.node.ty != OperationType::Query {
+        return None;
+    }
+    let root_type = schema.types.get(schema.query_type())?;
+    let ctx = PolicyContext {
+        schema,
+        document,
+        default_max_age,
+    };
+    let mut policy = CachePolicy::default();
+    selection_set_policy(&ctx, &mut policy, root_type, &operation.node.selection_set.node, None, true);
+    Some(policy)
+}
+
+fn find_operation<'a>(
+    document: &'a ExecutableDocument,
+    operation_name: Option<&str>,
+) -> Option<&'a Positioned<OperationDefinition>> {
+    match (&document.operations, operation_name) {
+        (DocumentOperations::Single(operation), _) => Some(operation),
+        (DocumentOperations::Multiple(operations), Some(operation_name)) => operations.get(operation_name),
+        (DocumentOperations::Multiple(operations), None) if operations.len() == 1 => {
+            operations.values().next()
+        }
+        (DocumentOperations::Multiple(_), None) => None,
+    }
+}
+
+fn field_hint(field_hint: Option<CacheControl>, type_hint: Option<CacheControl>) -> CacheControl {
+    let mut hint = type_hint.unwrap_or_default();
+    if let Some(field_hint) = field_hint {
+        if field_hint.max_age.is_some() || field_hint.inherit_max_age {
+            hint.max_age = field_hint.max_age;
+            hint.inherit_max_age = field_hint.inherit_max_age;
+        }
+        if field_hint.scope == Some(CacheScope::Private) || hint.scope.is_none() {
+            hint.scope = field_hint.scope;
+        }
+    }
+    hint
+}
+
+fn selection_set_policy(
+    ctx: &PolicyContext<'_>,
+    policy: &mut CachePolicy,
+    parent_type: &MetaType,
+    selection_set: &SelectionSet,
+    parent_max_age: Option<u64>,
+    is_root: bool,
+) {
+    for selection in &selection_set.items {
+        match &selection.node {
+            Selection::Field(field) => {
+                let field_definition = match parent_type.field_by_name(field.node.name.node.as_str()) {
+                    Some(field_definition) => field_definition,
+                    None => continue,
+                };
+                let field_type = ctx.schema.concrete_type_by_name(&field_definition.ty);
+                let hint = field_hint(
+                    field_definition.cache_control,
+                    field_type.and_then(|ty| ty.cache_control),
+                );
+                if let Some(scope) = hint.scope {
+                    policy.restrict_scope(scope);
+                }
+                let is_composite = field_type.map(MetaType::is_composite).unwrap_or_default();
+                let max_age = match hint.max_age {
+                    _ if hint.inherit_max_age => parent_max_age,
+                    Some(max_age) => Some(max_age),
+                    None if is_root || is_composite => Some(ctx.default_max_age),
+                    None => parent_max_age,
+                };
+                if let Some(max_age) = max_age {
+                    policy.restrict_max_age(max_age);
+                }
+                if let Some(field_type) = field_type.filter(|ty| ty.is_composite()) {
+                    selection_set_policy(ctx, policy, field_type, &field.node.selection_set.node, max_age, false);
+                }
+            }
+            Selection::FragmentSpread(fragment_spread) => {
+                if let Some(fragment) = ctx.document.fragments.get(&fragment_spread.node.fragment_name.node) {
+                    let ty = ctx
+                        .schema
+                        .types
+                        .get(&fragment.node.type_condition.node.on.node)
+                        .unwrap_or(parent_type);
+                    selection_set_policy(ctx, policy, ty, &fragment.node.selection_set.node, parent_max_age, is_root);
+                }
+            }
+            Selection::InlineFragment(inline_fragment) => {
+                let ty = inline_fragment
+                    .node
+                    .type_condition
+                    .as_ref()
+                    .and_then(|type_condition| ctx.schema.types.get(&type_condition.node.on.node))
+                    .unwrap_or(parent_type);
+                selection_set_policy(ctx, policy, ty, &inline_fragment.node.selection_set.node, parent_max_age, is_root);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cache_control_header() {
+        let policy = CachePolicy::from_header("max-age=60, private");
+        assert_eq!(policy.max_age, Some(60));
+        assert_eq!(policy.scope, CacheScope::Private);
+
+        let policy = CachePolicy::from_header("public, max-age=\"30\", s-maxage=10");
+        assert_eq!(policy.max_age, Some(10));
+        assert_eq!(policy.scope, CacheScope::Public);
+
+        assert_eq!(CachePolicy::from_header("no-store").max_age, Some(0));
+    }
+
+    #[test]
+    fn restrict_policy() {
+        let mut policy = CachePolicy::default();
+        policy.restrict(CachePolicy::from_header("max-age=60"));
+        policy.restrict(CachePolicy::from_header("max-age=120, private"));
+        assert_eq!(policy.to_header(0), "max-age=60, private");
+        policy.restrict(CachePolicy::uncacheable());
+        assert_eq!(policy.to_header(0), "no-store");
+    }
+}
@@ -0,0 +1,138 @@
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use value::ConstValue;
+
+use crate::schema::CacheScope;
+
+use super::CachePolicy;
+
+/// Value stored in a `CacheStorage`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub data: ConstValue,
+    pub created_at: DateTime<Utc>,
+    /// Time to live in seconds
+    pub max_age: u64,
+    /// Entities are always public, responses keep the scope of their cache policy.
+    pub scope: CacheScope,
+}
+
+impl CacheEntry {
+    pub fn new(data: ConstValue, max_age: u64) -> Self {
+        Self {
+            data,
+            created_at: Utc::now(),
+            max_age,
+            scope: CacheScope::Public,
+        }
+    }
+
+    /// Age of the entry in seconds
+    pub fn age(&self) -> u64 {
+        (Utc::now() - self.created_at).num_seconds().max(0) as u64
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.age() >= self.max_age
+    }
+
+    /// Value of the `Cache-Control` header of a response served from the cache, `max-age` is the remaining time to live.
+    pub fn cache_control(&self) -> String {
+        let policy = CachePolicy {
+            max_age: Some(self.max_age.saturating_sub(self.age())),
+            scope: self.scope,
+        };
+        policy.to_header(0)
+    }
+}
+
+/// Storage of the gateway caches. Implement it if you want to keep cached data outside of the gateway process, e.g. in redis.
+#[async_trait::async_trait]
+pub trait CacheStorage: Send + Sync + 'static {
+    async fn get(&self, key: &str) -> Option<CacheEntry>;
+    async fn set(&self, key: String, entry: CacheEntry);
+    async fn remove(&self, key: &str);
+}
+
+/// In-memory storage which evicts the least recently used entry when the capacity is reached.
+/// Expired entries are removed when they are read.
+pub struct InMemoryCacheStorage {
+    entries: Option<Mutex<LruCache<String, CacheEntry>>>,
+}
+
+impl InMemoryCacheStorage {
+    /// A storage with a capacity of 0 caches nothing.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    fn entries(&self) -> Option<MutexGuard<'_, LruCache<String, CacheEntry>>> {
+        self.entries
+            .as_ref()
+            .map(|entries| entries.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().map_or(0, |entries| entries.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStorage for InMemoryCacheStorage {
+    async fn get(&self, key: &str) -> Option<CacheEntry> {
+        let mut entries = self.entries()?;
+        let entry = entries.get(key)?;
+        if entry.is_expired() {
+            entries.pop(key);
+            return None;
+        }
+        Some(entry.clone())
+    }
+
+    async fn set(&self, key: String, entry: CacheEntry) {
+        if let Some(mut entries) = self.entries() {
+            entries.put(key, entry);
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        if let Some(mut entries) = self.entries() {
+            entries.pop(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let storage = InMemoryCacheStorage::new(2);
+        storage.set("a".into(), CacheEntry::new(ConstValue::Null, 60)).await;
+        storage.set("b".into(), CacheEntry::new(ConstValue::Null, 60)).await;
+        assert!(storage.get("a").await.is_some());
+        storage.set("c".into(), CacheEntry::new(ConstValue::Null, 60)).await;
+        assert!(storage.get("a").await.is_some());
+        assert!(storage.get("b").await.is_none());
+        assert!(storage.get("c").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn skip_expired() {
+        let storage = InMemoryCacheStorage::new(2);
+        storage.set("a".into(), CacheEntry::new(ConstValue::Null, 0)).await;
+        assert!(storage.get("a").await.is_none());
+        assert!(storage.is_empty());
+    }
+}
@@ -1,13 +1,12 @@
 #![allow(clippy::obfuscated_if_else)]
 
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::sync::Arc;
-use actix::dev::Stream;
-use actix_web::HttpRequest;
-use futures_util::TryFutureExt;
-use http::HeaderMap;
+use futures_util::{Stream, TryFutureExt};
+use http::{Extensions, HeaderMap};
 use once_cell::sync::Lazy;
 use crate::planner::{Response};
 
@@ -20,6 +19,9 @@ pub trait RemoteGraphQLDataSource: Sync + Send + 'static {
     fn tls(&self) -> bool { false }
     fn query_path(&self) -> Option<&str> { None }
     fn subscribe_path(&self) -> Option<&str> { None }
+    /// How long `_entities` results of this source may be kept in the entity cache, in seconds.
+    /// `@cacheControl` hints on the entity type take precedence.
+    fn entity_cache_max_age(&self) -> Option<u64> { None }
     fn url_query(&self) -> String {
         let address = self.address();
         let protocol = self.tls().then_some("https").unwrap_or("http");
@@ -52,6 +54,7 @@ pub struct DefaultSource {
     tls: bool,
     query_path: Option<String>,
     subscribe_path: Option<String>,
+    entity_cache_max_age: Option<u64>,
 }
 
 impl RemoteGraphQLDataSource for DefaultSource {
@@ -70,6 +73,9 @@ impl RemoteGraphQLDataSource for DefaultSource {
     fn subscribe_path(&self) -> Option<&str> {
         self.subscribe_path.as_deref()
     }
+    fn entity_cache_max_age(&self) -> Option<u64> {
+        self.entity_cache_max_age
+    }
 }
 
 impl<S: RemoteGraphQLDataSource> Config<S> {
@@ -96,8 +102,11 @@ type SubscriptionStream = Pin<Box<dyn Stream<Item = anyhow::Result<Response>>>>;
 #[async_trait::async_trait]
 pub trait GraphqlSourceMiddleware: Send + Sync + 'static + RemoteGraphQLDataSource {
     /// Override will_send_request to modify your gateway's requests to the subgraph before they're sent.
+    /// Headers, the query, variables, extensions, the operation name and the URL can be changed.
+    /// Subscriptions are sent to `Request::subscription_url` instead of `Request::url`, the headers and
+    /// the URL are used only when a new websocket connection to the subgraph is opened.
     #[allow(unused_variables)]
-    async fn will_send_request(&self, request: &mut HashMap<String, String>, ctx: &Context) -> anyhow::Result<()> {
+    async fn will_send_request(&self, request: &mut Request, ctx: &Context) -> anyhow::Result<()> {
         Ok(())
     }
     /// Override did_receive_response to modify your gateway's response after request to the subgraph. It will not modify response of subscription.
@@ -112,7 +121,7 @@ pub trait GraphqlSourceMiddleware: Send + Sync + 'static + RemoteGraphQLDataSour
     }
 
     async fn fetch(&self, request: Request) -> anyhow::Result<Response> {
-        let url = self.url_query();
+        let url = request.url.clone().unwrap_or_else(|| self.url_query());
         let headers = HeaderMap::try_from(&request.headers)?;
         let raw_resp = HTTP_CLIENT
             .post(&url)
@@ -121,9 +130,7 @@ pub trait GraphqlSourceMiddleware: Send + Sync + 'static + RemoteGraphQLDataSour
             .send()
             .and_then(|res| async move { res.error_for_status() })
             .await?;
-        let headers = raw_resp.headers().iter()
-            .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.as_str().to_string(), value.to_string())))
-            .collect();
+        let headers = raw_resp.headers().clone();
         let mut resp = raw_resp.json::<Response>().await?;
         if !resp.errors.is_empty() {
 
@@ -158,6 +165,10 @@ impl RemoteGraphQLDataSource for Arc<dyn GraphqlSource> {
         self.deref().subscribe_path()
     }
     #[inline]
+    fn entity_cache_max_age(&self) -> Option<u64> {
+        self.deref().entity_cache_max_age()
+    }
+    #[inline]
     fn url_query(&self) -> String {
         self.deref().url_query()
     }
@@ -169,7 +180,7 @@ impl RemoteGraphQLDataSource for Arc<dyn GraphqlSource> {
 
 #[async_trait::async_trait]
 impl GraphqlSourceMiddleware for Arc<dyn GraphqlSource> {
-    async fn will_send_request(&self, request: &mut HashMap<String, String>, ctx: &Context) -> anyhow::Result<()> {
+    async fn will_send_request(&self, request: &mut Request, ctx: &Context) -> anyhow::Result<()> {
         self.deref().will_send_request(request, ctx).await
     }
     async fn did_receive_response(&self, response: &mut Response, ctx: &Context) -> anyhow::Result<()> {
@@ -219,6 +230,10 @@ impl<S: RemoteGraphQLDataSource> RemoteGraphQLDataSource for SimpleSource<S> {
         self.source.subscribe_path()
     }
     #[inline]
+    fn entity_cache_max_age(&self) -> Option<u64> {
+        self.source.entity_cache_max_age()
+    }
+    #[inline]
     fn url_query(&self) -> String {
         self.source.url_query()
     }
@@ -256,6 +271,10 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> RemoteGraphQLDataSour
         self.source.subscribe_path()
     }
     #[inline]
+    fn entity_cache_max_age(&self) -> Option<u64> {
+        self.source.entity_cache_max_age()
+    }
+    #[inline]
     fn url_query(&self) -> String {
         self.source.url_query()
     }
@@ -269,7 +288,7 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> GraphqlSource for Sou
 
 #[async_trait::async_trait]
 impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> GraphqlSourceMiddleware for Source<S> {
-    async fn will_send_request(&self, request: &mut HashMap<String, String>, ctx: &Context) -> anyhow::Result<()> {
+    async fn will_send_request(&self, request: &mut Request, ctx: &Context) -> anyhow::Result<()> {
         self.source.will_send_request(request, ctx).await
     }
     async fn on_connection_init(&self, message: &mut Option<Value>, ctx: &Context) -> anyhow::Result<()> {
@@ -285,23 +304,53 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> GraphqlSourceMiddlewa
         self.source.subscribe(request).await
     }
 }
-/// Context give you access to request data like headers, app_data and extensions.
-pub struct Context(HttpRequest);
+/// Context give you access to request data like headers, extensions and the peer address.
+///
+/// It is owned by the gateway and independent of the HTTP framework, adapters fill it from the client request.
+/// Use extensions to pass your own data to the middleware of your sources.
+#[derive(Debug, Default)]
+pub struct Context {
+    headers: HeaderMap,
+    extensions: Extensions,
+    peer_addr: Option<SocketAddr>,
+}
 
 impl Context {
-    pub fn new(request: HttpRequest) -> Self {
-        Self(request)
+    pub fn new(headers: HeaderMap) -> Self {
+        Self {
+            headers,
+            ..Default::default()
+        }
     }
-}
 
-impl Deref for Context {
-    type Target = HttpRequest;
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    pub fn with_peer_addr(self, peer_addr: Option<SocketAddr>) -> Self {
+        Self { peer_addr, ..self }
     }
-}
 
-unsafe impl Send for Context {}
+    pub fn with_extension<T: Send + Sync + 'static>(mut self, extension: T) -> Self {
+        self.extensions.insert(extension);
+        self
+    }
+
+    /// Headers of the client request
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
 
-unsafe impl Sync for Context {}
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
 
+    /// Address of the client, if the adapter knows it
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{BTreeSet, HashSet};
+use std::time::Instant;
+
+use sha2::{Digest, Sha256};
+use value::{ConstValue, Variables};
+
+use crate::datasource::Context;
+use crate::planner::Response;
+
+use super::metrics::OperationLabels;
+
+pub const CLIENT_NAME_HEADER: &str = "apollographql-client-name";
+pub const CLIENT_VERSION_HEADER: &str = "apollographql-client-version";
+const REDACTED: &str = "[REDACTED]";
+
+/// Settings of the access log, see `GatewayServerBuilder::with_access_log`.
+///
+/// One record per query or mutation is emitted as a `tracing` event at `INFO` level with the target `apollo_gateway_rs::access_log`.
+#[derive(Debug, Default, Clone)]
+pub struct AccessLog {
+    redacted_variables: HashSet<String>,
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the value of variables, and of input object fields, with this name by `[REDACTED]` in the log.
+    pub fn redact_variable(mut self, name: impl Into<String>) -> Self {
+        self.redacted_variables.insert(name.into());
+        self
+    }
+
+    /// Variables of the operation as JSON, with the sensitive values redacted.
+    pub(crate) fn variables(&self, variables: &Variables) -> String {
+        let variables = ConstValue::Object(
+            variables
+                .iter()
+                .map(|(name, value)| (name.clone(), self.redact(name.as_str(), value)))
+                .collect(),
+        );
+        variables.into_json().map(|json| json.to_string()).unwrap_or_default()
+    }
+
+    fn redact(&self, name: &str, value: &ConstValue) -> ConstValue {
+        if self.redacted_variables.contains(name) {
+            return ConstValue::String(REDACTED.to_string());
+        }
+        match value {
+            ConstValue::Object(fields) => ConstValue::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), self.redact(name.as_str(), value)))
+                    .collect(),
+            ),
+            ConstValue::List(items) => ConstValue::List(items.iter().map(|item| self.redact(name, item)).collect()),
+            value => value.clone(),
+        }
+    }
+
+    pub(crate) fn log(&self, labels: &OperationLabels, record: &AccessRecord, started: Instant, resp: &Response) {
+        let error_codes = resp
+            .errors
+            .iter()
+            .filter_map(|err| match err.extensions.get("code") {
+                Some(ConstValue::String(code)) => Some(code.as_str()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        tracing::info!(
+            target: "apollo_gateway_rs::access_log",
+            operation_name = labels.name(),
+            operation_type = labels.ty(),
+            document_hash = record.document_hash.as_deref(),
+            client_name = record.client_name.as_deref(),
+            client_version = record.client_version.as_deref(),
+            duration_ms = started.elapsed().as_secs_f64() * 1000.0,
+            subgraphs = %record.services.iter().map(String::as_str).collect::<Vec<_>>().join(","),
+            error_count = resp.errors.len(),
+            error_codes = %error_codes.into_iter().collect::<Vec<_>>().join(","),
+            schema_version = record.schema_version.as_deref(),
+            variables = record.variables.as_deref(),
+            "operation"
+        );
+    }
+}
+
+/// What is known about an operation for its access log record, filled while it is executed.
+#[derive(Default)]
+pub struct AccessRecord {
+    pub document_hash: Option<String>,
+    pub client_name: Option<String>,
+    pub client_version: Option<String>,
+    pub services: BTreeSet<String>,
+    pub schema_version: Option<String>,
+    pub variables: Option<String>,
+}
+
+impl AccessRecord {
+    pub fn new(query: &str, ctx: &Context) -> Self {
+        let header = |name| {
+            ctx.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string)
+        };
+        Self {
+            document_hash: Some(format!("{:x}", Sha256::digest(query.as_bytes()))),
+            client_name: header(CLIENT_NAME_HEADER),
+            client_version: header(CLIENT_VERSION_HEADER),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_variables() {
+        let variables = Variables::from_json(serde_json::json!({
+            "password": "secret",
+            "input": { "email": "a@example.com", "password": "secret" },
+            "ids": [1, 2],
+        }));
+        let access_log = AccessLog::new().redact_variable("password");
+        let logged: serde_json::Value = serde_json::from_str(&access_log.variables(&variables)).expect("valid json");
+        assert_eq!(
+            logged,
+            serde_json::json!({
+                "password": "[REDACTED]",
+                "input": { "email": "a@example.com", "password": "[REDACTED]" },
+                "ids": [1, 2],
+            })
+        );
+    }
+}
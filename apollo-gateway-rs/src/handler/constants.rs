@@ -8,3 +8,6 @@ pub const KEY_RETURN_TYPE: Key = Key::from_static_str("graphgate.returnType");
 pub const KEY_FIELD_NAME: Key = Key::from_static_str("graphgate.fieldName");
 pub const KEY_VARIABLES: Key = Key::from_static_str("graphgate.variables");
 pub const KEY_ERROR: Key = Key::from_static_str("graphgate.error");
+pub const KEY_OPERATION_NAME: Key = Key::from_static_str("graphgate.operationName");
+pub const KEY_OPERATION_TYPE: Key = Key::from_static_str("graphgate.operationType");
+pub const KEY_STATUS: Key = Key::from_static_str("graphgate.status");
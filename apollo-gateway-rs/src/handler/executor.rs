@@ -3,9 +3,11 @@ use chrono::{DateTime, Duration, Utc};
 use futures_util::future::BoxFuture;
 use futures_util::stream::BoxStream;
 use futures_util::StreamExt;
-use crate::planner::{FetchNode, FlattenNode, IntrospectionNode, ParallelNode, PathSegment, PlanNode, ResponsePath, RootNode, SequenceNode, SubscribeNode};
+use http::HeaderMap;
+use crate::planner::{FetchNode, FlattenBatchNode, FlattenNode, IntrospectionNode, ParallelNode, PathSegment, PlanNode, ResponsePath, RootNode, SequenceNode, SubscribeNode};
 use crate::planner::{RequestData, Response, ServerError};
-use crate::schema::ComposedSchema;
+use crate::cache::{CachePolicy, EntityCache};
+use crate::schema::{ComposedSchema, KeyFields};
 use indexmap::IndexMap;
 use opentelemetry::trace::{FutureExt, TraceContextExt, Tracer};
 use opentelemetry::{global, Context};
@@ -15,6 +17,8 @@ use value::{ConstValue, Name, Variables};
 
 use super::constants::*;
 use super::fetcher::{Fetcher, WebSocketFetcher};
+use super::ftv1::{self, FederatedTracing};
+use super::headers::{sort_subgraph_headers, HeaderMergePolicy, SubgraphHeaders};
 use super::introspection::{IntrospectionRoot, Resolver, RootKind};
 use super::websocket::WebSocketController;
 
@@ -22,6 +26,13 @@ use super::websocket::WebSocketController;
 pub struct Executor<'e> {
     schema: &'e ComposedSchema,
     resp: Mutex<Response>,
+    header_policy: Option<&'e HeaderMergePolicy>,
+    headers: Mutex<Vec<SubgraphHeaders>>,
+    cache_policy: Mutex<CachePolicy>,
+    entity_cache: Option<&'e EntityCache>,
+    federated_tracing: Option<FederatedTracing>,
+    traces: Mutex<Vec<ConstValue>>,
+    abort_mutations_on_error: bool,
 }
 
 impl<'e> Executor<'e> {
@@ -29,19 +40,107 @@ impl<'e> Executor<'e> {
         Executor {
             schema,
             resp: Mutex::new(Response::default()),
+            header_policy: None,
+            headers: Default::default(),
+            cache_policy: Default::default(),
+            entity_cache: None,
+            federated_tracing: None,
+            traces: Default::default(),
+            abort_mutations_on_error: false,
         }
     }
 
-    /// Execute a query plan and return the results.
+    /// Merge headers of all subgraph responses into `Response::headers` with this policy.
+    pub fn header_policy(self, header_policy: &'e HeaderMergePolicy) -> Self {
+        Self {
+            header_policy: Some(header_policy),
+            ..self
+        }
+    }
+
+    /// Consult the entity cache before `_entities` fetches.
+    pub fn entity_cache(self, entity_cache: Option<&'e EntityCache>) -> Self {
+        Self {
+            entity_cache,
+            ..self
+        }
+    }
+
+    /// Turn federated traces of subgraph responses into spans and collect them if they are exposed.
+    pub fn federated_tracing(self, federated_tracing: Option<FederatedTracing>) -> Self {
+        Self {
+            federated_tracing,
+            ..self
+        }
+    }
+
+    /// Skip the remaining root fields of a mutation once one of them failed.
+    pub fn abort_mutations_on_error(self, abort_mutations_on_error: bool) -> Self {
+        Self {
+            abort_mutations_on_error,
+            ..self
+        }
+    }
+
+    async fn collect_trace(&self, service: &str, path: Option<&ResponsePath<'_>>, resp: &mut Response) {
+        let federated_tracing = match self.federated_tracing {
+            Some(federated_tracing) => federated_tracing,
+            None => return,
+        };
+        if let Some(trace) = ftv1::take_trace(resp) {
+            ftv1::add_spans(&trace);
+            if federated_tracing.expose_in_extensions {
+                self.traces.lock().await.push(ftv1::to_value(service, path.map(ToString::to_string), &trace));
+            }
+        }
+    }
+
+    async fn collect_headers(&self, service: &str, path: Option<&ResponsePath<'_>>, headers: HeaderMap) {
+        if let Some(cache_control) = headers.get("cache-control").and_then(|value| value.to_str().ok()) {
+            self.cache_policy.lock().await.restrict(CachePolicy::from_header(cache_control));
+        }
+        if self.header_policy.is_none() || headers.is_empty() {
+            return;
+        }
+        self.headers.lock().await.push(SubgraphHeaders {
+            service: service.to_string(),
+            path: path.map(ToString::to_string),
+            headers,
+        });
+    }
+
+    fn into_response(self) -> Response {
+        let mut resp = self.resp.into_inner();
+        let traces = self.traces.into_inner();
+        if !traces.is_empty() {
+            let mut trace = IndexMap::new();
+            trace.insert(Name::new("fetches"), ConstValue::List(traces));
+            resp.extensions.insert("federatedTrace".to_string(), ConstValue::Object(trace));
+        }
+        if let Some(header_policy) = self.header_policy {
+            let mut headers = self.headers.into_inner();
+            sort_subgraph_headers(&mut headers);
+            resp.headers = header_policy.merge(headers.iter().map(|h| &h.headers));
+        }
+        resp
+    }
+
+    /// Execute a query plan and return the results with the cache policy from the `Cache-Control` headers of subgraph responses.
     ///
     /// Only `Query` and `Mutation` operations are supported.
-    pub async fn execute_query(self, fetcher: &impl Fetcher, node: &RootNode<'_>, root_kind: RootKind) -> Response {
+    pub async fn execute_cacheable_query(self, fetcher: &impl Fetcher, node: &RootNode<'_>, root_kind: RootKind) -> (Response, CachePolicy) {
         match node {
             RootNode::Query(node) => {
-                self.execute_node(fetcher, node, root_kind).await;
-                self.resp.into_inner()
+                match (root_kind, node) {
+                    (RootKind::Mutation, PlanNode::Sequence(steps)) => {
+                        self.execute_mutation_steps(fetcher, steps, root_kind).await
+                    }
+                    _ => self.execute_node(fetcher, node, root_kind).await,
+                }
+                let cache_policy = *self.cache_policy.lock().await;
+                (self.into_response(), cache_policy)
             }
-            RootNode::Subscribe(_) => Response {
+            RootNode::Subscribe(_) => (Response {
                 data: None,
                 errors: vec![ServerError {
                     message: "Not supported".to_string(),
@@ -51,7 +150,7 @@ impl<'e> Executor<'e> {
                 }],
                 extensions: Default::default(),
                 headers: Default::default(),
-            },
+            }, CachePolicy::uncacheable()),
         }
     }
 
@@ -66,7 +165,7 @@ impl<'e> Executor<'e> {
         match node {
             RootNode::Query(node) => Box::pin(async_stream::stream! {
                 self.execute_node(&fetcher, node, RootKind::Query).await;
-                yield self.resp.into_inner();
+                yield self.into_response();
             }),
             RootNode::Subscribe(SubscribeNode {
                                     subscribe_nodes,
@@ -170,6 +269,7 @@ impl<'e> Executor<'e> {
                 }
                 PlanNode::Fetch(fetch) => self.execute_fetch_node(fetcher, fetch).await,
                 PlanNode::Flatten(flatten) => self.execute_flatten_node(fetcher, flatten).await,
+                PlanNode::FlattenBatch(batch) => self.execute_flatten_batch_node(fetcher, batch).await,
             }
         })
     }
@@ -180,6 +280,69 @@ impl<'e> Executor<'e> {
         }
     }
 
+    /// Execute the steps of a mutation in order, every step is the fetch of some root fields followed by its entity fetches.
+    async fn execute_mutation_steps(&self, fetcher: &impl Fetcher, steps: &SequenceNode<'_>, root_kind: RootKind) {
+        let mut failed_field: Option<String> = None;
+        for step in &steps.nodes {
+            let (fetch, entity_fetches) = match step {
+                PlanNode::Fetch(fetch) => (fetch, &[][..]),
+                PlanNode::Sequence(sequence) => match sequence.nodes.split_first() {
+                    Some((PlanNode::Fetch(fetch), entity_fetches)) => (fetch, entity_fetches),
+                    _ => {
+                        self.execute_node(fetcher, step, root_kind).await;
+                        continue;
+                    }
+                },
+                _ => {
+                    self.execute_node(fetcher, step, root_kind).await;
+                    continue;
+                }
+            };
+            let keys = fetch.query.response_keys();
+
+            if let Some(failed_field) = &failed_field {
+                let mut current_resp = self.resp.lock().await;
+                for key in keys {
+                    current_resp.errors.push(ServerError {
+                        message: format!("Mutation field '{}' was not executed because '{}' failed.", key, failed_field),
+                        path: vec![ConstValue::String(key.to_string())],
+                        locations: Default::default(),
+                        extensions: Default::default(),
+                    });
+                }
+                continue;
+            }
+
+            let errors = self.resp.lock().await.errors.len();
+            self.execute_fetch_node(fetcher, fetch).await;
+            if self.abort_mutations_on_error {
+                // Errors of the entity fetches which follow the root fields do not abort the mutation.
+                failed_field = self.failed_mutation_field(errors, &keys).await;
+            }
+            for node in entity_fetches {
+                self.execute_node(fetcher, node, root_kind).await;
+            }
+        }
+    }
+
+    /// The mutation field of `keys` which has failed, if the fetch added any error after the first `errors` or left a field without value.
+    async fn failed_mutation_field(&self, errors: usize, keys: &[&str]) -> Option<String> {
+        let current_resp = self.resp.lock().await;
+        let new_errors = &current_resp.errors[errors..];
+        let with_error = new_errors.iter().find_map(|err| match err.path.first() {
+            Some(ConstValue::String(key)) if keys.contains(&key.as_str()) => Some(key.as_str()),
+            _ => None,
+        });
+        let without_value = || {
+            keys.iter().copied().find(|key| match &current_resp.data {
+                Some(ConstValue::Object(data)) => matches!(data.get(*key), None | Some(ConstValue::Null)),
+                _ => true,
+            })
+        };
+        let without_path = || if new_errors.is_empty() { None } else { keys.first().copied() };
+        with_error.or_else(without_value).or_else(without_path).map(ToString::to_string)
+    }
+
     async fn execute_parallel_node(&self, fetcher: &impl Fetcher, parallel: &ParallelNode<'_>, root_kind: RootKind) {
         futures_util::future::join_all(
             parallel
@@ -228,30 +391,33 @@ impl<'e> Executor<'e> {
             .start(&tracer);
         let cx = Context::current_with_span(span);
         async move {
-            let res = fetcher.query(fetch.service, request).await;
+            let mut res = fetcher.query(fetch.service, request).await;
+            if let Ok(resp) = &mut res {
+                self.collect_headers(fetch.service, None, std::mem::take(&mut resp.headers)).await;
+            }
             let mut current_resp = self.resp.lock().await;
 
             match res {
                 Ok(mut resp) => {
-                    if resp.errors.is_empty() {
-                        add_tracing_spans(&mut resp);
-                        current_resp.headers = resp.headers;
-                        if current_resp.data .is_none() {
-                            current_resp.data = Some(ConstValue::Null)
-                        }
-                        if let Some(data) = &mut current_resp.data  {
-                            merge_data(data, resp.data.unwrap_or(ConstValue::Null));
-                        }
-                    } else {
-                        rewrite_errors(None, &mut current_resp.errors, resp.errors);
+                    add_tracing_spans(&mut resp);
+                    self.collect_trace(fetch.service, None, &mut resp).await;
+                    // Partial data is merged alongside the errors, null propagation happens once the plan is executed.
+                    if let Some(data) = resp.data {
+                        merge_data(current_resp.data.get_or_insert(ConstValue::Null), data);
+                    }
+                    current_resp.errors.extend(resp.errors);
+                }
+                Err(err) => {
+                    // Every root field resolved by this fetch has failed.
+                    for key in fetch.query.response_keys() {
+                        current_resp.errors.push(ServerError {
+                            message: err.to_string(),
+                            path: vec![ConstValue::String(key.to_string())],
+                            locations: Default::default(),
+                            extensions: Default::default(),
+                        });
                     }
                 }
-                Err(err) => current_resp.errors.push(ServerError {
-                    message: err.to_string(),
-                    path: Default::default(),
-                    locations: Default::default(),
-                    extensions: Default::default(),
-                }),
             }
         }
             .with_context(cx)
@@ -259,15 +425,66 @@ impl<'e> Executor<'e> {
     }
 
     async fn execute_flatten_node(&self, fetcher: &impl Fetcher, flatten: &FlattenNode<'_>) {
+        let selections = [EntitiesSelection {
+            node: flatten,
+            variable: "representations".to_string(),
+            response_key: "_entities".to_string(),
+        }];
+        self.execute_entities(fetcher, flatten.service, &selections, |representations| {
+            flatten.to_request(representations)
+        })
+        .await
+    }
+
+    async fn execute_flatten_batch_node(&self, fetcher: &impl Fetcher, batch: &FlattenBatchNode<'_>) {
+        let selections = batch
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| EntitiesSelection {
+                node,
+                variable: FlattenBatchNode::representations_variable(idx),
+                response_key: FlattenBatchNode::response_key(idx),
+            })
+            .collect::<Vec<_>>();
+        self.execute_entities(fetcher, batch.service, &selections, |representations| {
+            batch.to_request(representations)
+        })
+        .await
+    }
+
+    /// Fetch the entities of the selections with a single request and merge them into the response.
+    async fn execute_entities(
+        &self,
+        fetcher: &impl Fetcher,
+        service: &str,
+        selections: &[EntitiesSelection<'_, '_>],
+        to_request: impl FnOnce(Variables) -> RequestData,
+    ) {
         enum Representation {
-            Keys(ConstValue),
+            /// Key fields of the entity and its path in the response.
+            Keys(ConstValue, Vec<ConstValue>),
             Skip,
         }
 
+        /// Whether every key field has a value, the fields of nested keys included.
+        fn has_keys(value: &ConstValue, keys: &KeyFields) -> bool {
+            match value {
+                ConstValue::Object(object) => keys.iter().all(|(name, children)| match object.get(name) {
+                    None | Some(ConstValue::Null) => false,
+                    Some(value) => children.is_empty() || has_keys(value, children),
+                }),
+                ConstValue::List(values) => values.iter().all(|value| has_keys(value, keys)),
+                _ => false,
+            }
+        }
+
         fn extract_keys(
             from: &mut IndexMap<Name, ConstValue>,
             prefix: usize,
+            keys: &KeyFields,
             possible_type: Option<&str>,
+            response_path: &[ConstValue],
         ) -> Representation {
             let prefix = format!("__key{}_", prefix);
             if let Some(possible_type) = possible_type {
@@ -278,19 +495,23 @@ impl<'e> Executor<'e> {
             }
 
             let mut res = IndexMap::new();
-            let mut keys = Vec::new();
+            let mut prefixed = Vec::new();
             for key in from.keys() {
                 if key.as_str().starts_with(&prefix) {
-                    keys.push(key.clone());
+                    prefixed.push(key.clone());
                 }
             }
-            for key in keys {
+            for key in prefixed {
                 if let Some(value) = from.shift_remove(&key) {
                     let name = Name::new(&key[prefix.len()..]);
                     res.insert(name, value);
                 }
             }
-            Representation::Keys(ConstValue::Object(res))
+            let representation = ConstValue::Object(res);
+            if !has_keys(&representation, keys) {
+                return Representation::Skip;
+            }
+            Representation::Keys(representation, response_path.to_vec())
         }
 
         fn get_representations(
@@ -298,6 +519,8 @@ impl<'e> Executor<'e> {
             value: &mut ConstValue,
             path: &[PathSegment<'_>],
             prefix: usize,
+            keys: &KeyFields,
+            response_path: &mut Vec<ConstValue>,
         ) {
             let segment = match path.get(0) {
                 Some(segment) => segment,
@@ -309,28 +532,38 @@ impl<'e> Executor<'e> {
                 match value {
                     ConstValue::Object(object) if !segment.is_list => {
                         if let Some(ConstValue::Object(key_object)) = object.get_mut(segment.name) {
+                            response_path.push(ConstValue::String(segment.name.to_string()));
                             representations.push(extract_keys(
                                 key_object,
                                 prefix,
+                                keys,
                                 segment.possible_type,
+                                response_path,
                             ));
+                            response_path.pop();
                         } else {
                             representations.push(Representation::Skip);
                         }
                     }
                     ConstValue::Object(object) if segment.is_list => {
                         if let Some(ConstValue::List(array)) = object.get_mut(segment.name) {
-                            for element in array {
+                            response_path.push(ConstValue::String(segment.name.to_string()));
+                            for (idx, element) in array.iter_mut().enumerate() {
                                 if let ConstValue::Object(element_obj) = element {
+                                    response_path.push(ConstValue::Number(idx.into()));
                                     representations.push(extract_keys(
                                         element_obj,
                                         prefix,
+                                        keys,
                                         segment.possible_type,
+                                        response_path,
                                     ));
+                                    response_path.pop();
                                 } else {
                                     representations.push(Representation::Skip);
                                 }
                             }
+                            response_path.pop();
                         }
                     }
                     _ => {}
@@ -339,16 +572,22 @@ impl<'e> Executor<'e> {
                 match value {
                     ConstValue::Object(object) if !segment.is_list => {
                         if let Some(next_value) = object.get_mut(segment.name) {
-                            get_representations(representations, next_value, &path[1..], prefix);
+                            response_path.push(ConstValue::String(segment.name.to_string()));
+                            get_representations(representations, next_value, &path[1..], prefix, keys, response_path);
+                            response_path.pop();
                         } else {
                             representations.push(Representation::Skip);
                         }
                     }
                     ConstValue::Object(object) if segment.is_list => {
                         if let Some(ConstValue::List(ref mut array)) = object.get_mut(segment.name) {
-                            for element in array.iter_mut() {
-                                get_representations(representations, element, &path[1..], prefix);
+                            response_path.push(ConstValue::String(segment.name.to_string()));
+                            for (idx, element) in array.iter_mut().enumerate() {
+                                response_path.push(ConstValue::Number(idx.into()));
+                                get_representations(representations, element, &path[1..], prefix, keys, response_path);
+                                response_path.pop();
                             }
+                            response_path.pop();
                         } else {
                             representations.push(Representation::Skip);
                         }
@@ -412,37 +651,110 @@ impl<'e> Executor<'e> {
             }
         }
 
-        let (representations, flags) = {
-            let mut representations = Vec::new();
-            let mut resp = self.resp.lock().await;
-            get_representations(
-                &mut representations,
-                resp.data.as_mut().unwrap_or(&mut ConstValue::Null),
-                &flatten.path,
-                flatten.prefix,
-            );
-            if representations.is_empty() {
-                return;
-            }
+        struct Pending<'s> {
+            values: Vec<ConstValue>,
+            paths: Vec<Vec<ConstValue>>,
+            flags: Vec<bool>,
+            entity_cache: Option<(&'s EntityCache, &'s str, u64)>,
+            entities: Vec<Option<ConstValue>>,
+            cache_keys: Vec<String>,
+            missing: Vec<usize>,
+        }
+
+        let mut pending = Vec::with_capacity(selections.len());
+        for selection in selections {
+            let flatten = selection.node;
+            let (values, paths, flags) = {
+                let mut representations = Vec::new();
+                let mut resp = self.resp.lock().await;
+                get_representations(
+                    &mut representations,
+                    resp.data.as_mut().unwrap_or(&mut ConstValue::Null),
+                    &flatten.path,
+                    flatten.prefix,
+                    flatten.keys,
+                    &mut Vec::new(),
+                );
 
-            let mut flags = Vec::with_capacity(representations.len());
-            let mut values = Vec::with_capacity(representations.len());
+                let mut flags = Vec::with_capacity(representations.len());
+                let mut values = Vec::with_capacity(representations.len());
+                let mut paths = Vec::with_capacity(representations.len());
+
+                for representation in representations {
+                    match representation {
+                        Representation::Keys(value, path) => {
+                            values.push(value);
+                            paths.push(path);
+                            flags.push(true);
+                        }
+                        Representation::Skip => flags.push(false),
+                    }
+                }
+                (values, paths, flags)
+            };
 
-            for representation in representations {
-                match representation {
-                    Representation::Keys(value) => {
-                        values.push(value);
-                        flags.push(true);
+            let entity_cache = flatten.query.entity_type.and_then(|entity_type| {
+                let cache = self.entity_cache?;
+                cache
+                    .max_age(self.schema, service, entity_type)
+                    .map(|max_age| (cache, entity_type, max_age))
+            });
+            let mut entities: Vec<Option<ConstValue>> = Vec::with_capacity(values.len());
+            let mut cache_keys = Vec::new();
+            match entity_cache {
+                Some((cache, entity_type, _)) => {
+                    // Field arguments of the entity query are passed as variables, so they are part of the key.
+                    let cache_query = format!(
+                        "{}\n{}",
+                        flatten.query,
+                        serde_json::to_string(&flatten.variables.to_variables()).unwrap_or_default()
+                    );
+                    for value in &values {
+                        let key = cache.key(service, entity_type, &cache_query, value);
+                        entities.push(cache.get(&key).await);
+                        cache_keys.push(key);
                     }
-                    Representation::Skip => flags.push(false),
                 }
+                None => entities.resize(values.len(), None),
             }
+            let missing = entities
+                .iter()
+                .enumerate()
+                .filter(|(_, entity)| entity.is_none())
+                .map(|(idx, _)| idx)
+                .collect::<Vec<_>>();
+            pending.push(Pending {
+                values,
+                paths,
+                flags,
+                entity_cache,
+                entities,
+                cache_keys,
+                missing,
+            });
+        }
 
-            let mut variables = Variables::default();
-            variables.insert(Name::new("representations"), ConstValue::List(values));
-            (variables, flags)
-        };
-        let request = flatten.to_request(representations);
+        if pending.iter().all(|pending| pending.missing.is_empty()) {
+            let current_resp = &mut self.resp.lock().await;
+            for (selection, pending) in selections.iter().zip(pending) {
+                flatten_values(
+                    current_resp.data.as_mut().unwrap_or(&mut ConstValue::Null),
+                    &selection.node.path,
+                    &mut pending.entities.into_iter().flatten(),
+                    &mut pending.flags.into_iter().fuse(),
+                );
+            }
+            return;
+        }
+
+        let mut representations = Variables::default();
+        for (selection, pending) in selections.iter().zip(&pending) {
+            representations.insert(
+                Name::new(&selection.variable),
+                ConstValue::List(pending.missing.iter().map(|idx| pending.values[*idx].clone()).collect()),
+            );
+        }
+        let request = to_request(representations);
 
         let tracer = global::tracer("graphql");
         let variables = match serde_json::to_string(&request.variables) {
@@ -459,53 +771,110 @@ impl<'e> Executor<'e> {
             }
         };
         let span = tracer
-            .span_builder(format!("flatten [{}]", flatten.service))
+            .span_builder(format!("flatten [{}]", service))
             .with_attributes(vec![
-                KEY_SERVICE.string(flatten.service.to_string()),
-                KEY_QUERY.string(flatten.query.to_string()),
+                KEY_SERVICE.string(service.to_string()),
+                KEY_QUERY.string(request.query.clone()),
                 KEY_VARIABLES.string(variables),
-                KEY_PATH.string(flatten.path.to_string()),
+                KEY_PATH.string(
+                    selections
+                        .iter()
+                        .map(|selection| selection.node.path.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
             ])
             .start(&tracer);
         let cx = Context::current_with_span(span);
+        // Headers and traces of a merged request can't be attributed to one path.
+        let response_path = match selections {
+            [selection] => Some(&selection.node.path),
+            _ => None,
+        };
 
         async move {
-            let res = fetcher.query(flatten.service,  request).await;
-            let current_resp = &mut self.resp.lock().await;
+            let mut res = fetcher.query(service, request).await;
+            if let Ok(resp) = &mut res {
+                self.collect_headers(service, response_path, std::mem::take(&mut resp.headers)).await;
+            }
 
+            let mut subgraph_errors = Vec::new();
+            let mut fetch_error = None;
             match res {
                 Ok(mut resp) => {
-                    if resp.errors.is_empty() {
-                        add_tracing_spans(&mut resp);
-                        if let ConstValue::Object(mut data) = resp.data.unwrap_or_default() {
-                            if let Some(ConstValue::List(values)) = data.shift_remove("_entities") {
-                                flatten_values(
-                                    current_resp.data.as_mut().unwrap_or(&mut ConstValue::Null),
-                                    &flatten.path,
-                                    &mut values.into_iter().fuse(),
-                                    &mut flags.into_iter().fuse(),
-                                );
+                    add_tracing_spans(&mut resp);
+                    self.collect_trace(service, response_path, &mut resp).await;
+                    let cacheable = resp.errors.is_empty();
+                    // Partial data is merged alongside the errors, null propagation happens once the plan is executed.
+                    if let Some(ConstValue::Object(mut data)) = resp.data {
+                        for (selection, pending) in selections.iter().zip(&mut pending) {
+                            if let Some(ConstValue::List(fetched)) = data.shift_remove(selection.response_key.as_str()) {
+                                for (idx, entity) in pending.missing.iter().copied().zip(fetched) {
+                                    if let Some(((cache, entity_type, max_age), key)) =
+                                        pending.entity_cache.zip(pending.cache_keys.get(idx))
+                                    {
+                                        if cacheable && entity != ConstValue::Null {
+                                            cache.set(key.clone(), entity_type, pending.values[idx].clone(), entity.clone(), max_age).await;
+                                        }
+                                    }
+                                    pending.entities[idx] = Some(entity);
+                                }
                             }
                         }
-                    } else {
-                        rewrite_errors(Some(&flatten.path), &mut current_resp.errors, resp.errors);
                     }
+                    subgraph_errors = resp.errors;
                 }
-                Err(err) => {
-                    current_resp.errors.push(ServerError {
-                        message: err.to_string(),
-                        path: Default::default(),
-                        locations: Default::default(),
-                        extensions: Default::default(),
-                    });
+                Err(err) => fetch_error = Some(err.to_string()),
+            }
+
+            let current_resp = &mut self.resp.lock().await;
+            {
+                let fetched_paths = selections
+                    .iter()
+                    .zip(&pending)
+                    .map(|(selection, pending)| {
+                        (
+                            selection.response_key.as_str(),
+                            pending.missing.iter().map(|idx| pending.paths[*idx].as_slice()).collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                rewrite_entity_errors(&mut current_resp.errors, subgraph_errors, &fetched_paths);
+                if let Some(message) = fetch_error {
+                    // Every entity requested by this fetch has failed.
+                    for path in fetched_paths.iter().flat_map(|(_, paths)| paths) {
+                        current_resp.errors.push(ServerError {
+                            message: message.clone(),
+                            path: path.to_vec(),
+                            locations: Default::default(),
+                            extensions: Default::default(),
+                        });
+                    }
                 }
             }
+            for (selection, pending) in selections.iter().zip(pending) {
+                flatten_values(
+                    current_resp.data.as_mut().unwrap_or(&mut ConstValue::Null),
+                    &selection.node.path,
+                    &mut pending.entities.into_iter().map(Option::unwrap_or_default),
+                    &mut pending.flags.into_iter().fuse(),
+                );
+            }
         }
             .with_context(cx)
             .await
     }
 }
 
+/// An `_entities` selection of a request, a flatten node selects it once and a batch once per merged node.
+struct EntitiesSelection<'a, 'b> {
+    node: &'b FlattenNode<'a>,
+    /// Name of the variable with the representations.
+    variable: String,
+    /// Response key of the selected `_entities`.
+    response_key: String,
+}
+
 fn merge_data(target: &mut ConstValue, value: ConstValue) {
     match (target, value) {
         (target @ ConstValue::Null, fragment) => *target = fragment,
@@ -530,39 +899,28 @@ fn merge_data(target: &mut ConstValue, value: ConstValue) {
     }
 }
 
-fn rewrite_errors(
-    prefix_path: Option<&ResponsePath<'_>>,
+/// Rewrite paths of errors returned by an `_entities` fetch,
+/// `[response_key, idx, ...]` is replaced with the path of the requested entity in the response.
+fn rewrite_entity_errors(
     target: &mut Vec<ServerError>,
     errors: Vec<ServerError>,
+    entity_paths: &[(&str, Vec<&[ConstValue]>)],
 ) {
     for mut err in errors {
-        let mut path = Vec::new();
-
-        if let Some(prefix_path) = prefix_path {
-            for segment in prefix_path.iter() {
-                path.push(ConstValue::String(segment.name.to_string()));
-                if segment.is_list {
-                    path.push(ConstValue::Number(0.into()));
-                }
-            }
-        }
-
-        if matches!(err.path.first(), Some(ConstValue::String(s)) if s=="_entities") {
-            path.extend(err.path.drain(1..));
-        }
-
-        for subpath in err.path.iter() {
-            if let ConstValue::String(x) = subpath {
-                path.push(ConstValue::String(x.to_string()))
-            }
-        }
-
-        target.push(ServerError {
-            message: err.message,
-            path,
-            locations: err.locations,
-            extensions: err.extensions,
-        })
+        let entity_path = match err.path.as_slice() {
+            [ConstValue::String(name), ConstValue::Number(idx), ..] => entity_paths
+                .iter()
+                .find(|(response_key, _)| *response_key == name.as_str())
+                .zip(idx.as_u64())
+                .and_then(|((_, paths), idx)| paths.get(idx as usize))
+                .copied(),
+            _ => None,
+        };
+        err.path = match entity_path {
+            Some(entity_path) => entity_path.iter().cloned().chain(err.path.drain(2..)).collect(),
+            None => Vec::new(),
+        };
+        target.push(err);
     }
 }
 
@@ -699,3 +1057,281 @@ fn add_tracing_spans(response: &mut Response) {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::{json, Value};
+
+    use crate::cache::EntityCache;
+    use crate::planner::PlanBuilder;
+    use crate::testing::MockSource;
+    use crate::GatewayServer;
+    use super::*;
+
+    const ACCOUNTS: &str = r#"
+        type Query {
+            me: User
+            users: [User]
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    const REVIEWS: &str = r#"
+        type Review {
+            body: String
+        }
+
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            reviews: [Review]
+        }
+    "#;
+
+    fn user(id: &str) -> Value {
+        json!({ "__key1___typename": "User", "__key1_id": id })
+    }
+
+    /// Resolves the users of the accounts service and records the users the reviews service resolves.
+    #[derive(Default)]
+    struct StubFetcher {
+        resolved: Mutex<Vec<String>>,
+        entity_requests: Mutex<usize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Fetcher for StubFetcher {
+        async fn query(&self, service: &str, request: RequestData) -> anyhow::Result<Response> {
+            let data = match service {
+                "accounts" if request.query.contains("users") => json!({ "users": [user("1"), user("2")] }),
+                "accounts" => json!({ "me": user("1") }),
+                _ => {
+                    *self.entity_requests.lock().unwrap() += 1;
+                    let representations = request
+                        .variables
+                        .get("representations")
+                        .cloned()
+                        .and_then(|representations| representations.into_json().ok())
+                        .unwrap_or_default();
+                    let entities = representations
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|representation| {
+                            let id = representation["id"].as_str().unwrap_or_default().to_string();
+                            self.resolved.lock().unwrap().push(id.clone());
+                            json!({ "reviews": [{ "body": format!("Review of {}", id) }] })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({ "_entities": entities })
+                }
+            };
+            Ok(Response {
+                data: Some(ConstValue::from_json(data)?),
+                ..Default::default()
+            })
+        }
+    }
+
+    async fn execute(schema: &ComposedSchema, cache: &EntityCache, fetcher: &StubFetcher, query: &str) -> Option<Value> {
+        let document = parser::parse_query(query).unwrap();
+        let builder = PlanBuilder::new(schema, document);
+        builder.validate().expect("valid operation");
+        let plan = builder.plan_validated().expect("valid plan");
+        let (resp, _) = Executor::new(schema)
+            .entity_cache(Some(cache))
+            .execute_cacheable_query(fetcher, &plan, RootKind::Query)
+            .await;
+        data(resp)
+    }
+
+    fn data(resp: Response) -> Option<Value> {
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        resp.data.and_then(|data| data.into_json().ok())
+    }
+
+    #[tokio::test]
+    async fn fetch_only_entities_missing_in_cache() {
+        let schema = ComposedSchema::combine([
+            ("accounts".to_string(), parser::parse_schema(ACCOUNTS).unwrap()),
+            ("reviews".to_string(), parser::parse_schema(REVIEWS).unwrap()),
+        ])
+        .unwrap();
+        let cache = EntityCache::in_memory(16).default_max_age(60);
+        let fetcher = StubFetcher::default();
+
+        let data = execute(&schema, &cache, &fetcher, "{ me { reviews { body } } }").await;
+        assert_eq!(data, Some(json!({ "me": { "reviews": [{ "body": "Review of 1" }] } })));
+
+        let data = execute(&schema, &cache, &fetcher, "{ users { reviews { body } } }").await;
+        assert_eq!(
+            data,
+            Some(json!({
+                "users": [
+                    { "reviews": [{ "body": "Review of 1" }] },
+                    { "reviews": [{ "body": "Review of 2" }] },
+                ]
+            }))
+        );
+        assert_eq!(*fetcher.resolved.lock().unwrap(), vec!["1".to_string(), "2".to_string()]);
+        assert_eq!(*fetcher.entity_requests.lock().unwrap(), 2);
+
+        assert!(execute(&schema, &cache, &fetcher, "{ users { reviews { body } } }").await.is_some());
+        assert_eq!(*fetcher.entity_requests.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn resolve_entities_by_nested_keys() {
+        const MEMBERS: &str = r#"
+            type Query {
+                members: [Member]
+            }
+
+            type Organization {
+                id: ID!
+            }
+
+            type Member @key(fields: "id organization { id }") {
+                id: ID!
+                organization: Organization
+            }
+        "#;
+        const BADGES: &str = r#"
+            extend type Organization {
+                id: ID! @external
+            }
+
+            extend type Member @key(fields: "id organization { id }") {
+                id: ID! @external
+                organization: Organization @external
+                badge: String
+            }
+        "#;
+
+        let representations = Arc::new(Mutex::new(Vec::new()));
+        let members = MockSource::new("members", MEMBERS).query_value(
+            "members",
+            json!([
+                { "id": "1", "organization": { "id": "acme" } },
+                { "id": "2", "organization": null },
+            ]),
+        );
+        let badges = MockSource::new("badges", BADGES).entity("Member", {
+            let representations = representations.clone();
+            move |representation| {
+                representations.lock().unwrap().push(representation.clone());
+                Ok(json!({ "badge": "gold" }))
+            }
+        });
+        let server = GatewayServer::builder()
+            .with_middleware_source(members)
+            .with_middleware_source(badges.clone())
+            .build();
+        server.update_schema().await.expect("composed schema");
+
+        let resp = server.execute(RequestData::new("{ members { badge } }"), Default::default()).await;
+        assert_eq!(data(resp), Some(json!({ "members": [{ "badge": "gold" }, { "badge": null }] })));
+        // The member without an organization has an incomplete key and is not sent to `badges`.
+        assert_eq!(
+            *representations.lock().unwrap(),
+            vec![json!({ "__typename": "Member", "id": "1", "organization": { "id": "acme" } })]
+        );
+        assert_eq!(badges.requests().len(), 1);
+    }
+
+    const ACCOUNTS_MUTATIONS: &str = r#"
+        type Query {
+            me: ID
+        }
+
+        type Mutation {
+            createUser(name: String): ID
+            renameUser(name: String): ID
+        }
+    "#;
+
+    const REVIEWS_MUTATIONS: &str = r#"
+        type Query {
+            review: String
+        }
+
+        type Mutation {
+            addReview(body: String): String
+        }
+    "#;
+
+    /// Resolves the mutations of the accounts service and answers the reviews service with a fixed response.
+    struct MutationFetcher {
+        reviews: fn() -> Response,
+        accounts_requests: Mutex<usize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Fetcher for MutationFetcher {
+        async fn query(&self, service: &str, request: RequestData) -> anyhow::Result<Response> {
+            if service == "reviews" {
+                return Ok((self.reviews)());
+            }
+            *self.accounts_requests.lock().unwrap() += 1;
+            let data = match request.query.contains("createUser") {
+                true => json!({ "a": "1" }),
+                false => json!({ "c": "1" }),
+            };
+            Ok(Response {
+                data: Some(ConstValue::from_json(data)?),
+                ..Default::default()
+            })
+        }
+    }
+
+    async fn execute_mutation(reviews: fn() -> Response) -> (Response, usize) {
+        let schema = ComposedSchema::combine([
+            ("accounts".to_string(), parser::parse_schema(ACCOUNTS_MUTATIONS).unwrap()),
+            ("reviews".to_string(), parser::parse_schema(REVIEWS_MUTATIONS).unwrap()),
+        ])
+        .unwrap();
+        let document = parser::parse_query(r#"mutation { a: createUser(name: "a") b: addReview(body: "b") c: renameUser(name: "c") }"#).unwrap();
+        let builder = PlanBuilder::new(&schema, document);
+        builder.validate().expect("valid operation");
+        let plan = builder.plan_validated().expect("valid plan");
+        let fetcher = MutationFetcher {
+            reviews,
+            accounts_requests: Default::default(),
+        };
+        let (resp, _) = Executor::new(&schema)
+            .abort_mutations_on_error(true)
+            .execute_cacheable_query(&fetcher, &plan, RootKind::Mutation)
+            .await;
+        let accounts_requests = *fetcher.accounts_requests.lock().unwrap();
+        (resp, accounts_requests)
+    }
+
+    #[tokio::test]
+    async fn abort_mutations_after_an_error_without_path() {
+        let (resp, accounts_requests) = execute_mutation(|| Response::error("Service unavailable.")).await;
+        let messages = resp.errors.iter().map(|err| err.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            ["Service unavailable.", "Mutation field 'c' was not executed because 'b' failed."]
+        );
+        assert_eq!(accounts_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn abort_mutations_after_a_null_field() {
+        let (resp, accounts_requests) = execute_mutation(|| Response {
+            data: Some(ConstValue::from_json(json!({ "b": null })).unwrap()),
+            ..Default::default()
+        })
+        .await;
+        let messages = resp.errors.iter().map(|err| err.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, ["Mutation field 'c' was not executed because 'b' failed."]);
+        assert_eq!(resp.data.and_then(|data| data.into_json().ok()), Some(json!({ "a": "1", "b": null })));
+        assert_eq!(accounts_requests, 1);
+    }
+}
@@ -1,9 +1,16 @@
+use std::collections::BTreeSet;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
 use anyhow::Result;
-use crate::planner::{RequestData, Response};
+use crate::planner::{Request, RequestData, Response};
 use tokio::sync::mpsc;
 use crate::datasource::{Context, RemoteGraphQLDataSource};
+use crate::handler::ftv1;
+use crate::handler::metrics::Metrics;
+use crate::handler::propagation;
 use crate::handler::websocket::WebSocketController;
+use crate::plugin::Plugins;
 use crate::{GraphqlSourceMiddleware, ServiceRouteTable};
 
 #[async_trait::async_trait]
@@ -13,24 +20,70 @@ pub trait Fetcher: Send + Sync {
 
 pub struct HttpFetcher<'a, S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> {
     router_table: &'a ServiceRouteTable<S>,
-    pub ctx: Context
+    pub ctx: Arc<Context>,
+    plugins: Option<&'a Plugins>,
+    metrics: Option<&'a Metrics>,
+    include_trace: bool,
+    services: Mutex<BTreeSet<String>>,
 }
 
 impl<'a, S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> HttpFetcher<'a, S> {
-    pub fn new(router_table: &'a ServiceRouteTable<S>, ctx: Context) -> Self {
+    pub fn new(router_table: &'a ServiceRouteTable<S>, ctx: Arc<Context>) -> Self {
         Self {
             router_table,
-            ctx
+            ctx,
+            plugins: None,
+            metrics: None,
+            include_trace: false,
+            services: Default::default(),
         }
     }
+
+    pub fn plugins(self, plugins: &'a Plugins) -> Self {
+        Self { plugins: Some(plugins), ..self }
+    }
+
+    pub fn metrics(self, metrics: &'a Metrics) -> Self {
+        Self { metrics: Some(metrics), ..self }
+    }
+
+    /// Ask subgraphs for federated traces.
+    pub fn include_trace(self, include_trace: bool) -> Self {
+        Self { include_trace, ..self }
+    }
+
+    /// Names of the services which have been queried.
+    pub fn into_services(self) -> BTreeSet<String> {
+        self.services.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 #[async_trait::async_trait]
 impl<'a, S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> Fetcher for HttpFetcher<'a, S> {
-    async fn query(&self, service: &str, request: RequestData) -> Result<Response> {
-        self.router_table
-            .query(service, request, &self.ctx)
-            .await
+    async fn query(&self, service: &str, mut request: RequestData) -> Result<Response> {
+        if let Some(plugins) = self.plugins {
+            if let Err(resp) = plugins.will_fetch(service, &mut request, &self.ctx).await {
+                return Ok(resp);
+            }
+        }
+        let mut request = Request::new(request);
+        propagation::inject(&opentelemetry::Context::current(), &mut request.headers);
+        if self.include_trace {
+            request.headers.insert(ftv1::INCLUDE_TRACE_HEADER.to_string(), ftv1::INCLUDE_TRACE_VALUE.to_string());
+        }
+        self.services
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(service.to_string());
+        let started = Instant::now();
+        let mut resp = self.router_table.query(service, request, &self.ctx).await;
+        if let Some(metrics) = self.metrics {
+            metrics.record_fetch(service, started, &resp);
+        }
+        if let Some(plugins) = self.plugins {
+            plugins.did_fetch(service, &mut resp, &self.ctx).await;
+        }
+        resp
     }
 }
 
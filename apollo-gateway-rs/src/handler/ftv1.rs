@@ -0,0 +1,248 @@
+//! Federated tracing (ftv1) of Apollo compatible subgraphs.
+//!
+//! Subgraphs return a base64 encoded `Trace` protobuf message of Apollo's `reports.proto` in `extensions.ftv1`
+//! when the request has the `apollo-federation-include-trace: ftv1` header, only the fields used by the gateway are declared here.
+use base64::Engine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use indexmap::IndexMap;
+use opentelemetry::trace::{TraceContextExt, Tracer};
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::Context;
+use prost::Message;
+use value::{ConstValue, Name};
+
+use crate::planner::Response;
+
+use super::constants::*;
+
+pub const INCLUDE_TRACE_HEADER: &str = "apollo-federation-include-trace";
+pub const INCLUDE_TRACE_VALUE: &str = "ftv1";
+
+/// Settings of federated tracing, see `GatewayServerBuilder::with_federated_tracing`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FederatedTracing {
+    /// Return the traces of all fetches of an operation in `extensions.federatedTrace` of the response.
+    pub expose_in_extensions: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Trace {
+    #[prost(message, optional, tag = "4")]
+    pub start_time: Option<Timestamp>,
+    #[prost(message, optional, tag = "3")]
+    pub end_time: Option<Timestamp>,
+    #[prost(uint64, tag = "11")]
+    pub duration_ns: u64,
+    #[prost(message, optional, tag = "14")]
+    pub root: Option<Node>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Timestamp {
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+    #[prost(int32, tag = "2")]
+    pub nanos: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Node {
+    #[prost(string, optional, tag = "1")]
+    pub response_name: Option<String>,
+    #[prost(uint32, optional, tag = "2")]
+    pub index: Option<u32>,
+    #[prost(string, tag = "3")]
+    pub r#type: String,
+    #[prost(string, tag = "13")]
+    pub parent_type: String,
+    #[prost(string, tag = "14")]
+    pub original_field_name: String,
+    #[prost(uint64, tag = "8")]
+    pub start_time: u64,
+    #[prost(uint64, tag = "9")]
+    pub end_time: u64,
+    #[prost(message, repeated, tag = "11")]
+    pub error: Vec<TraceError>,
+    #[prost(message, repeated, tag = "12")]
+    pub child: Vec<Node>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TraceError {
+    #[prost(string, tag = "1")]
+    pub message: String,
+    #[prost(uint64, tag = "3")]
+    pub time_ns: u64,
+}
+
+/// Remove the encoded trace from the extensions of a subgraph response.
+pub fn take_trace(response: &mut Response) -> Option<Trace> {
+    let encoded = match response.extensions.remove("ftv1")? {
+        ConstValue::String(encoded) => encoded,
+        _ => return None,
+    };
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+    Trace::decode(bytes.as_slice()).ok()
+}
+
+/// Create a span for every resolved field of the trace, children of the current span.
+pub fn add_spans(trace: &Trace) {
+    let (start_time, root) = match (trace.start_time.as_ref().and_then(to_datetime), &trace.root) {
+        (Some(start_time), Some(root)) => (start_time, root),
+        _ => return,
+    };
+    let tracer = global::tracer("graphql");
+    let cx = Context::current();
+    let mut path = Vec::new();
+    for child in &root.child {
+        add_node_spans(&tracer, &cx, start_time, child, &mut path);
+    }
+}
+
+fn add_node_spans(tracer: &BoxedTracer, parent: &Context, start_time: DateTime<Utc>, node: &Node, path: &mut Vec<String>) {
+    path.push(segment(node));
+    let cx = match &node.response_name {
+        Some(_) => {
+            let mut attributes = vec![
+                KEY_PARENT_TYPE.string(node.parent_type.clone()),
+                KEY_RETURN_TYPE.string(node.r#type.clone()),
+                KEY_FIELD_NAME.string(field_name(node).to_string()),
+            ];
+            if let Some(error) = node.error.first() {
+                attributes.push(KEY_ERROR.string(error.message.clone()));
+            }
+            let span = tracer
+                .span_builder(path.join("."))
+                .with_start_time(start_time + Duration::nanoseconds(node.start_time as i64))
+                .with_end_time(start_time + Duration::nanoseconds(node.end_time as i64))
+                .with_attributes(attributes)
+                .start_with_context(tracer, parent);
+            parent.with_span(span)
+        }
+        // List items have no span of their own, the fields of the item are children of the list field.
+        None => parent.clone(),
+    };
+    for child in &node.child {
+        add_node_spans(tracer, &cx, start_time, child, path);
+    }
+    path.pop();
+}
+
+/// Trace tree of a fetch which can be returned to the client for debugging.
+pub fn to_value(service: &str, path: Option<String>, trace: &Trace) -> ConstValue {
+    let mut fetch = IndexMap::new();
+    fetch.insert(Name::new("service"), ConstValue::String(service.to_string()));
+    if let Some(path) = path {
+        fetch.insert(Name::new("path"), ConstValue::String(path));
+    }
+    if let Some(start_time) = trace.start_time.as_ref().and_then(to_datetime) {
+        fetch.insert(Name::new("startTime"), ConstValue::String(start_time.to_rfc3339()));
+    }
+    fetch.insert(Name::new("durationNs"), ConstValue::Number(trace.duration_ns.into()));
+    if let Some(root) = &trace.root {
+        fetch.insert(Name::new("children"), children_to_value(root));
+    }
+    ConstValue::Object(fetch)
+}
+
+fn children_to_value(node: &Node) -> ConstValue {
+    ConstValue::List(node.child.iter().map(node_to_value).collect())
+}
+
+fn node_to_value(node: &Node) -> ConstValue {
+    let mut value = IndexMap::new();
+    match (&node.response_name, node.index) {
+        (Some(response_name), _) => {
+            value.insert(Name::new("responseName"), ConstValue::String(response_name.clone()));
+            value.insert(Name::new("fieldName"), ConstValue::String(field_name(node).to_string()));
+            value.insert(Name::new("type"), ConstValue::String(node.r#type.clone()));
+            value.insert(Name::new("parentType"), ConstValue::String(node.parent_type.clone()));
+            value.insert(Name::new("startOffset"), ConstValue::Number(node.start_time.into()));
+            value.insert(Name::new("duration"), ConstValue::Number(node.end_time.saturating_sub(node.start_time).into()));
+        }
+        (None, Some(index)) => {
+            value.insert(Name::new("index"), ConstValue::Number(index.into()));
+        }
+        (None, None) => {}
+    }
+    if !node.error.is_empty() {
+        value.insert(
+            Name::new("errors"),
+            ConstValue::List(node.error.iter().map(|error| ConstValue::String(error.message.clone())).collect()),
+        );
+    }
+    if !node.child.is_empty() {
+        value.insert(Name::new("children"), children_to_value(node));
+    }
+    ConstValue::Object(value)
+}
+
+fn segment(node: &Node) -> String {
+    match (&node.response_name, node.index) {
+        (Some(response_name), _) => response_name.clone(),
+        (None, Some(index)) => index.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+fn field_name(node: &Node) -> &str {
+    match &node.response_name {
+        Some(response_name) if node.original_field_name.is_empty() => response_name,
+        _ => &node.original_field_name,
+    }
+}
+
+fn to_datetime(timestamp: &Timestamp) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(timestamp.seconds, timestamp.nanos.max(0) as u32).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(response_name: &str, start_time: u64, end_time: u64, child: Vec<Node>) -> Node {
+        Node {
+            response_name: Some(response_name.to_string()),
+            r#type: "String".to_string(),
+            parent_type: "Query".to_string(),
+            start_time,
+            end_time,
+            child,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decode_and_convert() {
+        let item = Node {
+            index: Some(0),
+            child: vec![field("name", 20, 30, Vec::new())],
+            ..Default::default()
+        };
+        let trace = Trace {
+            start_time: Some(Timestamp { seconds: 1_600_000_000, nanos: 0 }),
+            end_time: Some(Timestamp { seconds: 1_600_000_001, nanos: 0 }),
+            duration_ns: 50,
+            root: Some(Node {
+                child: vec![field("users", 10, 40, vec![item])],
+                ..Default::default()
+            }),
+        };
+        let mut resp = Response::default();
+        resp.extensions.insert(
+            "ftv1".to_string(),
+            ConstValue::String(base64::engine::general_purpose::STANDARD.encode(trace.encode_to_vec())),
+        );
+
+        let decoded = take_trace(&mut resp).expect("valid trace");
+        assert!(resp.extensions.is_empty());
+        assert_eq!(decoded, trace);
+
+        let value = to_value("accounts", None, &decoded).into_json().expect("valid json");
+        assert_eq!(value["service"], "accounts");
+        assert_eq!(value["children"][0]["responseName"], "users");
+        assert_eq!(value["children"][0]["duration"], 30);
+        assert_eq!(value["children"][0]["children"][0]["index"], 0);
+        assert_eq!(value["children"][0]["children"][0]["children"][0]["fieldName"], "name");
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use http::HeaderMap;
+
+/// Headers describing the subgraph HTTP message itself, they are never copied to the client response.
+const NOT_MERGEABLE: &[&str] = &[
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-type",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// How the values of one header coming from several subgraph responses are combined.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum HeaderMergeStrategy {
+    /// Drop the header, it will not be sent to the client.
+    Ignore,
+    /// Keep the value from the first response that contains the header.
+    First,
+    /// Keep the value from the last response that contains the header.
+    Last,
+    /// Keep all distinct values, each one is sent as a separate header line.
+    Append,
+}
+
+/// Policy used to merge headers of all subgraph responses into the client response.
+///
+/// Responses are merged in a deterministic order: root fetches first, then entity fetches
+/// ordered by their response path, ties are broken by the service name.
+/// Header names are case-insensitive.
+#[derive(Debug, Clone)]
+pub struct HeaderMergePolicy {
+    default: HeaderMergeStrategy,
+    rules: HashMap<String, HeaderMergeStrategy>,
+}
+
+impl Default for HeaderMergePolicy {
+    fn default() -> Self {
+        Self {
+            default: HeaderMergeStrategy::Ignore,
+            rules: Default::default(),
+        }
+    }
+}
+
+impl HeaderMergePolicy {
+    /// Strategy for headers which have no rule. Default is `HeaderMergeStrategy::Ignore`
+    pub fn default_strategy(mut self, strategy: HeaderMergeStrategy) -> Self {
+        self.default = strategy;
+        self
+    }
+
+    /// Strategy for the header with the given name
+    pub fn header(mut self, name: impl AsRef<str>, strategy: HeaderMergeStrategy) -> Self {
+        self.rules.insert(name.as_ref().to_ascii_lowercase(), strategy);
+        self
+    }
+
+    pub fn strategy(&self, name: &str) -> HeaderMergeStrategy {
+        self.rules
+            .get(&name.to_ascii_lowercase())
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Merge headers of subgraph responses. Responses must be already ordered.
+    pub fn merge<'a>(&self, responses: impl IntoIterator<Item=&'a HeaderMap>) -> HeaderMap {
+        let mut merged = HeaderMap::new();
+        for headers in responses {
+            for name in headers.keys() {
+                if NOT_MERGEABLE.contains(&name.as_str()) {
+                    continue;
+                }
+                let values = headers.get_all(name);
+                match self.strategy(name.as_str()) {
+                    HeaderMergeStrategy::Ignore => {}
+                    HeaderMergeStrategy::First => {
+                        if !merged.contains_key(name) {
+                            for value in values {
+                                merged.append(name.clone(), value.clone());
+                            }
+                        }
+                    }
+                    HeaderMergeStrategy::Last => {
+                        merged.remove(name);
+                        for value in values {
+                            merged.append(name.clone(), value.clone());
+                        }
+                    }
+                    HeaderMergeStrategy::Append => {
+                        for value in values {
+                            if !merged.get_all(name).iter().any(|current| current == value) {
+                                merged.append(name.clone(), value.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        merged
+    }
+}
+
+/// Headers received from one subgraph fetch.
+pub struct SubgraphHeaders {
+    pub service: String,
+    pub path: Option<String>,
+    pub headers: HeaderMap,
+}
+
+/// Sort collected headers into the merge order described in `HeaderMergePolicy`.
+pub fn sort_subgraph_headers(headers: &mut [SubgraphHeaders]) {
+    headers.sort_by(|a, b| {
+        (&a.path, &a.service).cmp(&(&b.path, &b.service))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn headers(items: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in items {
+            headers.append(HeaderName::from_static(name), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn values<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+        headers.get_all(name).iter().map(|value| value.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn merge_by_strategy() {
+        let policy = HeaderMergePolicy::default()
+            .header("X-First", HeaderMergeStrategy::First)
+            .header("x-last", HeaderMergeStrategy::Last)
+            .header("vary", HeaderMergeStrategy::Append);
+        let a = headers(&[("x-first", "a"), ("x-last", "a"), ("vary", "accept"), ("server", "a"), ("content-length", "10")]);
+        let b = headers(&[("x-first", "b"), ("x-last", "b"), ("vary", "origin")]);
+        let c = headers(&[("vary", "accept")]);
+        let merged = policy.merge([&a, &b, &c]);
+        assert_eq!(values(&merged, "x-first"), vec!["a"]);
+        assert_eq!(values(&merged, "x-last"), vec!["b"]);
+        assert_eq!(values(&merged, "vary"), vec!["accept", "origin"]);
+        assert!(!merged.contains_key("server"));
+        assert!(!merged.contains_key("content-length"));
+    }
+
+    #[test]
+    fn append_cookies_separately() {
+        let policy = HeaderMergePolicy::default().header("set-cookie", HeaderMergeStrategy::Append);
+        let a = headers(&[("set-cookie", "a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT"), ("set-cookie", "b=2")]);
+        let b = headers(&[("set-cookie", "c=3")]);
+        let merged = policy.merge([&a, &b]);
+        assert_eq!(
+            values(&merged, "set-cookie"),
+            vec!["a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT", "b=2", "c=3"]
+        );
+    }
+
+    #[test]
+    fn root_fetches_first() {
+        let mut collected = vec![
+            SubgraphHeaders { service: "b".into(), path: Some("users".into()), headers: Default::default() },
+            SubgraphHeaders { service: "b".into(), path: None, headers: Default::default() },
+            SubgraphHeaders { service: "a".into(), path: None, headers: Default::default() },
+        ];
+        sort_subgraph_headers(&mut collected);
+        let order: Vec<_> = collected.iter().map(|h| (h.service.as_str(), h.path.as_deref())).collect();
+        assert_eq!(order, vec![("a", None), ("b", None), ("b", Some("users"))]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::planner::{IntrospectionDirective, IntrospectionField, IntrospectionSelectionSet};
+use crate::planner::{is_skipped_by, IntrospectionDirective, IntrospectionField, IntrospectionSelectionSet};
 use crate::schema::ComposedSchema;
 use indexmap::IndexMap;
 use value::{ConstValue, Name};
@@ -31,21 +31,9 @@ pub fn resolve_obj(
 }
 
 fn is_skip(directives: &[IntrospectionDirective]) -> bool {
-    for directive in directives {
-        let include = match directive.name.as_str() {
-            "skip" => false,
-            "include" => true,
-            _ => continue,
-        };
-
-        let condition_input = directive.arguments.get("if").unwrap();
-        let value = match condition_input {
-            ConstValue::Boolean(value) => *value,
-            _ => false,
-        };
-        return include != value;
-    }
-    false
+    directives
+        .iter()
+        .any(|directive| is_skipped_by(directive.name.as_str(), directive.arguments.get("if")))
 }
 
 pub fn is_include_deprecated(arguments: &IndexMap<Name, ConstValue>) -> bool {
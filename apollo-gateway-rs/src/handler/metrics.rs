@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use opentelemetry::metrics::{Counter, Histogram, Meter, MeterProvider, Unit, UpDownCounter};
+use opentelemetry::{Context, KeyValue};
+use parser::types::{ExecutableDocument, OperationType};
+
+use crate::planner::Response;
+
+use super::constants::{KEY_OPERATION_NAME, KEY_OPERATION_TYPE, KEY_SERVICE, KEY_STATUS};
+
+/// Weight of the latest fetch in the moving average of the latency of a service.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Label value of operations whose name is not allowed as a label.
+const OTHER_OPERATION_NAME: &str = "other";
+
+/// Instruments of the gateway, every gateway server creates its own from the meter provider of the builder.
+pub struct Metrics {
+    requests: Counter<u64>,
+    request_errors: Counter<u64>,
+    request_duration: Histogram<f64>,
+    validation_duration: Histogram<f64>,
+    plan_duration: Histogram<f64>,
+    fetches: Counter<u64>,
+    fetch_errors: Counter<u64>,
+    fetch_duration: Histogram<f64>,
+    schema_compositions: Counter<u64>,
+    active_subscriptions: UpDownCounter<i64>,
+    active_subscription_count: AtomicI64,
+    source_subscription_counts: Mutex<HashMap<String, i64>>,
+    fetch_latencies: Mutex<HashMap<String, f64>>,
+}
+
+impl Metrics {
+    pub fn new(provider: &impl MeterProvider) -> Self {
+        Self::from_meter(provider.meter(env!("CARGO_PKG_NAME")))
+    }
+
+    fn from_meter(meter: Meter) -> Self {
+        Self {
+            requests: meter
+                .u64_counter("graphgate.requests")
+                .with_description("Number of operations executed by the gateway")
+                .init(),
+            request_errors: meter
+                .u64_counter("graphgate.request.errors")
+                .with_description("Number of operations which responded with errors")
+                .init(),
+            request_duration: meter
+                .f64_histogram("graphgate.request.duration")
+                .with_description("Duration of operations")
+                .with_unit(Unit::new("s"))
+                .init(),
+            validation_duration: meter
+                .f64_histogram("graphgate.validation.duration")
+                .with_description("Duration of the validation of operations")
+                .with_unit(Unit::new("s"))
+                .init(),
+            plan_duration: meter
+                .f64_histogram("graphgate.plan.duration")
+                .with_description("Duration of query planning")
+                .with_unit(Unit::new("s"))
+                .init(),
+            fetches: meter
+                .u64_counter("graphgate.fetches")
+                .with_description("Number of requests to subgraphs")
+                .init(),
+            fetch_errors: meter
+                .u64_counter("graphgate.fetch.errors")
+                .with_description("Number of requests to subgraphs which failed or responded with errors")
+                .init(),
+            fetch_duration: meter
+                .f64_histogram("graphgate.fetch.duration")
+                .with_description("Duration of requests to subgraphs")
+                .with_unit(Unit::new("s"))
+                .init(),
+            schema_compositions: meter
+                .u64_counter("graphgate.schema.compositions")
+                .with_description("Number of attempts to compose the schema of the subgraphs")
+                .init(),
+            active_subscriptions: meter
+                .i64_up_down_counter("graphgate.subscriptions.active")
+                .with_description("Number of running subscriptions")
+                .init(),
+            active_subscription_count: AtomicI64::new(0),
+            source_subscription_counts: Default::default(),
+            fetch_latencies: Default::default(),
+        }
+    }
+
+    /// Only operation names of `operation_names` are used as label values, see `OperationLabels::attributes`.
+    pub fn record_request(
+        &self,
+        labels: &OperationLabels,
+        operation_names: &HashSet<String>,
+        started: Instant,
+        resp: &Response,
+    ) {
+        let cx = Context::current();
+        let attributes = labels.attributes(operation_names);
+        self.requests.add(&cx, 1, &attributes);
+        self.request_duration.record(&cx, started.elapsed().as_secs_f64(), &attributes);
+        if !resp.errors.is_empty() {
+            self.request_errors.add(&cx, 1, &attributes);
+        }
+    }
+
+    pub fn record_validation(&self, started: Instant) {
+        self.validation_duration.record(&Context::current(), started.elapsed().as_secs_f64(), &[]);
+    }
+
+    pub fn record_plan(&self, started: Instant) {
+        self.plan_duration.record(&Context::current(), started.elapsed().as_secs_f64(), &[]);
+    }
+
+    pub fn record_fetch(&self, service: &str, started: Instant, resp: &anyhow::Result<Response>) {
+        let cx = Context::current();
+        let status = match resp {
+            Ok(resp) if resp.errors.is_empty() => "ok",
+            Ok(_) => "graphql_error",
+            Err(_) => "error",
+        };
+        let attributes = [
+            KEY_SERVICE.string(service.to_string()),
+            KEY_STATUS.string(status),
+        ];
+        self.fetches.add(&cx, 1, &attributes);
+        self.fetch_duration.record(&cx, started.elapsed().as_secs_f64(), &attributes);
+        if status != "ok" {
+            self.fetch_errors.add(&cx, 1, &attributes);
+        }
+
+        let latency = started.elapsed().as_secs_f64() * 1000.0;
+        let mut latencies = self.fetch_latencies.lock().unwrap_or_else(PoisonError::into_inner);
+        latencies
+            .entry(service.to_string())
+            .and_modify(|average| *average += LATENCY_SMOOTHING * (latency - *average))
+            .or_insert(latency);
+    }
+
+    /// Moving average of the fetch latency of every service in milliseconds, used by the query planner.
+    pub fn fetch_latencies(&self) -> HashMap<String, f64> {
+        self.fetch_latencies.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn record_schema_composition(&self, succeeded: bool) {
+        let status = if succeeded { "ok" } else { "error" };
+        self.schema_compositions.add(&Context::current(), 1, &[KEY_STATUS.string(status)]);
+    }
+
+    /// Counts a subscription as active until the guard is dropped.
+    pub fn subscription_started(self: &Arc<Self>) -> SubscriptionGuard {
+        self.active_subscriptions.add(&Context::current(), 1, &[]);
+        self.active_subscription_count.fetch_add(1, Ordering::Relaxed);
+        SubscriptionGuard {
+            metrics: Arc::clone(self),
+            services: Vec::new(),
+        }
+    }
+
+    pub fn active_subscriptions(&self) -> i64 {
+        self.active_subscription_count.load(Ordering::Relaxed)
+    }
+
+    /// Running subscriptions which subscribed to the source.
+    pub fn active_source_subscriptions(&self, service: &str) -> i64 {
+        let counts = self.source_subscription_counts.lock().unwrap_or_else(PoisonError::into_inner);
+        counts.get(service).copied().unwrap_or_default()
+    }
+
+    fn add_source_subscriptions(&self, services: &[String], delta: i64) {
+        let mut counts = self.source_subscription_counts.lock().unwrap_or_else(PoisonError::into_inner);
+        for service in services {
+            *counts.entry(service.clone()).or_default() += delta;
+        }
+    }
+}
+
+pub struct SubscriptionGuard {
+    metrics: Arc<Metrics>,
+    services: Vec<String>,
+}
+
+impl SubscriptionGuard {
+    /// Counts the subscription for the sources it subscribed to, once it has been planned.
+    pub fn subscribed_to(&mut self, services: Vec<String>) {
+        self.metrics.add_source_subscriptions(&services, 1);
+        self.services.extend(services);
+    }
+}
+
+impl Drop for SubscriptionGuard {
+    fn drop(&mut self) {
+        self.metrics.active_subscriptions.add(&Context::current(), -1, &[]);
+        self.metrics.active_subscription_count.fetch_sub(1, Ordering::Relaxed);
+        self.metrics.add_source_subscriptions(&self.services, -1);
+    }
+}
+
+/// Labels of the operation metrics, they are known once the request has been parsed.
+#[derive(Default)]
+pub struct OperationLabels {
+    name: Option<String>,
+    ty: Option<&'static str>,
+}
+
+impl OperationLabels {
+    pub fn set(&mut self, document: &ExecutableDocument, operation_name: Option<&str>) {
+        let operation = document
+            .operations
+            .iter()
+            .find(|(name, _)| operation_name.is_none() || name.map(|name| name.as_str()) == operation_name);
+        if let Some((name, operation)) = operation {
+            self.name = name.map(|name| name.to_string());
+            self.ty = Some(match operation.node.ty {
+                OperationType::Query => "query",
+                OperationType::Mutation => "mutation",
+                OperationType::Subscription => "subscription",
+            });
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn ty(&self) -> Option<&'static str> {
+        self.ty
+    }
+
+    /// Operation names are chosen by clients, names which are not allowed are replaced with `other`
+    /// so the number of label values stays bounded. Anonymous operations have an empty name.
+    fn attributes(&self, operation_names: &HashSet<String>) -> [KeyValue; 2] {
+        let name = match &self.name {
+            Some(name) if operation_names.contains(name) => name.clone(),
+            Some(_) => OTHER_OPERATION_NAME.to_string(),
+            None => String::new(),
+        };
+        [
+            KEY_OPERATION_NAME.string(name),
+            KEY_OPERATION_TYPE.string(self.ty.unwrap_or("unknown")),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation_name(query: &str, operation_names: &HashSet<String>) -> String {
+        let mut labels = OperationLabels::default();
+        labels.set(&parser::parse_query(query).unwrap(), None);
+        labels.attributes(operation_names)[0].value.as_str().to_string()
+    }
+
+    #[test]
+    fn bound_operation_names() {
+        let operation_names = HashSet::from(["TopProducts".to_string()]);
+        assert_eq!(operation_name("query TopProducts { a }", &operation_names), "TopProducts");
+        assert_eq!(operation_name("query Random123 { a }", &operation_names), "other");
+        assert_eq!(operation_name("{ a }", &operation_names), "");
+    }
+}
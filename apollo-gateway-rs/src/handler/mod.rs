@@ -1,13 +1,26 @@
 #![forbid(unsafe_code)]
 
+pub use access_log::AccessLog;
+pub use ftv1::FederatedTracing;
+pub use headers::{HeaderMergePolicy, HeaderMergeStrategy};
 pub use service_route::{ServiceRouteTable};
-pub use shared_route_table::SharedRouteTable;
-pub use websocket::{Subscription, Protocols};
+pub use metrics::Metrics;
+pub use shared_route_table::{ExecuteOptions, SharedRouteTable};
+pub use status::{GatewayStatus, SourceStatus};
+#[cfg(feature = "actix")]
+pub use websocket::Subscription;
+pub use websocket::{serve_websocket, Protocols};
 
+pub(crate) mod access_log;
 pub mod constants;
 mod executor;
 mod fetcher;
+mod ftv1;
+mod headers;
 mod introspection;
+mod metrics;
+pub(crate) mod propagation;
 mod service_route;
 mod shared_route_table;
+mod status;
 mod websocket;
\ No newline at end of file
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use http::HeaderMap;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::{global, Context};
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Extract the trace context and baggage of the client with the global propagator,
+/// for example `traceparent` and `tracestate` if `TraceContextPropagator` is installed.
+pub fn extract(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+/// Inject the trace context and baggage into the headers of a request to a subgraph.
+pub fn inject(cx: &Context, headers: &mut HashMap<String, String>) {
+    global::get_text_map_propagator(|propagator| propagator.inject_context(cx, headers));
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::sdk::propagation::TraceContextPropagator;
+    use opentelemetry::trace::TraceContextExt;
+
+    use super::*;
+
+    #[test]
+    fn round_trip_traceparent() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let mut client_headers = HeaderMap::new();
+        client_headers.insert("traceparent", traceparent.parse().expect("valid header"));
+
+        let cx = extract(&client_headers);
+        assert!(cx.span().span_context().is_remote());
+
+        let mut headers = HashMap::new();
+        inject(&cx, &mut headers);
+        assert_eq!(headers.get("traceparent").map(String::as_str), Some(traceparent));
+    }
+}
@@ -1,28 +1,45 @@
 use std::collections::HashMap;
 
 use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use tower::service_fn;
+use tower::util::BoxCloneService;
 
 use crate::planner::{RequestData, Response};
 
 
 use crate::datasource::{Context, RemoteGraphQLDataSource, GraphqlSourceMiddleware};
+use crate::service::{BoxError, LayerStack, SharedService, SubgraphRequest};
 use crate::Request;
 
 
 ///
 /// The key is the service name.
-#[derive(Default, Clone)]
-pub struct ServiceRouteTable<Source: RemoteGraphQLDataSource + GraphqlSourceMiddleware>(HashMap<String, Source>);
+#[derive(Clone)]
+pub struct ServiceRouteTable<Source: RemoteGraphQLDataSource + GraphqlSourceMiddleware> {
+    sources: HashMap<String, Source>,
+    service: Option<SharedService<SubgraphRequest>>,
+}
+
+impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> Default for ServiceRouteTable<S> {
+    fn default() -> Self {
+        Self::from(HashMap::new())
+    }
+}
 
 impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> From<HashMap<String, S>> for ServiceRouteTable<S> {
     fn from(map: HashMap<String, S>) -> Self {
-        Self (map)
+        Self {
+            sources: map,
+            service: None,
+        }
     }
 }
 
 impl<Source: RemoteGraphQLDataSource + GraphqlSourceMiddleware> PartialEq for ServiceRouteTable<Source> {
     fn eq(&self, other: &Self) -> bool {
-        self.0.keys().all(|key| other.contains_key(key))
+        self.sources.keys().all(|key| other.contains_key(key))
     }
 }
 
@@ -30,12 +47,33 @@ impl<Source: RemoteGraphQLDataSource + GraphqlSourceMiddleware> Deref for Servic
     type Target = HashMap<String, Source>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.sources
     }
 }
 impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> DerefMut for ServiceRouteTable<S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.sources
+    }
+}
+
+impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware + Clone> ServiceRouteTable<S> {
+    /// Send the subgraph requests of `query` through the layers.
+    pub(crate) fn with_layers(mut self, layers: &LayerStack<SubgraphRequest>) -> Self {
+        if layers.is_empty() {
+            return self;
+        }
+        let sources = Arc::new(self.sources.clone());
+        let fetch = service_fn(move |request: SubgraphRequest| {
+            let sources = Arc::clone(&sources);
+            async move {
+                let source = sources.get(&request.service).ok_or_else(|| {
+                    anyhow::anyhow!("Service '{}' is not defined in the routing table.", request.service)
+                })?;
+                source.fetch(request.request).await.map_err(BoxError::from)
+            }
+        });
+        self.service = Some(SharedService::new(layers.apply(BoxCloneService::new(fetch))));
+        self
     }
 }
 
@@ -44,19 +82,27 @@ impl<Source: RemoteGraphQLDataSource + GraphqlSourceMiddleware> ServiceRouteTabl
     pub async fn query(
         &self,
         service: impl AsRef<str>,
-        request: RequestData,
-        ctx: &Context
+        mut request: Request,
+        ctx: &Arc<Context>
     ) -> anyhow::Result<Response> {
         let service = service.as_ref();
-        let source = self.0.get(service).ok_or_else(|| {
+        let source = self.sources.get(service).ok_or_else(|| {
             anyhow::anyhow!("Service '{}' is not defined in the routing table.", service)
         })?;
 
-        let mut headers = HashMap::new();
-
-        source.will_send_request(&mut headers, ctx).await?;
+        source.will_send_request(&mut request, ctx).await?;
 
-        let mut resp = source.fetch(Request { headers, data: request}).await?;
+        let mut resp = match &self.service {
+            Some(layered) => layered
+                .call(SubgraphRequest {
+                    service: service.to_string(),
+                    request,
+                    context: Arc::clone(ctx),
+                })
+                .await
+                .map_err(|err| anyhow::anyhow!(err))?,
+            None => source.fetch(request).await?,
+        };
 
         source.did_receive_response(&mut resp, ctx).await?;
 
@@ -68,10 +114,10 @@ impl<Source: RemoteGraphQLDataSource + GraphqlSourceMiddleware> ServiceRouteTabl
         request: RequestData,
     ) -> anyhow::Result<Response> {
         let service = service.as_ref();
-        let source = self.0.get(service).ok_or_else(|| {
+        let source = self.sources.get(service).ok_or_else(|| {
             anyhow::anyhow!("Service '{}' is not defined in the routing table.", service)
         })?;
-        let resp = source.fetch(Request {headers:  HashMap::with_capacity(0) , data: request}).await?;
+        let resp = source.fetch(Request::new(request)).await?;
         Ok(resp)
     }
 }
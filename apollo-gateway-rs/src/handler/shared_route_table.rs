@@ -1,93 +1,98 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use actix_web::HttpResponse;
 
 use anyhow::{Context, Error, Result};
-use crate::planner::{PlanBuilder, RequestData, Response, ServerError};
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use http::HeaderValue;
+use crate::planner::{plan_to_json, CostModel, PlanBuilder, RequestData, Response, RootNode, ServerError};
 use crate::schema::ComposedSchema;
-use opentelemetry::trace::{TraceContextExt, Tracer};
+use opentelemetry::trace::{Span, TraceContextExt, Tracer};
 use opentelemetry::{global, Context as OpenTelemetryContext};
 use parser::Positioned;
 use parser::types::{ExecutableDocument, Selection, SelectionSet};
 use serde::Deserialize;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::RwLock;
 use tokio::time::{Duration, Instant};
+use value::{ConstValue, Name};
+use crate::cache::{hash_parts, EntityCache, ResponseCache};
 use crate::datasource::RemoteGraphQLDataSource;
+use crate::plugin::Plugins;
+use crate::usage::{ClientInfo, UsageReporting};
 use crate::GraphqlSourceMiddleware;
 
+use super::access_log::{AccessLog, AccessRecord};
 use super::executor::Executor;
 use super::fetcher::HttpFetcher;
+use super::ftv1::FederatedTracing;
+use super::headers::HeaderMergePolicy;
+use super::metrics::{Metrics, OperationLabels};
 use super::service_route::ServiceRouteTable;
-
-enum Command<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> {
-    Change(ServiceRouteTable<S>),
-}
+use super::status::{GatewayStatus, SourceState};
+use super::websocket::WebSocketController;
 
 struct Inner<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> {
     schema: Option<Arc<ComposedSchema>>,
     route_table: Option<Arc<ServiceRouteTable<S>>>,
+    sources: HashMap<String, SourceState>,
+    last_error: Option<String>,
 }
 
 pub struct SharedRouteTable<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> {
     inner: Arc<RwLock<Inner<S>>>,
-    tx: mpsc::UnboundedSender<Command<S>>,
+    metrics: Arc<Metrics>,
 }
 
 impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> Clone for SharedRouteTable<S> {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
-            tx: self.tx.clone(),
+            metrics: Arc::clone(&self.metrics),
         }
     }
 }
 
-impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> Default for SharedRouteTable<S> {
-    fn default() -> Self {
-        let (tx, rx) = mpsc::unbounded_channel();
+impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> SharedRouteTable<S> {
+    /// Create a shared route table which polls the schema of the sources in the background.
+    pub fn new(route_table: ServiceRouteTable<S>, metrics: Metrics) -> Self {
         let shared_route_table = Self {
             inner: Arc::new(RwLock::new(Inner {
                 schema: None,
-                route_table: None,
+                route_table: Some(Arc::new(route_table)),
+                sources: HashMap::new(),
+                last_error: None,
             })),
-            tx,
+            metrics: Arc::new(metrics),
         };
         tokio::spawn({
             let shared_route_table = shared_route_table.clone();
-            async move { shared_route_table.update_loop(rx).await }
+            async move { shared_route_table.update_loop().await }
         });
         shared_route_table
     }
-}
 
-impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> SharedRouteTable<S> {
-    async fn update_loop(self, mut rx: mpsc::UnboundedReceiver<Command<S>>) {
+    async fn update_loop(self) {
         let mut update_interval = tokio::time::interval_at(
             Instant::now() + Duration::from_secs(3),
             Duration::from_secs(30),
         );
 
         loop {
-            tokio::select! {
-                _ = update_interval.tick() => {
-                    if let Err(err) = self.update().await {
-                        tracing::error!(error = %err, "Failed to update schema.");
-                    }
-                }
-                command = rx.recv() => {
-                    if let Some(command) = command {
-                        match command {
-                            Command::Change(route_table) => {
-                                let mut inner = self.inner.write().await;
-                                inner.route_table = Some(Arc::new(route_table));
-                                inner.schema = None;
-                            }
-                        }
-                    }
-                }
-            }
+            update_interval.tick().await;
+            self.refresh().await.ok();
         }
     }
 
+    /// Fetch the SDL of all sources and compose the schema.
+    pub async fn refresh(&self) -> Result<()> {
+        let result = self.update().await;
+        if let Err(err) = &result {
+            tracing::error!(error = %err, "Failed to update schema.");
+        }
+        self.inner.write().await.last_error = result.as_ref().err().map(|err| format!("{:#}", err));
+        result
+    }
+
     async fn update(&self) -> Result<()> {
         const QUERY_SDL: &str = "{ _service { sdl }}";
 
@@ -107,30 +112,56 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> SharedRouteTable<S> {
             None => return Ok(()),
         };
 
-        let resp = futures_util::future::try_join_all(route_table.keys().map(|service| {
+        let results = futures_util::future::join_all(route_table.keys().map(|service| {
             let route_table = route_table.clone();
             async move {
-                let resp = route_table
-                    .get_schema(service, RequestData::new(QUERY_SDL))
-                    .await
-                    .with_context(|| format!("Failed to fetch SDL from '{}'.", service))?;
-                let resp: ResponseQuery =
-                    value::from_value(resp.data.unwrap_or_default()).context("Failed to parse response.")?;
-                let document = parser::parse_schema(resp.service.sdl)
-                    .with_context(|| format!("Invalid SDL from '{}'.", service))?;
-                Ok::<_, Error>((service.to_string(), document))
+                let result = async {
+                    let resp = route_table
+                        .get_schema(service, RequestData::new(QUERY_SDL))
+                        .await
+                        .with_context(|| format!("Failed to fetch SDL from '{}'.", service))?;
+                    let resp: ResponseQuery =
+                        value::from_value(resp.data.unwrap_or_default()).context("Failed to parse response.")?;
+                    let document = parser::parse_schema(&resp.service.sdl)
+                        .with_context(|| format!("Invalid SDL from '{}'.", service))?;
+                    Ok::<_, Error>((service.to_string(), resp.service.sdl, document))
+                }
+                .await;
+                (service, result)
             }
         }))
-            .await?;
-
+            .await;
 
-        let schema = ComposedSchema::combine(resp)?;
+        {
+            let mut inner = self.inner.write().await;
+            for (service, result) in &results {
+                let state = inner.sources.entry(service.to_string()).or_default();
+                state.record(result);
+                if let Ok((_, sdl, _)) = result {
+                    state.schema_hash = Some(hash_parts(&[sdl]));
+                }
+            }
+        }
+        let resp = results
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect::<Result<Vec<_>>>()
+            .inspect_err(|_| self.metrics.record_schema_composition(false))?;
+
+        let mut sdls = resp.iter().map(|(service, sdl, _)| (service.as_str(), sdl.as_str())).collect::<Vec<_>>();
+        sdls.sort_unstable();
+        let version = hash_parts(&sdls.into_iter().flat_map(|(service, sdl)| [service, sdl]).collect::<Vec<_>>());
+
+        let schema = ComposedSchema::combine(resp.into_iter().map(|(service, _, document)| (service, document)));
+        self.metrics.record_schema_composition(schema.is_ok());
+        let mut schema = schema?;
+        schema.version = Some(version);
         self.inner.write().await.schema = Some(Arc::new(schema));
         Ok(())
     }
 
-    pub fn set_route_table(&self, route_table: ServiceRouteTable<S>) {
-        self.tx.send(Command::Change(route_table)).ok();
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
     }
 
     pub async fn get(&self) -> Option<(Arc<ComposedSchema>, Arc<ServiceRouteTable<S>>)> {
@@ -141,89 +172,331 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> SharedRouteTable<S> {
         composed_schema.zip(route_table)
     }
 
-    pub async fn query(&self, request: RequestData, ctx: crate::datasource::Context, limit: Option<usize>) -> HttpResponse {
-        let tracer = global::tracer("graphql");
+    /// State of the composed schema and of the subgraphs as of the last poll.
+    pub async fn status(&self) -> GatewayStatus {
+        let inner = self.inner.read().await;
+        let mut sources = inner
+            .route_table
+            .iter()
+            .flat_map(|route_table| route_table.iter())
+            .map(|(name, source)| {
+                let mut status = inner.sources.get(name).cloned().unwrap_or_default().status(name, source.url_query());
+                status.active_subscriptions = self.metrics.active_source_subscriptions(name);
+                status
+            })
+            .collect::<Vec<_>>();
+        sources.sort_by(|a, b| a.name.cmp(&b.name));
+        GatewayStatus {
+            ready: inner.schema.is_some(),
+            schema_hash: inner.schema.as_ref().and_then(|schema| schema.version.clone()),
+            last_error: inner.last_error.clone(),
+            active_subscriptions: self.metrics.active_subscriptions(),
+            sources,
+        }
+    }
 
-        let document = match tracer.in_span("parse", |_| parser::parse_query(&request.query)) {
-            Ok(document) => document,
-            Err(err) => {
-                return HttpResponse::BadRequest().body(err.to_string());
-            }
+    /// Validate and plan an operation without executing it.
+    pub async fn plan(&self, request: RequestData, options: &ExecuteOptions) -> Result<serde_json::Value, Response> {
+        let (composed_schema, _) = self.get().await.ok_or_else(|| Response::error("Not ready."))?;
+        plan_to_json(&composed_schema, request, options.cost_model(&self.metrics))
+    }
+
+    /// Execute a query or a mutation. Headers for the client are returned in `Response::headers`.
+    pub async fn execute(&self, request: RequestData, ctx: crate::datasource::Context, options: &ExecuteOptions) -> Response {
+        let started = std::time::Instant::now();
+        let ctx = Arc::new(ctx);
+        let mut labels = OperationLabels::default();
+        let mut record = options.access_log.as_ref().map(|_| AccessRecord::new(&request.query, &ctx));
+        let mut resp = match self.execute_operation(request, &ctx, options, &mut labels, record.as_mut()).await {
+            Ok(resp) | Err(resp) => resp,
         };
-        if let Some(limit) = limit {
-            match check_recursive_depth(&document, limit) {
-                Ok(_) => {},
-                Err(e) => {
-                    let response = Response {
-                        data: None,
-                        errors: vec![e],
-                        extensions: Default::default(),
-                        headers: Default::default(),
-                    };
-                    let response = match serde_json::to_string(&response) {
-                        Ok(r) => r,
-                        Err(e) => return HttpResponse::BadRequest().body(e.to_string())
-                    };
-                    return HttpResponse::Ok().body(response);
-                }
-            }
+        options.plugins.response_ready(&mut resp, &ctx).await;
+        self.metrics.record_request(&labels, &options.metric_operation_names, started, &resp);
+        if let Some((access_log, record)) = options.access_log.as_ref().zip(record) {
+            access_log.log(&labels, &record, started, &resp);
         }
+        resp
+    }
 
+    async fn execute_operation(
+        &self,
+        mut request: RequestData,
+        ctx: &Arc<crate::datasource::Context>,
+        options: &ExecuteOptions,
+        labels: &mut OperationLabels,
+        mut record: Option<&mut AccessRecord>,
+    ) -> Result<Response, Response> {
+        let tracer = global::tracer("graphql");
+        let plugins = &options.plugins;
+
+        plugins.request_received(&mut request, ctx).await?;
+        let document = tracer
+            .in_span("parse", |_| parser::parse_query(&request.query))
+            .map_err(|err| Response::error(err.to_string()))?;
+        labels.set(&document, request.operation.as_deref());
+        if let Some(limit) = options.limit {
+            check_recursive_depth(&document, limit).map_err(|err| Response {
+                data: None,
+                errors: vec![err],
+                extensions: Default::default(),
+                headers: Default::default(),
+            })?;
+        }
+        plugins.request_parsed(&document, ctx).await?;
 
-        let (composed_schema, route_table) = match self.get().await {
-            Some((composed_schema, route_table)) => (composed_schema, route_table),
-            _ => {
-                let response = Response {
-                    data: None,
-                    errors: vec![ServerError::new("Not ready.")],
-                    extensions: Default::default(),
-                    headers: Default::default(),
-                };
-                let response = match serde_json::to_string(&response) {
-                    Ok(r) => r,
-                    Err(e) => return HttpResponse::BadRequest().body(e.to_string())
-                };
-                return HttpResponse::Ok().body(response);
-            }
-        };
+        let (composed_schema, route_table) = self.get().await.ok_or_else(|| Response::error("Not ready."))?;
+        if let Some(record) = record.as_deref_mut() {
+            record.schema_version = composed_schema.version.clone();
+            record.variables = options.access_log.as_ref().map(|access_log| access_log.variables(&request.variables));
+        }
 
         let root_kind = document.operations.iter().next()
             .map(|(_, op)| op.node.ty.into())
             .unwrap_or(super::introspection::RootKind::Query);
 
-        let mut plan_builder =
-            PlanBuilder::new(&composed_schema, document).variables(request.variables);
+        let query_plan_mode = if options.query_plan_header { QueryPlanMode::from_context(ctx) } else { None };
+        // Responses with a query plan are neither read from nor written to the cache.
+        let response_cache = options.response_cache.as_ref().filter(|_| query_plan_mode.is_none());
+        let cache_lookup = response_cache
+            .and_then(|cache| cache.lookup(&composed_schema, &document, &request, ctx));
+
+        let mut plan_builder = PlanBuilder::new(&composed_schema, document)
+            .variables(request.variables)
+            .cost_model(options.cost_model(&self.metrics));
 
         if let Some(operation) = request.operation {
             plan_builder = plan_builder.operation_name(operation);
         }
 
-
-
-        let plan = match tracer.in_span("plan", |_| plan_builder.plan()) {
-            Ok(plan) => plan,
-            Err(response) => {
-                let response = match serde_json::to_string(&response) {
-                    Ok(r) => r,
-                    Err(e) => return HttpResponse::BadRequest().body(e.to_string())
+        let started = std::time::Instant::now();
+        let mut span = tracer.start("validate");
+        let validated = plan_builder.validate();
+        span.end();
+        validated?;
+        self.metrics.record_validation(started);
+        plugins.request_validated(plan_builder.document(), ctx).await?;
+        let started = std::time::Instant::now();
+        let mut span = tracer.start("plan");
+        let planned = plan_builder.plan_with_alternatives();
+        span.end();
+        let (plan, alternatives) = planned?;
+        self.metrics.record_plan(started);
+        if let Some(usage_reporting) = &options.usage_reporting {
+            usage_reporting.record(ClientInfo::from_context(ctx), plan_builder.field_usage());
+        }
+        plugins.query_planned(&plan, ctx).await?;
+        // Cached responses are served only after plugins had the chance to reject the operation.
+        if let Some((cache, lookup)) = response_cache.zip(cache_lookup.as_ref()) {
+            if let Some(entry) = cache.get(lookup).await {
+                let age = entry.age();
+                let cache_control = entry.cache_control();
+                let mut response = Response {
+                    data: Some(entry.data),
+                    ..Default::default()
                 };
-                return HttpResponse::BadRequest().body(response);
+                response.headers.insert("age", HeaderValue::from(age));
+                if let Ok(cache_control) = HeaderValue::from_str(&cache_control) {
+                    response.headers.insert("cache-control", cache_control);
+                }
+                return Ok(response);
             }
+        }
+        let query_plan = match query_plan_mode {
+            Some(mode) => {
+                let mut query_plan = value::to_value(&plan).map_err(|err| Response::error(err.to_string()))?;
+                if let (ConstValue::Object(object), Some(alternatives)) = (&mut query_plan, alternatives) {
+                    let alternatives = value::to_value(&alternatives).map_err(|err| Response::error(err.to_string()))?;
+                    object.insert(Name::new("alternatives"), alternatives);
+                }
+                if mode == QueryPlanMode::PlanOnly {
+                    let mut resp = Response::default();
+                    resp.extensions.insert("queryPlan".to_string(), query_plan);
+                    return Ok(resp);
+                }
+                Some(query_plan)
+            }
+            None => None,
         };
 
-
-        let executor = Executor::new(&composed_schema);
-        let fetcher = HttpFetcher::new(&*route_table, ctx);
-        let resp = opentelemetry::trace::FutureExt::with_context(
-            executor.execute_query(&fetcher, &plan, root_kind),
+        let executor = Executor::new(&composed_schema)
+            .header_policy(&options.header_policy)
+            .entity_cache(options.entity_cache.as_ref())
+            .federated_tracing(options.federated_tracing)
+            .abort_mutations_on_error(options.abort_mutations_on_error);
+        let fetcher = HttpFetcher::new(&*route_table, Arc::clone(ctx))
+            .plugins(plugins)
+            .metrics(&self.metrics)
+            .include_trace(options.federated_tracing.is_some());
+        let (mut resp, subgraph_cache_policy) = opentelemetry::trace::FutureExt::with_context(
+            executor.execute_cacheable_query(&fetcher, &plan, root_kind),
             OpenTelemetryContext::current_with_span(tracer.span_builder("execute").start(&tracer)),
         )
             .await;
-        let response = match serde_json::to_string(&resp) {
-            Ok(r) => r,
-            Err(e) => return HttpResponse::BadRequest().body(e.to_string())
+        plan_builder.shape_response(&mut resp);
+        if let Some(query_plan) = query_plan {
+            resp.extensions.insert("queryPlan".to_string(), query_plan);
+        }
+        if let Some(record) = record {
+            record.services = fetcher.into_services();
+        }
+
+        if let Some((cache, lookup)) = response_cache.zip(cache_lookup) {
+            let mut policy = lookup.policy;
+            policy.restrict(subgraph_cache_policy);
+            if !resp.errors.is_empty() {
+                policy.restrict_max_age(0);
+            }
+            if let Some(data) = &resp.data {
+                cache.set(&lookup, policy, data.clone()).await;
+            }
+            if let Ok(cache_control) = HeaderValue::from_str(&cache.cache_control(&policy)) {
+                resp.headers.insert("cache-control", cache_control);
+            }
+        }
+        Ok(resp)
+    }
+
+    /// Execute a subscription, every event of the subgraphs produces a response.
+    ///
+    /// Subgraphs are subscribed through their websocket endpoints, the connections are closed when the stream is dropped.
+    pub fn subscribe(&self, request: RequestData, ctx: Arc<crate::datasource::Context>, options: Arc<ExecuteOptions>) -> BoxStream<'static, Response> {
+        let table = self.clone();
+        Box::pin(async_stream::stream! {
+            let (composed_schema, route_table) = match table.get().await {
+                Some(value) => value,
+                None => {
+                    yield Response::error("Not ready.");
+                    return;
+                }
+            };
+            let controller = WebSocketController::new(route_table, None, Arc::clone(&ctx));
+            let mut stream = subscription_stream(composed_schema, controller, "1".to_string(), request, options, table.metrics(), ctx);
+            while let Some(resp) = stream.next().await {
+                yield resp;
+            }
+        })
+    }
+}
+
+/// Execute a subscription with the subgraph connections of an existing client connection.
+pub(crate) fn subscription_stream(
+    composed_schema: Arc<ComposedSchema>,
+    controller: WebSocketController,
+    id: String,
+    mut request: RequestData,
+    options: Arc<ExecuteOptions>,
+    metrics: Arc<Metrics>,
+    ctx: Arc<crate::datasource::Context>,
+) -> BoxStream<'static, Response> {
+    Box::pin(async_stream::stream! {
+        let plugins = &options.plugins;
+        let mut active = metrics.subscription_started();
+        if let Err(resp) = plugins.request_received(&mut request, &ctx).await {
+            yield resp;
+            return;
+        }
+        let document = match parser::parse_query(&request.query) {
+            Ok(document) => document,
+            Err(err) => {
+                yield Response::error(err.to_string());
+                return;
+            }
+        };
+        if let Err(resp) = plugins.request_parsed(&document, &ctx).await {
+            yield resp;
+            return;
+        }
+        let mut builder = PlanBuilder::new(&composed_schema, document)
+            .variables(request.variables)
+            .cost_model(options.cost_model(&metrics));
+        if let Some(operation) = request.operation {
+            builder = builder.operation_name(operation);
+        }
+        let started = std::time::Instant::now();
+        if let Err(resp) = builder.validate() {
+            yield resp;
+            return;
+        }
+        metrics.record_validation(started);
+        if let Err(resp) = plugins.request_validated(builder.document(), &ctx).await {
+            yield resp;
+            return;
+        }
+        let started = std::time::Instant::now();
+        let node = match builder.plan_validated() {
+            Ok(node) => node,
+            Err(resp) => {
+                yield resp;
+                return;
+            }
         };
-        HttpResponse::Ok().body(response)
+        metrics.record_plan(started);
+        if let Err(resp) = plugins.query_planned(&node, &ctx).await {
+            yield resp;
+            return;
+        }
+        if let RootNode::Subscribe(subscribe_node) = &node {
+            active.subscribed_to(subscribe_node.subscribe_nodes.iter().map(|fetch| fetch.service.to_string()).collect());
+        }
+        let executor = Executor::new(&composed_schema);
+        let parent = super::propagation::extract(ctx.headers());
+        let mut stream = opentelemetry::trace::FutureExt::with_context(
+            executor.execute_stream(controller, &id, &node),
+            parent,
+        )
+            .await;
+        while let Some(mut resp) = stream.next().await {
+            builder.shape_response(&mut resp);
+            plugins.subscription_event(&mut resp, &ctx).await;
+            yield resp;
+        }
+    })
+}
+
+/// Settings of `SharedRouteTable::execute`, configured with `GatewayServerBuilder`.
+#[derive(Default)]
+pub struct ExecuteOptions {
+    pub limit: Option<usize>,
+    pub header_policy: HeaderMergePolicy,
+    pub response_cache: Option<ResponseCache>,
+    pub entity_cache: Option<EntityCache>,
+    pub plugins: Plugins,
+    pub federated_tracing: Option<FederatedTracing>,
+    pub access_log: Option<AccessLog>,
+    pub usage_reporting: Option<UsageReporting>,
+    pub query_plan_header: bool,
+    pub cost_model: CostModel,
+    /// Skip the remaining root fields of a mutation once one of them failed.
+    pub abort_mutations_on_error: bool,
+    /// Operation names which are used as values of the `operation_name` metric label.
+    pub metric_operation_names: HashSet<String>,
+}
+
+impl ExecuteOptions {
+    /// The configured cost model with the latencies measured so far.
+    fn cost_model(&self, metrics: &Metrics) -> CostModel {
+        self.cost_model.clone().latencies(metrics.fetch_latencies())
+    }
+}
+
+/// Header of client requests which asks for the query plan in `extensions.queryPlan` of the response,
+/// the operation is not executed if its value is `plan-only`.
+pub const QUERY_PLAN_HEADER: &str = "apollo-query-plan-experimental";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueryPlanMode {
+    Include,
+    PlanOnly,
+}
+
+impl QueryPlanMode {
+    fn from_context(ctx: &crate::datasource::Context) -> Option<Self> {
+        let value = ctx.headers().get(QUERY_PLAN_HEADER)?;
+        Some(if value.as_bytes().eq_ignore_ascii_case(b"plan-only") {
+            QueryPlanMode::PlanOnly
+        } else {
+            QueryPlanMode::Include
+        })
     }
 }
 
@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// State of the gateway, returned by `GatewayServer::status`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayStatus {
+    /// Whether a schema has been composed, operations are rejected with `Not ready.` until then.
+    pub ready: bool,
+    /// Hash of the subgraph SDLs of the composed schema.
+    pub schema_hash: Option<String>,
+    /// Error of the last attempt to compose the schema, if it failed.
+    pub last_error: Option<String>,
+    pub active_subscriptions: i64,
+    pub sources: Vec<SourceStatus>,
+}
+
+/// State of a subgraph as of the last schema poll.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceStatus {
+    pub name: String,
+    pub url: String,
+    /// Whether the SDL could be fetched in the last poll.
+    pub reachable: bool,
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// Hash of the last SDL fetched from the source.
+    pub schema_hash: Option<String>,
+    /// Running subscriptions which subscribed to the source.
+    pub active_subscriptions: i64,
+}
+
+/// Result of the SDL polls of a source.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SourceState {
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub schema_hash: Option<String>,
+}
+
+impl SourceState {
+    pub fn record<T>(&mut self, result: &anyhow::Result<T>) {
+        let now = Utc::now();
+        self.last_fetch = Some(now);
+        match result {
+            Ok(_) => {
+                self.last_success = Some(now);
+                self.last_error = None;
+            }
+            Err(err) => self.last_error = Some(format!("{:#}", err)),
+        }
+    }
+
+    pub fn status(&self, name: &str, url: String) -> SourceStatus {
+        SourceStatus {
+            name: name.to_string(),
+            url,
+            reachable: self.last_fetch.is_some() && self.last_error.is_none(),
+            last_fetch: self.last_fetch,
+            last_success: self.last_success,
+            last_error: self.last_error.clone(),
+            schema_hash: self.schema_hash.clone(),
+            active_subscriptions: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::time::Duration;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Server};
+
+    use crate::{Context, GatewayServer, RemoteGraphQLDataSource, RequestData};
+
+    struct LocalSource {
+        address: String,
+    }
+
+    impl RemoteGraphQLDataSource for LocalSource {
+        fn name(&self) -> &str {
+            "hello"
+        }
+        fn address(&self) -> &str {
+            &self.address
+        }
+    }
+
+    /// Serves the SDL of a subgraph, the gateway only sends `_service` queries while it is not used.
+    fn serve_subgraph() -> String {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_| async {
+                let body = serde_json::json!({ "data": { "_service": { "sdl": "type Query { hello: String }" } } });
+                Ok::<_, Infallible>(hyper::Response::new(Body::from(body.to_string())))
+            }))
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_service));
+        address
+    }
+
+    #[tokio::test]
+    async fn ready_once_the_schema_is_composed() {
+        let server = GatewayServer::builder()
+            .with_source(LocalSource { address: serve_subgraph() })
+            .build();
+        let status = server.status().await;
+        assert!(!status.ready);
+        assert!(!server.is_ready().await);
+        let resp = server.execute(RequestData::new("{ hello }"), Context::new(Default::default())).await;
+        assert_eq!(resp.errors[0].message, "Not ready.");
+
+        // The first poll of the subgraphs happens 3 seconds after the gateway has been built.
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while !server.is_ready().await {
+            assert!(tokio::time::Instant::now() < deadline, "schema has not been composed");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let status = serde_json::to_value(server.status().await).unwrap();
+        assert_eq!(status["ready"], true);
+        assert_eq!(status["activeSubscriptions"], 0);
+        assert_eq!(status["lastError"], serde_json::Value::Null);
+        assert!(status["schemaHash"].is_string());
+        let source = &status["sources"][0];
+        assert_eq!(source["name"], "hello");
+        assert_eq!(source["reachable"], true);
+        assert_eq!(source["activeSubscriptions"], 0);
+        assert!(source["schemaHash"].is_string());
+        assert_ne!(source["schemaHash"], status["schemaHash"]);
+        assert!(source["lastSuccess"].is_string());
+    }
+}
@@ -5,7 +5,7 @@ use std::sync::Arc;
 use anyhow::Result;
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
-use crate::planner::{RequestData, Response};
+use crate::planner::{Request, RequestData, Response};
 use http::{HeaderMap, Request as HttpRequest};
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, oneshot};
@@ -18,6 +18,7 @@ use crate::datasource::{Context, RemoteGraphQLDataSource, GraphqlSourceMiddlewar
 use super::grouped_stream::{GroupedStream, StreamEvent};
 use super::protocol::{ClientMessage, Protocols, ServerMessage};
 use crate::ServiceRouteTable;
+use super::super::propagation;
 
 const CONNECT_TIMEOUT_SECONDS: u64 = 5;
 
@@ -28,6 +29,8 @@ struct SubscribeCommand {
     payload: RequestData,
     tx: mpsc::UnboundedSender<Response>,
     reply: oneshot::Sender<Result<()>>,
+    /// Trace context of the subscription, injected into the handshake of a new upstream connection.
+    trace_headers: HashMap<String, String>,
 }
 
 struct StopCommand {
@@ -35,7 +38,7 @@ struct StopCommand {
 }
 
 enum Command {
-    Subscribe(SubscribeCommand),
+    Subscribe(Box<SubscribeCommand>),
     Stop(StopCommand),
 }
 
@@ -73,15 +76,18 @@ impl WebSocketController {
         tx: mpsc::UnboundedSender<Response>,
     ) -> Result<()> {
         let (tx_reply, rx_reply) = oneshot::channel();
+        let mut trace_headers = HashMap::new();
+        propagation::inject(&opentelemetry::Context::current(), &mut trace_headers);
         if self
             .tx_command
-            .send(Command::Subscribe(SubscribeCommand {
+            .send(Command::Subscribe(Box::new(SubscribeCommand {
                 service: service.into(),
                 id: id.into(),
                 payload: request,
                 tx,
                 reply: tx_reply,
-            }))
+                trace_headers,
+            })))
             .is_err()
         {
             return Err(anyhow::anyhow!("Connection closed."));
@@ -139,29 +145,41 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> WebSocketContext<S> {
 
     async fn handle_command(&mut self, command: Command) {
         match command {
-            Command::Subscribe(command) => self.handle_command_subscribe(command).await,
+            Command::Subscribe(command) => self.handle_command_subscribe(*command).await,
             Command::Stop(command) => self.handle_command_stop(command).await,
         }
     }
 
+    /// Let the source modify the subscription request before it is sent.
+    async fn prepare_request(
+        &self,
+        service: &str,
+        payload: RequestData,
+        trace_headers: HashMap<String, String>,
+    ) -> Result<Request> {
+        let source = self.route_table.get(service).ok_or_else(|| {
+            anyhow::anyhow!("Service '{}' is not defined in the routing table.", service)
+        })?;
+        let mut request = Request::new(payload);
+        request.headers.extend(trace_headers);
+        source.will_send_request(&mut request, &self.ctx).await?;
+        Ok(request)
+    }
+
     async fn ensure_upstream(
         &mut self,
         service: &str,
+        request: &Request,
     ) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Protocols)> {
         const PROTOCOLS: &str = "graphql-ws, graphql-transport-ws";
         let source = self.route_table.get(service).ok_or_else(|| {
             anyhow::anyhow!("Service '{}' is not defined in the routing table.", service)
         })?;
 
-        let url = source.url_subscription();
-
-        let mut headers = HashMap::new();
-
-        source.will_send_request(&mut headers, &self.ctx).await?;
-
         source.on_connection_init(&mut self.init_payload, &self.ctx).await?;
 
-        let headers = HeaderMap::try_from(&headers)?;
+        let url = request.subscription_url.clone().unwrap_or_else(|| source.url_subscription());
+        let headers = HeaderMap::try_from(&request.headers)?;
 
         tracing::debug!(url = %url, service = service, "Connect to upstream websocket");
         let mut http_request = HttpRequest::builder()
@@ -217,8 +235,15 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> WebSocketContext<S> {
     }
 
     async fn handle_command_subscribe(&mut self, command: SubscribeCommand) {
+        let request = match self.prepare_request(&command.service, command.payload, command.trace_headers).await {
+            Ok(request) => request,
+            Err(err) => {
+                command.reply.send(Err(err)).ok();
+                return;
+            }
+        };
         if !self.upstream.contains_key(&command.service) {
-            let (stream, protocol) = match self.ensure_upstream(&command.service).await {
+            let (stream, protocol) = match self.ensure_upstream(&command.service, &request).await {
                 Ok(stream) => stream,
                 Err(err) => {
                     command.reply.send(Err(err)).ok();
@@ -261,7 +286,7 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> WebSocketContext<S> {
                     serde_json::to_string(
                         &info
                             .protocol
-                            .subscribe_message(&command.id, command.payload),
+                            .subscribe_message(&command.id, request.data),
                     )
                     .unwrap(),
                 ))
@@ -3,7 +3,6 @@ use std::collections::HashMap;
 use std::hash::Hash;
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use actix::Message;
 
 use futures_util::stream::Stream;
 use futures_util::task::AtomicWaker;
@@ -56,7 +55,8 @@ pub enum StreamEvent<K, T> {
     Complete(K),
 }
 
-impl<K, T> Message for StreamEvent<K, T> {
+#[cfg(feature = "actix")]
+impl<K, T> actix::Message for StreamEvent<K, T> {
     type Result = ();
 }
 
@@ -1,8 +1,12 @@
 mod controller;
 mod protocol;
+mod session;
+#[cfg(feature = "actix")]
 mod subscription;
 mod grouped_stream;
 
 pub use controller::WebSocketController;
 pub use protocol::Protocols;
-pub use subscription::Subscription;
\ No newline at end of file
+pub use session::serve_websocket;
+#[cfg(feature = "actix")]
+pub use subscription::Subscription;
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use futures_util::stream::BoxStream;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+
+use crate::datasource::{Context, GraphqlSourceMiddleware, RemoteGraphQLDataSource};
+use crate::planner::Response;
+use super::super::shared_route_table::subscription_stream;
+use super::super::{ExecuteOptions, SharedRouteTable};
+use super::grouped_stream::{GroupedStream, StreamEvent};
+use super::protocol::{ClientMessage, ConnectionError, Protocols, ServerMessage};
+use super::WebSocketController;
+
+/// Serve GraphQL subscriptions over a client websocket connection of any HTTP framework.
+///
+/// `incoming` yields text messages of the client and the messages of the gateway are sent to `outgoing`.
+/// Returns when the client has gone or the protocol requires to close the connection.
+pub async fn serve_websocket<S, I, O>(
+    table: SharedRouteTable<S>,
+    ctx: Arc<Context>,
+    options: Arc<ExecuteOptions>,
+    protocol: Protocols,
+    mut incoming: I,
+    mut outgoing: O,
+) where
+    S: RemoteGraphQLDataSource + GraphqlSourceMiddleware,
+    I: Stream<Item = String> + Unpin,
+    O: Sink<String> + Unpin,
+{
+    let (composed_schema, route_table) = match table.get().await {
+        Some(value) => value,
+        None => return,
+    };
+    let mut controller: Option<WebSocketController> = None;
+    let mut streams = GroupedStream::<String, BoxStream<'static, Response>>::default();
+
+    loop {
+        tokio::select! {
+            message = incoming.next() => {
+                let text = match message {
+                    Some(text) => text,
+                    None => return,
+                };
+                let message = match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+                match message {
+                    ClientMessage::ConnectionInit { payload } if controller.is_none() => {
+                        controller = Some(WebSocketController::new(route_table.clone(), payload, Arc::clone(&ctx)));
+                        if send(&mut outgoing, &ServerMessage::ConnectionAck).await.is_err() {
+                            return;
+                        }
+                    }
+                    ClientMessage::ConnectionInit { .. } => {
+                        if protocol == Protocols::SubscriptionsTransportWS {
+                            let message = ServerMessage::ConnectionError {
+                                payload: ConnectionError {
+                                    message: "Too many initialisation requests.",
+                                },
+                            };
+                            send(&mut outgoing, &message).await.ok();
+                        }
+                        return;
+                    }
+                    ClientMessage::Start { id, payload } | ClientMessage::Subscribe { id, payload } => {
+                        let controller = controller
+                            .get_or_insert_with(|| WebSocketController::new(route_table.clone(), None, Arc::clone(&ctx)))
+                            .clone();
+                        let stream = subscription_stream(
+                            composed_schema.clone(),
+                            controller,
+                            id.to_string(),
+                            payload,
+                            Arc::clone(&options),
+                            table.metrics(),
+                            Arc::clone(&ctx),
+                        );
+                        streams.insert(id.to_string(), stream);
+                    }
+                    ClientMessage::Stop { id } | ClientMessage::Complete { id } => {
+                        streams.remove(id);
+                        if let Some(controller) = &controller {
+                            controller.stop(id).await;
+                        }
+                    }
+                    ClientMessage::ConnectionTerminate => return,
+                }
+            }
+            Some(event) = streams.next() => {
+                let sent = match event {
+                    StreamEvent::Data(id, resp) => send(&mut outgoing, &protocol.next_message(&id, resp)).await,
+                    StreamEvent::Complete(id) => send(&mut outgoing, &ServerMessage::Complete { id: &id }).await,
+                };
+                if sent.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn send<O: Sink<String> + Unpin>(outgoing: &mut O, message: &ServerMessage<'_>) -> Result<(), ()> {
+    let text = serde_json::to_string(message).map_err(|_| ())?;
+    outgoing.send(text).await.map_err(|_| ())
+}
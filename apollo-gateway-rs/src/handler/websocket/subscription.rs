@@ -4,8 +4,10 @@ use actix::{Actor, AsyncContext, ActorContext, Handler, StreamHandler};
 use crate::schema::ComposedSchema;
 use actix_web_actors::ws;
 use actix_web_actors::ws::{CloseCode, CloseReason, Message, ProtocolError};
-use crate::planner::{Response, ServerError};
 use crate::{RemoteGraphQLDataSource, Context, ServiceRouteTable, GraphqlSourceMiddleware};
+use super::super::metrics::Metrics;
+use super::super::ExecuteOptions;
+use super::super::shared_route_table::subscription_stream;
 use super::protocol::{ClientMessage, ConnectionError, ServerMessage};
 use super::{Protocols, WebSocketController, grouped_stream::StreamEvent};
 
@@ -15,10 +17,12 @@ pub struct Subscription<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> {
     context: Arc<Context>,
     controller: Option<WebSocketController>,
     protocol: Protocols,
+    options: Arc<ExecuteOptions>,
+    metrics: Arc<Metrics>,
 }
 
 impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> Subscription<S> {
-    pub fn new(schema: Arc<ComposedSchema>, route_table: Arc<ServiceRouteTable<S>>, context: Arc<Context>, protocol: Protocols) -> Self {
+    pub fn new(schema: Arc<ComposedSchema>, route_table: Arc<ServiceRouteTable<S>>, context: Arc<Context>, protocol: Protocols, options: Arc<ExecuteOptions>, metrics: Arc<Metrics>) -> Self {
         let controller = None;
         Self {
             schema,
@@ -26,6 +30,8 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> Subscription<S> {
             context,
             controller,
             protocol,
+            options,
+            metrics,
         }
     }
 }
@@ -88,56 +94,23 @@ impl<S: RemoteGraphQLDataSource + GraphqlSourceMiddleware> StreamHandler<Result<
                         let table = self.route_table.clone();
                         let context = Arc::clone(&self.context);
                         let controller = self.controller.get_or_insert_with(|| WebSocketController::new(table, None, context)).clone();
-                        let document = match parser::parse_query(&payload.query) {
-                            Ok(document) => document,
-                            Err(err) => {
-                                let resp = Response {
-                                    data: None,
-                                    errors: vec![ServerError::new(err.to_string())],
-                                    extensions: Default::default(),
-                                    headers: Default::default()
-                                };
-                                let data = ServerMessage::Data { id, payload: resp };
-                                match serde_json::to_string(&data) {
-                                    Ok(m) => ctx.text(m),
-                                    Err(e) => ctx.text(e.to_string())
-                                };
-                                let complete = ServerMessage::Complete { id };
-                                match serde_json::to_string(&complete) {
-                                    Ok(m) => ctx.text(m),
-                                    Err(e) => ctx.text(e.to_string())
-                                };
-                                ctx.stop();
-                                return;
-                            }
-                        };
                         let id = Arc::new(id.to_string());
-                        let schema = self.schema.clone();
-                        let stream = {
-                            let id = id;
-                            use crate::planner::PlanBuilder;
-                            use super::super::executor::Executor;
-                            async_stream::stream! {
-                                let mut builder = PlanBuilder::new(&schema, document).variables(payload.variables);
-                                if let Some(operation) = payload.operation {
-                                   builder = builder.operation_name(operation);
-                                }
-                                let node = match builder.plan() {
-                                    Ok(node) => node,
-                                    Err(resp) => {
-                                        yield StreamEvent::Data(Arc::clone(&id), resp);
-                                        yield StreamEvent::Complete(id);
-                                        return;
-                                    }
-                                };
-                                let executor = Executor::new(&schema);
-                                let mut stream = executor.execute_stream(controller.clone(), &id, &node).await;
-                                use futures_util::StreamExt;
-                                while let Some(item) = stream.next().await {
-                                    yield StreamEvent::Data(Arc::clone(&id), item);
-                                }
-                                yield StreamEvent::Complete(id);
+                        let events = subscription_stream(
+                            self.schema.clone(),
+                            controller,
+                            id.to_string(),
+                            payload,
+                            Arc::clone(&self.options),
+                            Arc::clone(&self.metrics),
+                            Arc::clone(&self.context),
+                        );
+                        let stream = async_stream::stream! {
+                            use futures_util::StreamExt;
+                            let mut events = events;
+                            while let Some(resp) = events.next().await {
+                                yield StreamEvent::Data(Arc::clone(&id), resp);
                             }
+                            yield StreamEvent::Complete(id);
                         };
                         ctx.add_message_stream(stream);
                     }
@@ -0,0 +1,259 @@
+//! A [tower](https://github.com/tower-rs/tower) service for [hyper](https://hyper.rs).
+//!
+//! Operations are read from `POST` bodies and from the query string of `GET` requests,
+//! `GET` requests with a websocket upgrade start a subscription connection.
+//! `GET /health`, `/ready` and `/status` are answered like the handlers of the other adapters.
+//!
+//! ```rust,ignore
+//! use hyper::service::make_service_fn;
+//! use apollo_gateway_rs::hyper::GatewayService;
+//!
+//! let service = GatewayService::new(gateway_server);
+//! let make_service = make_service_fn(move |_| {
+//!     let service = service.clone();
+//!     async move { Ok::<_, std::convert::Infallible>(service) }
+//! });
+//! hyper::Server::bind(&addr).serve(make_service).await?;
+//! ```
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+
+use futures_util::{future, SinkExt, StreamExt};
+use http::header::{CONNECTION, CONTENT_TYPE, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, UPGRADE};
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use hyper::upgrade::Upgraded;
+use hyper::Body;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::{Context, GatewayServer, Protocols, RequestData, Response};
+
+/// Serves GraphQL requests of `POST` bodies and `GET` query strings, and subscriptions over websockets.
+///
+/// The peer address is taken from a `SocketAddr` extension of the request, if there is one.
+#[derive(Clone)]
+pub struct GatewayService {
+    server: Arc<GatewayServer>,
+}
+
+impl GatewayService {
+    pub fn new(server: GatewayServer) -> Self {
+        Self { server: Arc::new(server) }
+    }
+
+    pub fn from_arc(server: Arc<GatewayServer>) -> Self {
+        Self { server }
+    }
+}
+
+impl tower::Service<http::Request<Body>> for GatewayService {
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+        let server = self.server.clone();
+        Box::pin(async move {
+            if req.method() == Method::GET {
+                if is_websocket_upgrade(req.headers()) {
+                    return Ok(upgrade(server, req));
+                }
+                match req.uri().path() {
+                    "/health" => return Ok(json_response(StatusCode::OK, &serde_json::json!({ "status": "ok" }))),
+                    "/ready" => {
+                        let status = server.status().await;
+                        let status_code = if status.ready {
+                            StatusCode::OK
+                        } else {
+                            StatusCode::SERVICE_UNAVAILABLE
+                        };
+                        return Ok(json_response(status_code, &status));
+                    }
+                    "/status" => return Ok(json_response(StatusCode::OK, &server.status().await)),
+                    _ => {}
+                }
+            }
+            let (parts, body) = req.into_parts();
+            let peer_addr = parts.extensions.get::<SocketAddr>().copied();
+            let request = if parts.method == Method::GET {
+                RequestData::from_query_string(parts.uri.query().unwrap_or_default()).map_err(|err| err.to_string())
+            } else {
+                match hyper::body::to_bytes(body).await {
+                    Ok(bytes) => serde_json::from_slice::<RequestData>(&bytes).map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                }
+            };
+            let resp = match request {
+                Ok(request) => {
+                    let ctx = Context::new(parts.headers).with_peer_addr(peer_addr);
+                    server.execute(request, ctx).await
+                }
+                Err(err) => Response::error(err),
+            };
+            Ok(into_http_response(resp))
+        })
+    }
+}
+
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    headers
+        .get(UPGRADE)
+        .and_then(|header| header.to_str().ok())
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+}
+
+/// Accept the websocket handshake, subscriptions are served once hyper has upgraded the connection.
+fn upgrade(server: Arc<GatewayServer>, mut req: http::Request<Body>) -> http::Response<Body> {
+    let accept = match req.headers().get(SEC_WEBSOCKET_KEY) {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => return into_http_response(Response::error("Missing sec-websocket-key header.")),
+    };
+    let protocol = req
+        .headers()
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|protocols| {
+            protocols.split(',').find_map(|p| Protocols::from_str(p.trim()).ok())
+        })
+        .unwrap_or(Protocols::SubscriptionsTransportWS);
+    let peer_addr = req.extensions().get::<SocketAddr>().copied();
+    let ctx = Arc::new(Context::new(req.headers().clone()).with_peer_addr(peer_addr));
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                let socket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                serve(server, ctx, protocol, socket).await;
+            }
+            Err(err) => tracing::error!(error = %err, "Failed to upgrade the connection to a websocket."),
+        }
+    });
+
+    let mut resp = http::Response::new(Body::empty());
+    *resp.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = resp.headers_mut();
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    if let Ok(accept) = HeaderValue::from_str(&accept) {
+        headers.insert(SEC_WEBSOCKET_ACCEPT, accept);
+    }
+    if let Ok(protocol) = HeaderValue::from_str(protocol.sec_websocket_protocol()) {
+        headers.insert(SEC_WEBSOCKET_PROTOCOL, protocol);
+    }
+    resp
+}
+
+async fn serve(server: Arc<GatewayServer>, ctx: Arc<Context>, protocol: Protocols, socket: WebSocketStream<Upgraded>) {
+    let (sink, stream) = socket.split();
+    let incoming = stream
+        .take_while(|message| future::ready(message.is_ok()))
+        .filter_map(|message| async move {
+            match message {
+                Ok(Message::Text(text)) => Some(text),
+                _ => None,
+            }
+        });
+    let outgoing = sink.with(|text: String| future::ok::<_, WsError>(Message::Text(text)));
+    futures_util::pin_mut!(incoming, outgoing);
+    server.serve_websocket(ctx, protocol, incoming, outgoing).await;
+}
+
+fn json_response(status: StatusCode, body: &impl serde::Serialize) -> http::Response<Body> {
+    let mut resp = match serde_json::to_vec(body) {
+        Ok(body) => http::Response::new(Body::from(body)),
+        Err(err) => {
+            let mut internal = http::Response::new(Body::from(err.to_string()));
+            *internal.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return internal;
+        }
+    };
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp
+}
+
+/// Convert a response of the gateway into a hyper response.
+pub fn into_http_response(resp: Response) -> http::Response<Body> {
+    let status = if resp.is_request_error() {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::OK
+    };
+    let mut http_resp = json_response(status, &resp);
+    let headers = http_resp.headers_mut();
+    for (name, value) in &resp.headers {
+        headers.append(name, value.clone());
+    }
+    http_resp
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::service::make_service_fn;
+    use serde_json::{json, Value};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    use super::*;
+    use crate::testing::MockSource;
+
+    async fn server() -> GatewayServer {
+        let accounts = MockSource::new("accounts", "type Query { me: User } type User { id: ID! }")
+            .query_value("me", json!({ "id": "1" }));
+        let server = GatewayServer::builder().with_middleware_source(accounts).build();
+        server.update_schema().await.expect("composed schema");
+        server
+    }
+
+    #[tokio::test]
+    async fn route_requests() {
+        let service = GatewayService::new(server().await);
+        let make_service = make_service_fn(move |_| {
+            let service = service.clone();
+            async move { Ok::<_, Infallible>(service) }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(hyper::Server::from_tcp(listener).unwrap().serve(make_service));
+        let client = reqwest::Client::new();
+
+        let resp = client.post(format!("http://{addr}/")).json(&json!({ "query": "{ me { id } }" })).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body, json!({ "data": { "me": { "id": "1" } } }));
+
+        let resp = client.get(format!("http://{addr}/")).query(&[("query", "{ me { id } }")]).send().await.unwrap();
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body, json!({ "data": { "me": { "id": "1" } } }));
+
+        let resp = client.get(format!("http://{addr}/")).query(&[("query", "mutation { logout }")]).send().await.unwrap();
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["errors"][0]["message"], "Mutations can only be sent with POST.");
+
+        let resp = client.get(format!("http://{addr}/health")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let resp = client.get(format!("http://{addr}/ready")).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: Value = client.get(format!("http://{addr}/status")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(body["ready"], true);
+
+        let mut req = format!("ws://{addr}/").into_client_request().unwrap();
+        req.headers_mut().insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static("graphql-transport-ws"));
+        let (mut socket, resp) = tokio_tungstenite::connect_async(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(resp.headers()[SEC_WEBSOCKET_PROTOCOL], "graphql-transport-ws");
+        socket.send(Message::Text(json!({ "type": "connection_init" }).to_string())).await.unwrap();
+        let ack = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&ack).unwrap(), json!({ "type": "connection_ack" }));
+    }
+}
@@ -3,27 +3,77 @@
 #[forbid(clippy::unnecessary_unwrap)]
 #[forbid(clippy::unwrap_in_result)]
 mod datasource;
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(feature = "hyper")]
+pub mod hyper;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+mod cache;
 mod handler;
 mod planner;
+mod plugin;
 mod schema;
+mod service;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod usage;
 mod validation;
 
 use std::cell::Cell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
 use std::marker::PhantomData;
 use std::sync::Arc;
+use futures_util::stream::BoxStream;
+use futures_util::{Sink, Stream};
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::trace::{FutureExt, TraceContextExt, Tracer};
 use serde::Deserialize;
+use tower::util::BoxCloneService;
+use tower::{Layer, Service};
+use crate::handler::constants::{KEY_QUERY, KEY_VARIABLES};
 pub use crate::datasource::{RemoteGraphQLDataSource, Context, GraphqlSourceMiddleware, DefaultSource};
 use crate::datasource::{Config, GraphqlSource, SimpleSource, Source};
-pub use crate::planner::{Response, Request};
-use crate::handler::{ServiceRouteTable, SharedRouteTable};
+pub use crate::planner::{Response, Request, RequestData, ServerError};
+pub use crate::planner::{ChosenAlternative, CostModel, PlanAlternatives, PlanCost};
+pub use crate::planner::{
+    FetchNode, FlattenBatchNode, FlattenNode, IntrospectionNode, ParallelNode, PathSegment, PlanNode, ResponsePath, RootNode,
+    SequenceNode, SubscribeNode,
+};
+pub use crate::plugin::Plugin;
+pub use parser::types::ExecutableDocument;
+pub use value::{ConstValue, Variables};
+use crate::handler::{ExecuteOptions, Metrics, ServiceRouteTable, SharedRouteTable};
+pub use crate::handler::{
+    AccessLog, FederatedTracing, GatewayStatus, HeaderMergePolicy, HeaderMergeStrategy, Protocols, SourceStatus,
+};
+pub use crate::cache::{CacheEntry, CacheScope, CacheStorage, EntityCache, InMemoryCacheStorage, ResponseCache};
+pub use crate::service::{BoxError, OperationRequest, OperationService, SubgraphRequest, SubgraphService};
+use crate::service::{LayerStack, SharedService};
+pub use crate::usage::{aggregate_clients, ClientInfo, ClientUsage, JsonFileSink, UsageRecord, UsageReporting, UsageSink};
 
 #[derive(Default)]
 pub struct GatewayServerBuilder {
     table: HashMap<String, Arc<dyn GraphqlSource>>,
     limit: Option<usize>,
+    header_policy: HeaderMergePolicy,
+    response_cache: Option<ResponseCache>,
+    entity_cache: Option<EntityCache>,
+    operation_layers: LayerStack<OperationRequest>,
+    subgraph_layers: LayerStack<SubgraphRequest>,
+    plugins: Vec<Arc<dyn Plugin>>,
+    federated_tracing: Option<FederatedTracing>,
+    access_log: Option<AccessLog>,
+    usage_reporting: Option<UsageReporting>,
+    query_plan_header: bool,
+    cost_model: CostModel,
+    abort_mutations_on_error: bool,
+    metric_operation_names: HashSet<String>,
+    metrics: Option<Metrics>,
     // Compile time check, because someone can don't use build() and push Data<GatewayServerBuilder> instead of Data<GatewayServer> to state of app
     _marker: PhantomData<Cell<()>>,
 }
@@ -33,6 +83,129 @@ impl GatewayServerBuilder {
         self.limit = Some(limit);
         self
     }
+    /// Define how headers of subgraph responses are merged into the client response.
+    /// By default no subgraph header is sent to the client.
+    /// ```rust
+    /// use apollo_gateway_rs::{GatewayServer, HeaderMergePolicy, HeaderMergeStrategy};
+    /// let builder = GatewayServer::builder()
+    ///     .with_header_merge_policy(HeaderMergePolicy::default()
+    ///         .header("set-cookie", HeaderMergeStrategy::Last)
+    ///         .header("vary", HeaderMergeStrategy::Append));
+    /// ```
+    pub fn with_header_merge_policy(mut self, policy: HeaderMergePolicy) -> GatewayServerBuilder {
+        self.header_policy = policy;
+        self
+    }
+    /// Cache full responses of query operations, see `ResponseCache`
+    /// ```rust
+    /// use apollo_gateway_rs::{GatewayServer, ResponseCache};
+    /// let builder = GatewayServer::builder()
+    ///     .with_response_cache(ResponseCache::in_memory(1024).private_id_header("authorization"));
+    /// ```
+    pub fn with_response_cache(mut self, cache: ResponseCache) -> GatewayServerBuilder {
+        self.response_cache = Some(cache);
+        self
+    }
+    /// Cache results of `_entities` fetches per representation, see `EntityCache`.
+    /// Sources without `entity_cache_max_age` and entity types without a `@cacheControl` hint
+    /// use the default max age of the cache, entities are not cached if there is none.
+    /// ```rust
+    /// use apollo_gateway_rs::{EntityCache, GatewayServer};
+    /// let builder = GatewayServer::builder()
+    ///     .with_entity_cache(EntityCache::in_memory(4096).service_max_age("products", 60));
+    /// ```
+    pub fn with_entity_cache(mut self, cache: EntityCache) -> GatewayServerBuilder {
+        self.entity_cache = Some(cache);
+        self
+    }
+    /// Wrap the execution of client operations with a `tower::Layer`, for example a timeout or a rate limit.
+    /// Layers are applied in the order they were added, the first one is the outermost.
+    /// Errors of layers are returned to the client as a request error. Subscriptions are not affected.
+    /// ```rust
+    /// use std::time::Duration;
+    /// use apollo_gateway_rs::GatewayServer;
+    /// let builder = GatewayServer::builder()
+    ///     .with_operation_layer(tower::timeout::TimeoutLayer::new(Duration::from_secs(10)));
+    /// ```
+    pub fn with_operation_layer<L>(mut self, layer: L) -> GatewayServerBuilder
+        where L: Layer<OperationService> + Send + Sync + 'static,
+              L::Service: Service<OperationRequest, Response=Response, Error=BoxError> + Clone + Send + 'static,
+              <L::Service as Service<OperationRequest>>::Future: Send + 'static {
+        self.operation_layers.push(layer);
+        self
+    }
+    /// Wrap every request to a subgraph with a `tower::Layer`, it is called after `GraphqlSourceMiddleware::will_send_request`
+    /// and before `GraphqlSourceMiddleware::did_receive_response`. Errors of layers become errors of the fetch.
+    /// Layers are applied in the order they were added, the first one is the outermost.
+    /// ```rust
+    /// use apollo_gateway_rs::GatewayServer;
+    /// let builder = GatewayServer::builder()
+    ///     .with_subgraph_layer(tower::limit::ConcurrencyLimitLayer::new(64));
+    /// ```
+    pub fn with_subgraph_layer<L>(mut self, layer: L) -> GatewayServerBuilder
+        where L: Layer<SubgraphService> + Send + Sync + 'static,
+              L::Service: Service<SubgraphRequest, Response=Response, Error=BoxError> + Clone + Send + 'static,
+              <L::Service as Service<SubgraphRequest>>::Future: Send + 'static {
+        self.subgraph_layers.push(layer);
+        self
+    }
+    /// Register a plugin, plugins are called in the order they were registered. See `Plugin`.
+    pub fn with_plugin<P: Plugin>(mut self, plugin: P) -> GatewayServerBuilder {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+    /// Ask subgraphs for federated traces (ftv1) of their part of an operation.
+    /// The resolvers of every trace become child spans of the fetch span,
+    /// with `expose_in_extensions` the traces are also returned in `extensions.federatedTrace` of the response.
+    pub fn with_federated_tracing(mut self, federated_tracing: FederatedTracing) -> GatewayServerBuilder {
+        self.federated_tracing = Some(federated_tracing);
+        self
+    }
+    /// Emit one structured log record per query or mutation with `tracing`, see `AccessLog`.
+    /// ```rust
+    /// use apollo_gateway_rs::{AccessLog, GatewayServer};
+    /// let builder = GatewayServer::builder()
+    ///     .with_access_log(AccessLog::new().redact_variable("password"));
+    /// ```
+    pub fn with_access_log(mut self, access_log: AccessLog) -> GatewayServerBuilder {
+        self.access_log = Some(access_log);
+        self
+    }
+    /// Collect which fields of the schema are used by which clients, see `UsageReporting`.
+    pub fn with_usage_reporting(mut self, usage_reporting: UsageReporting) -> GatewayServerBuilder {
+        self.usage_reporting = Some(usage_reporting);
+        self
+    }
+    /// Allow clients to ask for the query plan of their operation with the `apollo-query-plan-experimental` header.
+    /// The plan is returned in `extensions.queryPlan`, with the header value `plan-only` the operation is not executed.
+    /// Plans reveal the structure of your subgraphs, only enable it for trusted clients.
+    pub fn with_query_plan_header(mut self, enabled: bool) -> GatewayServerBuilder {
+        self.query_plan_header = enabled;
+        self
+    }
+    /// Weights of the cost model which picks the services of `@shareable` fields,
+    /// the latencies of the services are measured by the gateway. See `CostModel`.
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> GatewayServerBuilder {
+        self.cost_model = cost_model;
+        self
+    }
+    /// Root fields of a mutation always run one after the other in document order.
+    /// When enabled, the fields after the first failed one are not executed and get an error instead.
+    pub fn with_abort_mutations_on_error(mut self, enabled: bool) -> GatewayServerBuilder {
+        self.abort_mutations_on_error = enabled;
+        self
+    }
+    /// Operation names recorded in the `operation_name` label of the operation metrics.
+    /// Clients choose the names of their operations, so other names are recorded as `other` to keep the number of series bounded.
+    pub fn with_metric_operation_names<N: Into<String>>(mut self, names: impl IntoIterator<Item=N>) -> GatewayServerBuilder {
+        self.metric_operation_names.extend(names.into_iter().map(Into::into));
+        self
+    }
+    /// Create the instruments of the gateway from this meter provider instead of the global one.
+    pub fn with_meter_provider(mut self, provider: &impl MeterProvider) -> GatewayServerBuilder {
+        self.metrics = Some(Metrics::new(provider));
+        self
+    }
     /// Append sources. Make sure that all sources have unique name
     pub fn with_sources<S: RemoteGraphQLDataSource>(mut self, sources: impl Iterator<Item=S>) -> GatewayServerBuilder {
         let sources = sources
@@ -109,19 +282,54 @@ impl GatewayServerBuilder {
     }
 
     /// Build a Gateway-Server. After building gateway-server will try to parse a schema from your remote sources.
-    pub fn build(self) -> GatewayServer {
-        let table = ServiceRouteTable::from(self.table);
-        let shared_route_table = SharedRouteTable::default();
-        shared_route_table.set_route_table(table);
+    pub fn build(mut self) -> GatewayServer {
+        if let Some(entity_cache) = &mut self.entity_cache {
+            for source in self.table.values() {
+                if let Some(max_age) = source.entity_cache_max_age() {
+                    entity_cache.set_service_max_age_if_absent(source.name(), max_age);
+                }
+            }
+        }
+        let table = ServiceRouteTable::from(self.table).with_layers(&self.subgraph_layers);
+        let metrics = self.metrics.unwrap_or_else(|| Metrics::new(&opentelemetry::global::meter_provider()));
+        let shared_route_table = SharedRouteTable::new(table, metrics);
+        let options = Arc::new(ExecuteOptions {
+            limit: self.limit,
+            header_policy: self.header_policy,
+            response_cache: self.response_cache,
+            entity_cache: self.entity_cache,
+            plugins: self.plugins.into(),
+            federated_tracing: self.federated_tracing,
+            access_log: self.access_log,
+            usage_reporting: self.usage_reporting,
+            query_plan_header: self.query_plan_header,
+            cost_model: self.cost_model,
+            abort_mutations_on_error: self.abort_mutations_on_error,
+            metric_operation_names: self.metric_operation_names,
+        });
+        if let Some(usage_reporting) = &options.usage_reporting {
+            usage_reporting.spawn_flush_loop();
+        }
+        let service = (!self.operation_layers.is_empty()).then(|| {
+            let table = shared_route_table.clone();
+            let options = options.clone();
+            let execute = tower::service_fn(move |request: OperationRequest| {
+                let table = table.clone();
+                let options = options.clone();
+                async move { Ok::<_, BoxError>(table.execute(request.request, request.context, &options).await) }
+            });
+            SharedService::new(self.operation_layers.apply(BoxCloneService::new(execute)))
+        });
         GatewayServer {
             table: shared_route_table,
-            limit: self.limit
+            options,
+            service,
         }
     }
 }
 
 /// Gateway-server will parse a schema from your remote sources, fetch request and make subscription. Don't forget to pass it into app_data. See example:
-/// ```rust
+/// ```rust,ignore
 /// async fn main() -> std::io::Result<()> {
 ///     use actix_web::{App, HttpServer, web::Data};
 ///     use apollo_gateway_rs::GatewayServer;
@@ -140,7 +348,8 @@ impl GatewayServerBuilder {
 /// ```
 pub struct GatewayServer {
     table: SharedRouteTable<Arc<dyn GraphqlSource>>,
-    limit: Option<usize>
+    options: Arc<ExecuteOptions>,
+    service: Option<SharedService<OperationRequest>>,
 }
 
 impl GatewayServer {
@@ -148,67 +357,84 @@ impl GatewayServer {
     pub fn builder() -> GatewayServerBuilder {
         GatewayServerBuilder::default()
     }
-}
 
-pub mod actix {
-    use std::str::FromStr;
-    use std::sync::Arc;
-    use actix_web::http::header::SEC_WEBSOCKET_PROTOCOL;
-    use actix_web::HttpResponse;
-    use k8s_openapi::serde_json;
-    use opentelemetry::trace::{FutureExt, TraceContextExt, Tracer};
-    use crate::{Context, GatewayServer};
-    use crate::handler::constants::{KEY_QUERY, KEY_VARIABLES};
-    use crate::handler::{Protocols, Subscription};
-    use crate::planner::RequestData;
+    /// Entity cache of the server, use it to invalidate entities after they have been changed.
+    pub fn entity_cache(&self) -> Option<&EntityCache> {
+        self.options.entity_cache.as_ref()
+    }
+
+    /// Field usage statistics, e.g. to ask which clients still use a deprecated field.
+    /// ```rust,ignore
+    /// let clients = gateway_server
+    ///     .usage_reporting()
+    ///     .expect("usage reporting is enabled")
+    ///     .clients_using("User.legacyId", Duration::from_secs(30 * 86400))
+    ///     .await?;
+    /// ```
+    pub fn usage_reporting(&self) -> Option<&UsageReporting> {
+        self.options.usage_reporting.as_ref()
+    }
+
+    /// The query plan of an operation as JSON, the operation is validated but not executed.
+    pub async fn plan(&self, query: &str, operation_name: Option<&str>, variables: Variables) -> Result<serde_json::Value, Response> {
+        let mut request = RequestData::new(query).variables(variables);
+        if let Some(operation_name) = operation_name {
+            request = request.operation(operation_name);
+        }
+        self.table.plan(request, &self.options).await
+    }
+
+    /// Fetch the SDL of all sources and compose the schema now instead of waiting for the next poll,
+    /// e.g. in tests or to pick up a deployed subgraph immediately.
+    pub async fn update_schema(&self) -> anyhow::Result<()> {
+        self.table.refresh().await
+    }
+
+    /// Whether the schema of the subgraphs has been composed, use it for readiness probes.
+    pub async fn is_ready(&self) -> bool {
+        self.table.get().await.is_some()
+    }
+
+    /// State of the composed schema and of every subgraph as of the last schema poll.
+    pub async fn status(&self) -> GatewayStatus {
+        self.table.status().await
+    }
 
-    /// Request handler
-    pub async fn graphql_request(
-        server: actix_web::web::Data<GatewayServer>,
-        request: actix_web::web::Json<RequestData>,
-        req: actix_web::HttpRequest,
-    ) -> HttpResponse {
-        let request = request.into_inner();
-        let ctx = Context::new(req);
+    /// Execute a query or a mutation independently of the HTTP framework.
+    /// Headers for the client are returned in `Response::headers`.
+    pub async fn execute(&self, request: RequestData, ctx: Context) -> Response {
         let tracer = opentelemetry::global::tracer("graphql");
-        let query = opentelemetry::Context::current_with_span(
+        let parent = handler::propagation::extract(ctx.headers());
+        let query = parent.with_span(
             tracer
                 .span_builder("query")
                 .with_attributes(vec![
                     KEY_QUERY.string(request.query.clone()),
-                    KEY_VARIABLES.string(serde_json::to_string(&request.variables).unwrap()),
+                    KEY_VARIABLES.string(serde_json::to_string(&request.variables).unwrap_or_default()),
                 ])
-                .start(&tracer),
+                .start_with_context(&tracer, &parent),
         );
-        server.table.query(request, ctx, server.limit).with_context(query).await
+        let execute = async move {
+            match &self.service {
+                Some(service) => service
+                    .call(OperationRequest { request, context: ctx })
+                    .await
+                    .unwrap_or_else(|err| Response::error(err.to_string())),
+                None => self.table.execute(request, ctx, &self.options).await,
+            }
+        };
+        execute.with_context(query).await
     }
 
-    /// Subscription handler
-    pub async fn graphql_subscription(
-        server: actix_web::web::Data<GatewayServer>,
-        req: actix_web::HttpRequest,
-        payload: actix_web::web::Payload,
-    ) -> HttpResponse {
-        let ctx = Arc::new(Context::new(req.clone()));
-        let protocols = req.headers().get(SEC_WEBSOCKET_PROTOCOL).and_then(|header| header.to_str().ok());
-        let protocol = protocols
-            .and_then(|protocols| {
-                protocols.split(',').find_map(|p| Protocols::from_str(p.trim()).ok())
-            })
-            .unwrap_or(Protocols::SubscriptionsTransportWS);
-        if let Some((composed_schema, route_table)) = server.table.get().await {
-            let protocols = [protocol.sec_websocket_protocol()];
-            let subscription = Subscription::new(composed_schema, route_table, ctx, protocol);
-            return match actix_web_actors::ws::WsResponseBuilder::new(subscription, &req, payload)
-                .protocols(&protocols)
-                .start() {
-                Ok(r) => r,
-                Err(e) => HttpResponse::InternalServerError().body(e.to_string())
-            };
-        }
-        HttpResponse::InternalServerError().finish()
+    /// Execute a subscription independently of the HTTP framework, every event produces a response.
+    pub fn subscribe(&self, request: RequestData, ctx: Context) -> BoxStream<'static, Response> {
+        self.table.subscribe(request, Arc::new(ctx), self.options.clone())
     }
-}
-
-
 
+    /// Serve subscriptions over a websocket connection of any HTTP framework.
+    /// `incoming` yields the text messages of the client, the messages of the gateway are sent to `outgoing`.
+    pub async fn serve_websocket<I, O>(&self, ctx: Arc<Context>, protocol: Protocols, incoming: I, outgoing: O)
+        where I: Stream<Item=String> + Unpin, O: Sink<String> + Unpin {
+        handler::serve_websocket(self.table.clone(), ctx, self.options.clone(), protocol, incoming, outgoing).await
+    }
+}
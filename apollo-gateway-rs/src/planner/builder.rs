@@ -5,37 +5,78 @@ use std::collections::HashMap;
 use crate::schema::{ComposedSchema, KeyFields, MetaField, MetaType, TypeKind, ValueExt};
 use indexmap::IndexMap;
 use parser::types::{
-    BaseType, DocumentOperations, ExecutableDocument, Field, FragmentDefinition,
+    BaseType, Directive, DocumentOperations, ExecutableDocument, Field, FragmentDefinition,
     OperationDefinition, OperationType, Selection, SelectionSet, Type, VariableDefinition,
 };
 use parser::Positioned;
 use value::{ConstValue, Name, Value, Variables};
 
+use super::cost::{ChosenAlternative, CostModel, PlanAlternatives, PlanCost};
+use super::optimize::merge_entity_fetches;
 use super::plan::{
     FetchNode, FlattenNode, IntrospectionDirective, IntrospectionField, IntrospectionNode,
     IntrospectionSelectionSet, ParallelNode, PathSegment, PlanNode, ResponsePath, SequenceNode,
 };
 use super::types::{
     FetchEntity, FetchEntityGroup, FetchEntityKey, FetchQuery, FieldRef, MutationRootGroup,
-    QueryRootGroup, RequiredRef, RootGroup, SelectionRef, SelectionRefSet, VariableDefinitionsRef,
+    QueryRootGroup, RequiredField, RequiredRef, RootGroup, SelectionRef, SelectionRefSet, VariableDefinitionsRef,
     VariablesRef,
 };
-use super::{Response, RootNode, ServerError, SubscribeNode};
+use super::{RequestData, Response, RootNode, ServerError, SubscribeNode};
 
 #[derive(Debug)]
 struct Context<'a> {
     schema: &'a ComposedSchema,
     fragments: &'a HashMap<Name, Positioned<FragmentDefinition>>,
     variables: &'a Variables,
+    variable_definitions: &'a [Positioned<VariableDefinition>],
     key_id: usize,
+    /// Services picked for the fields in `alternatives`.
+    choices: Choices<'a>,
+    /// Fields met while planning which several services can resolve, by `(type, field)`.
+    alternatives: IndexMap<(&'a str, &'a str), Vec<&'a str>>,
+    /// Fields of the current selection set which the service of the enclosing fetch provides with `@provides`.
+    provided: Option<&'a KeyFields>,
+    /// Entity fetches which wait for the fetches of their `@requires` fields,
+    /// `deferred[0]` runs one level after the group being built.
+    deferred: Vec<FetchEntityGroup<'a>>,
+}
+
+/// What an entity fetch selects for an object: a field of the operation,
+/// or a field of `@requires` for the representations of a later fetch.
+#[derive(Debug, Clone, Copy)]
+enum EntityField<'a> {
+    Field(&'a Field),
+    Required(RequiredField<'a>),
+}
+
+type Choices<'a> = HashMap<(&'a str, &'a str), &'a str>;
+
+/// `@requires` fields owned by another service: name, selection, owning service and definition.
+type RemoteRequires<'a> = Vec<(&'a str, &'a KeyFields, &'a str, &'a MetaField)>;
+
+/// Cheapest plan found so far by `PlanBuilder::plan_with_alternatives`.
+struct BestPlan<'a> {
+    score: f64,
+    cost: PlanCost,
+    plan: RootNode<'a>,
+    choices: Choices<'a>,
+    plans: usize,
 }
 
+/// Upper bound of plans compared exhaustively, beyond it every field is decided on its own.
+const MAX_ALTERNATIVE_PLANS: usize = 64;
+
+/// Upper bound of chained `@requires` followed across services, which guards against cycles.
+const MAX_REQUIRES_DEPTH: usize = 8;
+
 /// Query plan generator
 pub struct PlanBuilder<'a> {
     schema: &'a ComposedSchema,
     document: ExecutableDocument,
     operation_name: Option<String>,
     variables: Variables,
+    cost_model: CostModel,
 }
 
 impl<'a> PlanBuilder<'a> {
@@ -45,6 +86,7 @@ impl<'a> PlanBuilder<'a> {
             document,
             operation_name: None,
             variables: Default::default(),
+            cost_model: Default::default(),
         }
     }
 
@@ -57,7 +99,33 @@ impl<'a> PlanBuilder<'a> {
         Self { variables, ..self }
     }
 
-    fn check_rules(&self) -> Result<(), Response> {
+    /// Cost model used to choose between the services of `@shareable` fields.
+    pub fn cost_model(self, cost_model: CostModel) -> Self {
+        Self { cost_model, ..self }
+    }
+
+    /// Shape the response of the executed plan, see `shaping::shape_response`.
+    pub fn shape_response(&self, resp: &mut Response) {
+        super::shaping::shape_response(
+            self.schema,
+            &self.document,
+            self.operation_name.as_deref(),
+            &self.variables,
+            resp,
+        );
+    }
+
+    /// Count the fields of the operation by `Type.field`, see `usage::field_usage`.
+    pub fn field_usage(&self) -> HashMap<String, u64> {
+        super::usage::field_usage(self.schema, &self.document, self.operation_name.as_deref(), &self.variables)
+    }
+
+    pub fn document(&self) -> &ExecutableDocument {
+        &self.document
+    }
+
+    /// Validate the document against the composed schema.
+    pub fn validate(&self) -> Result<(), Response> {
         let rule_errors =
             crate::validation::check_rules(self.schema, &self.document, &self.variables);
         if !rule_errors.is_empty() {
@@ -85,15 +153,99 @@ impl<'a> PlanBuilder<'a> {
             schema: self.schema,
             fragments,
             variables: &self.variables,
+            variable_definitions: &[],
             key_id: 1,
+            choices: Default::default(),
+            alternatives: Default::default(),
+            provided: None,
+            deferred: Default::default(),
+        }
+    }
+
+    /// Create a plan for a document which has already been checked with `validate`.
+    pub fn plan_validated(&self) -> Result<RootNode<'_>, Response> {
+        self.plan_with_alternatives().map(|(plan, _)| plan)
+    }
+
+    /// Like `plan_validated`, fields which several services can resolve are assigned
+    /// to the services of the cheapest plan according to the cost model.
+    pub fn plan_with_alternatives(&self) -> Result<(RootNode<'_>, Option<PlanAlternatives>), Response> {
+        let (plan, alternatives) = self.build_plan(Choices::default());
+        if alternatives.is_empty() {
+            return Ok((plan, None));
+        }
+        let alternatives = alternatives.into_iter().collect::<Vec<_>>();
+
+        let cost = self.cost_model.cost(&plan);
+        let mut best = BestPlan {
+            score: self.cost_model.score(&cost),
+            cost,
+            plan,
+            choices: Choices::default(),
+            plans: 1,
+        };
+        let combinations = alternatives
+            .iter()
+            .try_fold(1usize, |count, (_, candidates)| count.checked_mul(candidates.len()));
+        match combinations {
+            Some(combinations) if combinations <= MAX_ALTERNATIVE_PLANS => {
+                // The first combination picks the owner of every field, it has been planned already.
+                for mut combination in 1..combinations {
+                    let mut choices = Choices::default();
+                    for (field, candidates) in &alternatives {
+                        choices.insert(*field, candidates[combination % candidates.len()]);
+                        combination /= candidates.len();
+                    }
+                    self.compare_plan(choices, &mut best);
+                }
+            }
+            _ => {
+                for (field, candidates) in &alternatives {
+                    for candidate in &candidates[1..] {
+                        let mut choices = best.choices.clone();
+                        choices.insert(*field, *candidate);
+                        self.compare_plan(choices, &mut best);
+                    }
+                }
+            }
         }
+
+        let fields = alternatives
+            .iter()
+            .map(|(field, candidates)| ChosenAlternative {
+                field: format!("{}.{}", field.0, field.1),
+                service: best.choices.get(field).unwrap_or(&candidates[0]).to_string(),
+                candidates: candidates.iter().map(ToString::to_string).collect(),
+            })
+            .collect();
+        Ok((
+            best.plan,
+            Some(PlanAlternatives {
+                fields,
+                plans: best.plans,
+                cost: best.cost,
+            }),
+        ))
     }
 
-    pub fn plan(&self) -> Result<RootNode, Response> {
-        self.check_rules()?;
+    fn compare_plan<'b>(&'b self, choices: Choices<'b>, best: &mut BestPlan<'b>) {
+        let (plan, _) = self.build_plan(choices.clone());
+        let cost = self.cost_model.cost(&plan);
+        let score = self.cost_model.score(&cost);
+        best.plans += 1;
+        if score < best.score {
+            best.score = score;
+            best.cost = cost;
+            best.plan = plan;
+            best.choices = choices;
+        }
+    }
 
+    fn build_plan<'b>(&'b self, choices: Choices<'b>) -> (RootNode<'b>, IndexMap<(&'b str, &'b str), Vec<&'b str>>) {
         let mut ctx = self.create_context();
+        ctx.choices = choices;
         let operation_definition = get_operation(&self.document, self.operation_name.as_deref());
+        ctx.variable_definitions = &operation_definition.node.variable_definitions;
 
         let root_type = match operation_definition.node.ty {
             OperationType::Query => ctx.schema.query_type(),
@@ -107,31 +259,37 @@ impl<'a> PlanBuilder<'a> {
                 .expect("The query validator should find this error."),
         };
 
-        if let Some(root_type) = ctx.schema.types.get(root_type) {
-            match operation_definition.node.ty {
-                OperationType::Query => Ok(RootNode::Query(ctx.build_root_selection_set(
-                    QueryRootGroup::default(),
-                    operation_definition.node.ty,
+        let root_type = ctx
+            .schema
+            .types
+            .get(root_type)
+            .expect("The query validator should find this error.");
+        let plan = match operation_definition.node.ty {
+            OperationType::Query => RootNode::Query(merge_entity_fetches(ctx.build_root_selection_set(
+                QueryRootGroup::default(),
+                operation_definition.node.ty,
+                &operation_definition.node.variable_definitions,
+                root_type,
+                &operation_definition.node.selection_set.node,
+            ))),
+            OperationType::Mutation => RootNode::Query(merge_entity_fetches(ctx.build_root_selection_set(
+                MutationRootGroup::default(),
+                operation_definition.node.ty,
+                &operation_definition.node.variable_definitions,
+                root_type,
+                &operation_definition.node.selection_set.node,
+            ))),
+            OperationType::Subscription => {
+                let mut subscribe = ctx.build_subscribe(
                     &operation_definition.node.variable_definitions,
                     root_type,
                     &operation_definition.node.selection_set.node,
-                ))),
-                OperationType::Mutation => Ok(RootNode::Query(ctx.build_root_selection_set(
-                    MutationRootGroup::default(),
-                    operation_definition.node.ty,
-                    &operation_definition.node.variable_definitions,
-                    root_type,
-                    &operation_definition.node.selection_set.node,
-                ))),
-                OperationType::Subscription => Ok(RootNode::Subscribe(ctx.build_subscribe(
-                    &operation_definition.node.variable_definitions,
-                    root_type,
-                    &operation_definition.node.selection_set.node,
-                ))),
+                );
+                subscribe.flatten_node = subscribe.flatten_node.map(merge_entity_fetches);
+                RootNode::Subscribe(subscribe)
             }
-        } else {
-            unreachable!("The query validator should find this error.")
-        }
+        };
+        (plan, ctx.alternatives)
     }
 }
 
@@ -155,6 +313,9 @@ impl<'a> Context<'a> {
             for selection in &selection_set.items {
                 match &selection.node {
                     Selection::Field(field) => {
+                        if ctx.is_skipped(&field.node.directives) {
+                            continue;
+                        }
                         let field_name = field.node.name.node.as_str();
                         if is_introspection_field(field_name) {
                             ctx.build_introspection_field(inspection_selection_set, &field.node);
@@ -164,9 +325,8 @@ impl<'a> Context<'a> {
                             Some(field_definition) => field_definition,
                             None => continue,
                         };
-                        println!("{:?}", field_name);
-                        
-                        if let Some(service) = &field_definition.service {
+
+                        if let Some(service) = ctx.field_service(None, parent_type, field_definition) {
                             let selection_ref_set = root_group.selection_set_mut(service);
                             let mut path = ResponsePath::default();
                             ctx.build_field(
@@ -180,6 +340,9 @@ impl<'a> Context<'a> {
                         }
                     }
                     Selection::FragmentSpread(fragment_spread) => {
+                        if ctx.is_skipped(&fragment_spread.node.directives) {
+                            continue;
+                        }
                         if let Some(fragment) = ctx
                             .fragments
                             .get(fragment_spread.node.fragment_name.node.as_str())
@@ -195,6 +358,9 @@ impl<'a> Context<'a> {
                         }
                     }
                     Selection::InlineFragment(inline_fragment) => {
+                        if ctx.is_skipped(&inline_fragment.node.directives) {
+                            continue;
+                        }
                         build_root_selection_set_rec(
                             ctx,
                             root_group,
@@ -226,6 +392,41 @@ impl<'a> Context<'a> {
             }));
         }
 
+        if operation_type == OperationType::Mutation {
+            // Root fields of a mutation run in document order, and the entities of every step
+            // are fetched before the next step starts.
+            let mut deferred = std::mem::take(&mut self.deferred);
+            for (service, selection_set) in root_group.into_selection_set() {
+                let (variables, query_variable_definitions) =
+                    referenced_variables(&selection_set, self.variables, variable_definitions);
+                let query = FetchQuery {
+                    entity_type: None,
+                    operation_type,
+                    variable_definitions: query_variable_definitions,
+                    selection_set,
+                };
+                let step_group = {
+                    let keys = query.response_keys();
+                    let in_step = |key: &FetchEntityKey<'a>| {
+                        key.path.first().is_some_and(|segment| keys.contains(&segment.name))
+                    };
+                    self.deferred = deferred
+                        .iter_mut()
+                        .map(|group| take_fetch_entities(group, in_step))
+                        .collect();
+                    take_fetch_entities(&mut fetch_entity_group, in_step)
+                };
+                let mut step = vec![PlanNode::Fetch(FetchNode {
+                    service,
+                    variables,
+                    query,
+                })];
+                step.extend(self.build_fetch_entities(step_group, variable_definitions));
+                nodes.push(PlanNode::Sequence(SequenceNode { nodes: step }).flatten());
+            }
+            return PlanNode::Sequence(SequenceNode { nodes });
+        }
+
         let fetch_node = {
             let mut nodes = Vec::new();
             for (service, selection_set) in root_group.into_selection_set() {
@@ -242,17 +443,28 @@ impl<'a> Context<'a> {
                     },
                 }));
             }
-            if operation_type == OperationType::Query {
-                PlanNode::Parallel(ParallelNode { nodes }).flatten()
-            } else {
-                PlanNode::Sequence(SequenceNode { nodes }).flatten()
-            }
+            PlanNode::Parallel(ParallelNode { nodes }).flatten()
         };
         nodes.push(fetch_node);
+        nodes.extend(self.build_fetch_entities(fetch_entity_group, variable_definitions));
+
+        PlanNode::Sequence(SequenceNode { nodes }).flatten()
+    }
 
-        while !fetch_entity_group.is_empty() {
+    /// Plan the levels of entity fetches which follow the fetches of `fetch_entity_group`'s parents.
+    fn build_fetch_entities(
+        &mut self,
+        mut fetch_entity_group: FetchEntityGroup<'a>,
+        variable_definitions: &'a [Positioned<VariableDefinition>],
+    ) -> Vec<PlanNode<'a>> {
+        let mut nodes = Vec::new();
+        while !fetch_entity_group.is_empty() || !self.deferred.is_empty() {
             let mut flatten_nodes = Vec::new();
-            let mut next_group = FetchEntityGroup::new();
+            let mut next_group = if self.deferred.is_empty() {
+                FetchEntityGroup::new()
+            } else {
+                self.deferred.remove(0)
+            };
 
             for (
                 FetchEntityKey {
@@ -261,11 +473,16 @@ impl<'a> Context<'a> {
                 FetchEntity {
                     parent_type,
                     prefix,
+                    keys,
                     fields,
+                    required,
                 },
             ) in fetch_entity_group
             {
                 let mut selection_ref_set = SelectionRefSet::default();
+                selection_ref_set
+                    .0
+                    .extend(required.into_iter().map(SelectionRef::RequiredField));
 
                 for field in fields {
                     self.build_field(
@@ -283,6 +500,7 @@ impl<'a> Context<'a> {
                 flatten_nodes.push(PlanNode::Flatten(FlattenNode {
                     path,
                     prefix,
+                    keys,
                     service,
                     variables,
                     query: FetchQuery {
@@ -294,16 +512,18 @@ impl<'a> Context<'a> {
                 }));
             }
 
-            nodes.push(
-                PlanNode::Parallel(ParallelNode {
-                    nodes: flatten_nodes,
-                })
-                .flatten(),
-            );
+            if !flatten_nodes.is_empty() {
+                nodes.push(
+                    PlanNode::Parallel(ParallelNode {
+                        nodes: flatten_nodes,
+                    })
+                    .flatten(),
+                );
+            }
             fetch_entity_group = next_group;
         }
 
-        PlanNode::Sequence(SequenceNode { nodes }).flatten()
+        nodes
     }
 
     fn build_subscribe(
@@ -317,12 +537,15 @@ impl<'a> Context<'a> {
 
         for selection in &selection_set.items {
             if let Selection::Field(field) = &selection.node {
+                if self.is_skipped(&field.node.directives) {
+                    continue;
+                }
                 let field_name = field.node.name.node.as_str();
                 let field_definition = match parent_type.fields.get(field_name) {
                     Some(field_definition) => field_definition,
                     None => continue,
                 };
-                if let Some(service) = &field_definition.service {
+                if let Some(service) = self.field_service(None, parent_type, field_definition) {
                     let selection_ref_set = root_group.selection_set_mut(service);
                     let mut path = ResponsePath::default();
                     self.build_field(
@@ -357,9 +580,13 @@ impl<'a> Context<'a> {
         };
 
         let mut query_nodes = Vec::new();
-        while !fetch_entity_group.is_empty() {
+        while !fetch_entity_group.is_empty() || !self.deferred.is_empty() {
             let mut flatten_nodes = Vec::new();
-            let mut next_group = FetchEntityGroup::new();
+            let mut next_group = if self.deferred.is_empty() {
+                FetchEntityGroup::new()
+            } else {
+                self.deferred.remove(0)
+            };
 
             for (
                 FetchEntityKey {
@@ -368,11 +595,16 @@ impl<'a> Context<'a> {
                 FetchEntity {
                     parent_type,
                     prefix,
+                    keys,
                     fields,
+                    required,
                 },
             ) in fetch_entity_group
             {
                 let mut selection_ref_set = SelectionRefSet::default();
+                selection_ref_set
+                    .0
+                    .extend(required.into_iter().map(SelectionRef::RequiredField));
 
                 for field in fields {
                     self.build_field(
@@ -390,6 +622,7 @@ impl<'a> Context<'a> {
                 flatten_nodes.push(PlanNode::Flatten(FlattenNode {
                     path,
                     prefix,
+                    keys,
                     service,
                     variables,
                     query: FetchQuery {
@@ -401,12 +634,14 @@ impl<'a> Context<'a> {
                 }));
             }
 
-            query_nodes.push(
-                PlanNode::Parallel(ParallelNode {
-                    nodes: flatten_nodes,
-                })
-                .flatten(),
-            );
+            if !flatten_nodes.is_empty() {
+                query_nodes.push(
+                    PlanNode::Parallel(ParallelNode {
+                        nodes: flatten_nodes,
+                    })
+                    .flatten(),
+                );
+            }
             fetch_entity_group = next_group;
         }
 
@@ -436,6 +671,9 @@ impl<'a> Context<'a> {
                         ctx.build_introspection_field(introspection_selection_set, &field.node);
                     }
                     Selection::FragmentSpread(fragment_spread) => {
+                        if ctx.is_skipped(&fragment_spread.node.directives) {
+                            continue;
+                        }
                         if let Some(fragment) = ctx
                             .fragments
                             .get(fragment_spread.node.fragment_name.node.as_str())
@@ -448,6 +686,9 @@ impl<'a> Context<'a> {
                         }
                     }
                     Selection::InlineFragment(inline_fragment) => {
+                        if ctx.is_skipped(&inline_fragment.node.directives) {
+                            continue;
+                        }
                         build_selection_set(
                             ctx,
                             introspection_selection_set,
@@ -481,6 +722,9 @@ impl<'a> Context<'a> {
                 .collect()
         }
 
+        if self.is_skipped(&field.directives) {
+            return;
+        }
         let mut sub_selection_set = IntrospectionSelectionSet::default();
         build_selection_set(self, &mut sub_selection_set, &field.selection_set.node);
         introspection_selection_set.0.push(IntrospectionField {
@@ -508,6 +752,9 @@ impl<'a> Context<'a> {
         parent_type: &'a MetaType,
         field: &'a Field,
     ) {
+        if self.is_skipped(&field.directives) {
+            return;
+        }
         let field_name = field.name.node.as_str();
 
         if field_name == "__typename" {
@@ -526,22 +773,16 @@ impl<'a> Context<'a> {
             None => return,
         };
 
-        let service = match field_definition
-            .service
-            .as_deref().or(parent_type.owner.as_deref())
-        {
-            Some(service) => service,
-            None => current_service,
+        let provided = self.provided.and_then(|provided| provided.get(field_name));
+        let service = match provided {
+            Some(_) => current_service,
+            None => self
+                .field_service(Some(current_service), parent_type, field_definition)
+                .unwrap_or(current_service),
         };
 
         if service != current_service {
-            let mut keys = parent_type.keys.get(service).and_then(|x| x.get(0));
-            if keys.is_none() {
-                if let Some(owner) = &parent_type.owner {
-                    keys = parent_type.keys.get(owner).and_then(|x| x.get(0));
-                }
-            }
-            let keys = match keys {
+            let keys = match self.entity_keys(current_service, parent_type, service) {
                 Some(keys) => keys,
                 None => return,
             };
@@ -550,16 +791,27 @@ impl<'a> Context<'a> {
                     path,
                     selection_ref_set,
                     fetch_entity_group,
+                    current_service,
                     parent_type,
-                    field,
+                    EntityField::Field(field),
                     field_definition,
                     service,
                     keys,
+                    0,
                 );
                 return;
             }
         }
 
+        // `@provides` of the field applies if it is resolved by the service which declared it.
+        let provides = match &field_definition.provides {
+            Some(provides) if field_definition.service.as_deref().or(parent_type.owner.as_deref()) == Some(service) => {
+                Some(provides)
+            }
+            _ => provided.filter(|provided| !provided.is_empty()),
+        };
+        let parent_provided = std::mem::replace(&mut self.provided, provides);
+
         path.push(PathSegment {
             name: field.response_key().node.as_str(),
             is_list: is_list(&field_definition.ty),
@@ -568,6 +820,8 @@ impl<'a> Context<'a> {
         let mut sub_selection_set = SelectionRefSet::default();
 
         if matches!(field_type.kind, TypeKind::Interface | TypeKind::Union) {
+            // The runtime type is required to shape the response.
+            sub_selection_set.0.push(SelectionRef::IntrospectionTypename);
             self.build_abstract_selection_set(
                 path,
                 &mut sub_selection_set,
@@ -592,6 +846,192 @@ impl<'a> Context<'a> {
             selection_set: sub_selection_set,
         }));
         path.pop();
+        self.provided = parent_provided;
+    }
+
+    /// Service which resolves the field, `None` if neither the field nor its type have one.
+    ///
+    /// The service of the enclosing fetch is preferred when it can resolve the field,
+    /// otherwise the choice between several services is left to `PlanBuilder::plan_with_alternatives`.
+    fn field_service(
+        &mut self,
+        current_service: Option<&'a str>,
+        parent_type: &'a MetaType,
+        field_definition: &'a MetaField,
+    ) -> Option<&'a str> {
+        let owner = field_definition
+            .service
+            .as_deref()
+            .or(parent_type.owner.as_deref())?;
+        // Other services can only be reached at the root or through an entity fetch.
+        let candidates = std::iter::once(owner)
+            .chain(
+                field_definition
+                    .alternatives
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|service| {
+                        current_service.is_none()
+                            || current_service == Some(*service)
+                            || parent_type.keys.contains_key(*service)
+                    }),
+            )
+            .collect::<Vec<_>>();
+        if candidates.len() == 1 {
+            return Some(owner);
+        }
+        if let Some(current_service) = current_service.filter(|service| candidates.contains(service)) {
+            return Some(current_service);
+        }
+
+        let field = (parent_type.name.as_str(), field_definition.name.as_str());
+        let service = self
+            .choices
+            .get(&field)
+            .copied()
+            .filter(|service| candidates.contains(service))
+            .unwrap_or(owner);
+        self.alternatives.entry(field).or_insert(candidates);
+        Some(service)
+    }
+
+    /// Keys which identify the objects of `parent_type` in the representations sent to `service`,
+    /// the keys of an entity interface apply to its implementations.
+    ///
+    /// Of several keys the first one `current_service` can select without another fetch is used.
+    fn entity_keys(
+        &self,
+        current_service: &str,
+        parent_type: &'a MetaType,
+        service: &str,
+    ) -> Option<&'a KeyFields> {
+        let keys = parent_type
+            .keys
+            .get(service)
+            .filter(|keys| !keys.is_empty())
+            .or_else(|| {
+                let owner = parent_type.owner.as_ref()?;
+                parent_type.keys.get(owner).filter(|keys| !keys.is_empty())
+            })
+            .or_else(|| {
+                parent_type
+                    .implements
+                    .iter()
+                    .find_map(|interface| self.schema.types.get(interface)?.keys.get(service))
+                    .filter(|keys| !keys.is_empty())
+            })?;
+        keys.iter()
+            .find(|keys| self.key_available(current_service, parent_type, keys, self.provided))
+            .or_else(|| keys.first())
+    }
+
+    /// Whether `current_service` can select every field of the key, nested fields included.
+    fn key_available(
+        &self,
+        current_service: &str,
+        ty: &MetaType,
+        keys: &KeyFields,
+        provided: Option<&KeyFields>,
+    ) -> bool {
+        keys.iter().all(|(name, children)| {
+            // The service selects the fields of its own keys and the fields it provides.
+            let own_key = ty
+                .keys
+                .get(current_service)
+                .is_some_and(|keys| keys.iter().any(|key| key.contains_key(name)));
+            if own_key || provided.is_some_and(|provided| provided.contains_key(name)) {
+                return true;
+            }
+            let field = match ty.fields.get(name) {
+                Some(field) => field,
+                None => return false,
+            };
+            let resolvable = match field.service.as_deref().or(ty.owner.as_deref()) {
+                Some(service) => {
+                    service == current_service || field.alternatives.iter().any(|service| service == current_service)
+                }
+                None => true,
+            };
+            resolvable
+                && (children.is_empty()
+                    || self.schema.concrete_type_by_name(&field.ty).is_some_and(|child| {
+                        self.key_available(
+                            current_service,
+                            child,
+                            children,
+                            provided.and_then(|provided| provided.get(name)),
+                        )
+                    }))
+        })
+    }
+
+    /// Split the `@requires` fields of `meta_field` into those `current_service` selects with the keys,
+    /// and those which have to be fetched from another service first, with that service and their definition.
+    fn split_requires(
+        &mut self,
+        current_service: &'a str,
+        parent_type: &'a MetaType,
+        meta_field: &'a MetaField,
+    ) -> (Vec<(&'a str, &'a KeyFields)>, RemoteRequires<'a>) {
+        let mut local = Vec::new();
+        let mut remote = Vec::new();
+        let requires = match &meta_field.requires {
+            Some(requires) => requires,
+            None => return (local, remote),
+        };
+        for (name, fields) in requires.iter() {
+            let name = name.as_str();
+            let definition = match parent_type.fields.get(name) {
+                Some(definition) => definition,
+                None => continue,
+            };
+            let provided = self.provided.is_some_and(|provided| provided.contains_key(name));
+            let service = match self.field_service(Some(current_service), parent_type, definition) {
+                Some(service) if !provided && service != current_service => service,
+                _ => {
+                    local.push((name, fields));
+                    continue;
+                }
+            };
+            remote.push((name, fields, service, definition));
+        }
+        (local, remote)
+    }
+
+    /// Number of levels an entity fetch of `meta_field` waits for the fetches of its `@requires` fields.
+    fn requires_delay(
+        &mut self,
+        current_service: &'a str,
+        parent_type: &'a MetaType,
+        meta_field: &'a MetaField,
+        depth: usize,
+    ) -> usize {
+        if depth >= MAX_REQUIRES_DEPTH {
+            return 0;
+        }
+        let (_, remote) = self.split_requires(current_service, parent_type, meta_field);
+        remote
+            .into_iter()
+            .map(|(_, _, _, definition)| {
+                self.requires_delay(current_service, parent_type, definition, depth + 1) + 1
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Group of entity fetches which runs `delay` levels after `fetch_entity_group`.
+    fn fetch_entity_group_at<'g>(
+        &'g mut self,
+        fetch_entity_group: &'g mut FetchEntityGroup<'a>,
+        delay: usize,
+    ) -> &'g mut FetchEntityGroup<'a> {
+        if delay == 0 {
+            return fetch_entity_group;
+        }
+        if self.deferred.len() < delay {
+            self.deferred.resize_with(delay, Default::default);
+        }
+        &mut self.deferred[delay - 1]
     }
 
     fn add_fetch_entity(
@@ -599,21 +1039,43 @@ impl<'a> Context<'a> {
         path: &mut ResponsePath<'a>,
         selection_ref_set: &mut SelectionRefSet<'a>,
         fetch_entity_group: &mut FetchEntityGroup<'a>,
+        current_service: &'a str,
         parent_type: &'a MetaType,
-        field: &'a Field,
+        field: EntityField<'a>,
         meta_field: &'a MetaField,
         service: &'a str,
         keys: &'a KeyFields,
+        depth: usize,
     ) {
+        let (local_requires, remote_requires) =
+            self.split_requires(current_service, parent_type, meta_field);
+        let delay = self.requires_delay(current_service, parent_type, meta_field, depth);
         let fetch_entity_key = FetchEntityKey {
             service,
             path: path.clone(),
             ty: parent_type.name.as_str(),
         };
 
-        match fetch_entity_group.get_mut(&fetch_entity_key) {
-            Some(fetch_entity) => {
-                fetch_entity.fields.push(field);
+        let existing = self
+            .fetch_entity_group_at(fetch_entity_group, delay)
+            .get(&fetch_entity_key)
+            .map(|fetch_entity| fetch_entity.prefix);
+        let prefix = match existing {
+            Some(prefix) => {
+                let required_ref = selection_ref_set.0.iter_mut().find_map(|selection| match selection {
+                    SelectionRef::RequiredRef(required_ref) if required_ref.prefix == prefix => {
+                        Some(required_ref)
+                    }
+                    _ => None,
+                });
+                if let Some(required_ref) = required_ref {
+                    for (name, fields) in local_requires {
+                        if !required_ref.requires.iter().any(|(required, _)| *required == name) {
+                            required_ref.requires.push((name, fields));
+                        }
+                    }
+                }
+                prefix
             }
             None => {
                 let prefix = self.take_key_prefix();
@@ -622,18 +1084,64 @@ impl<'a> Context<'a> {
                     .push(SelectionRef::RequiredRef(RequiredRef {
                         prefix,
                         fields: keys,
-                        requires: meta_field.requires.as_ref(),
+                        requires: local_requires,
                     }));
-                fetch_entity_group.insert(
-                    fetch_entity_key,
+                self.fetch_entity_group_at(fetch_entity_group, delay).insert(
+                    fetch_entity_key.clone(),
                     FetchEntity {
                         parent_type,
                         prefix,
-                        fields: vec![field],
+                        keys,
+                        fields: Vec::new(),
+                        required: Vec::new(),
                     },
                 );
+                prefix
+            }
+        };
+
+        if let Some(fetch_entity) = self
+            .fetch_entity_group_at(fetch_entity_group, delay)
+            .get_mut(&fetch_entity_key)
+        {
+            match field {
+                EntityField::Field(field) => fetch_entity.fields.push(field),
+                EntityField::Required(required) => {
+                    if fetch_entity
+                        .required
+                        .iter()
+                        .any(|fetched| fetched.prefix == required.prefix && fetched.name == required.name)
+                    {
+                        return;
+                    }
+                    fetch_entity.required.push(required);
+                }
             }
         }
+
+        // Fields of `@requires` which `current_service` can't select are fetched from their own service
+        // in an earlier level, under the prefix of this fetch so they end up in its representations.
+        if depth >= MAX_REQUIRES_DEPTH {
+            return;
+        }
+        for (name, fields, required_service, definition) in remote_requires {
+            let keys = match self.entity_keys(current_service, parent_type, required_service) {
+                Some(keys) => keys,
+                None => continue,
+            };
+            self.add_fetch_entity(
+                path,
+                selection_ref_set,
+                fetch_entity_group,
+                current_service,
+                parent_type,
+                EntityField::Required(RequiredField { prefix, name, fields }),
+                definition,
+                required_service,
+                keys,
+                depth + 1,
+            );
+        }
     }
 
     fn build_selection_set(
@@ -658,6 +1166,9 @@ impl<'a> Context<'a> {
                     );
                 }
                 Selection::FragmentSpread(fragment_spread) => {
+                    if self.is_skipped(&fragment_spread.node.directives) {
+                        continue;
+                    }
                     if let Some(fragment) = self
                         .fragments
                         .get(fragment_spread.node.fragment_name.node.as_str())
@@ -673,6 +1184,9 @@ impl<'a> Context<'a> {
                     }
                 }
                 Selection::InlineFragment(inline_fragment) => {
+                    if self.is_skipped(&inline_fragment.node.directives) {
+                        continue;
+                    }
                     self.build_selection_set(
                         path,
                         selection_ref_set,
@@ -719,6 +1233,9 @@ impl<'a> Context<'a> {
                         );
                     }
                     Selection::FragmentSpread(fragment_spread) => {
+                        if ctx.is_skipped(&fragment_spread.node.directives) {
+                            continue;
+                        }
                         if let Some(fragment) =
                             ctx.fragments.get(&fragment_spread.node.fragment_name.node)
                         {
@@ -758,6 +1275,9 @@ impl<'a> Context<'a> {
                         }
                     }
                     Selection::InlineFragment(inline_fragment) => {
+                        if ctx.is_skipped(&inline_fragment.node.directives) {
+                            continue;
+                        }
                         match inline_fragment
                             .node
                             .type_condition
@@ -798,6 +1318,10 @@ impl<'a> Context<'a> {
         let mut selection_ref_set_group = IndexMap::new();
         for possible_type in &parent_type.possible_types {
             if let Some(ty) = self.schema.types.get(possible_type) {
+                // A service never returns objects of types it does not define.
+                if !ty.services.is_empty() && !ty.services.contains(current_service) {
+                    continue;
+                }
                 path.last_mut().unwrap().possible_type = Some(ty.name.as_str());
                 build_fields(
                     self,
@@ -823,6 +1347,10 @@ impl<'a> Context<'a> {
         }
     }
 
+    fn is_skipped(&self, directives: &[Positioned<Directive>]) -> bool {
+        is_skipped(directives, self.variables, self.variable_definitions)
+    }
+
     fn take_key_prefix(&mut self) -> usize {
         let id = self.key_id;
         self.key_id += 1;
@@ -877,12 +1405,49 @@ impl<'a> Context<'a> {
     }
 }
 
+/// Validate and plan the operation of the request, the plan is serialized to JSON.
+///
+/// Services picked by the cost model for `@shareable` fields are listed in `alternatives`.
+pub fn plan_to_json(
+    schema: &ComposedSchema,
+    request: RequestData,
+    cost_model: CostModel,
+) -> Result<serde_json::Value, Response> {
+    let document = parser::parse_query(&request.query).map_err(|err| Response::error(err.to_string()))?;
+    let mut plan_builder = PlanBuilder::new(schema, document)
+        .variables(request.variables)
+        .cost_model(cost_model);
+    if let Some(operation) = request.operation {
+        plan_builder = plan_builder.operation_name(operation);
+    }
+    plan_builder.validate()?;
+    let (plan, alternatives) = plan_builder.plan_with_alternatives()?;
+    let mut plan = serde_json::to_value(&plan).map_err(|err| Response::error(err.to_string()))?;
+    if let (serde_json::Value::Object(object), Some(alternatives)) = (&mut plan, alternatives) {
+        let alternatives = serde_json::to_value(&alternatives).map_err(|err| Response::error(err.to_string()))?;
+        object.insert("alternatives".to_string(), alternatives);
+    }
+    Ok(plan)
+}
+
+/// Remove the entity fetches whose key matches from the group.
+fn take_fetch_entities<'a>(
+    fetch_entity_group: &mut FetchEntityGroup<'a>,
+    f: impl Fn(&FetchEntityKey<'a>) -> bool,
+) -> FetchEntityGroup<'a> {
+    let (taken, rest) = std::mem::take(fetch_entity_group)
+        .into_iter()
+        .partition(|(key, _)| f(key));
+    *fetch_entity_group = rest;
+    taken
+}
+
 #[inline]
 fn is_list(ty: &Type) -> bool {
     matches!(ty.base, BaseType::List(_))
 }
 
-fn get_operation<'a>(
+pub(super) fn get_operation<'a>(
     document: &'a ExecutableDocument,
     operation_name: Option<&str>,
 ) -> &'a Positioned<OperationDefinition> {
@@ -903,6 +1468,47 @@ fn get_operation<'a>(
     operation.expect("The query validator should find this error.")
 }
 
+/// Whether `@skip(if:)` or `@include(if:)` with the given condition excludes a selection.
+pub fn is_skipped_by(directive: &str, condition: Option<&ConstValue>) -> bool {
+    matches!(
+        (directive, condition),
+        ("skip", Some(ConstValue::Boolean(true))) | ("include", Some(ConstValue::Boolean(false)))
+    )
+}
+
+pub(super) fn is_skipped(
+    directives: &[Positioned<Directive>],
+    variables: &Variables,
+    variable_definitions: &[Positioned<VariableDefinition>],
+) -> bool {
+    directives.iter().any(|directive| {
+        let condition = directive
+            .node
+            .arguments
+            .iter()
+            .find(|(name, _)| name.node == "if")
+            .and_then(|(_, value)| {
+                value
+                    .node
+                    .clone()
+                    .into_const_with(|name| {
+                        variables
+                            .get(&name)
+                            .or_else(|| {
+                                variable_definitions
+                                    .iter()
+                                    .find(|definition| definition.node.name.node == name)
+                                    .and_then(|definition| definition.node.default_value())
+                            })
+                            .cloned()
+                            .ok_or(())
+                    })
+                    .ok()
+            });
+        is_skipped_by(directive.node.name.node.as_str(), condition.as_ref())
+    })
+}
+
 fn referenced_variables<'a>(
     selection_set: &SelectionRefSet<'a>,
     variables: &'a Variables,
@@ -1002,3 +1608,417 @@ fn referenced_variables<'a>(
 fn is_introspection_field(name: &str) -> bool {
     name == "__type" || name == "__schema" || name == "__typename"
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::planner::RequestData;
+    use crate::testing::{MockSource, PlanHarness};
+    use crate::GatewayServer;
+
+    const USERS: &str = r#"
+        type Query {
+            me: User
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            username: String
+            email: String
+        }
+    "#;
+
+    const REVIEWS: &str = r#"
+        type Query {
+            topReviews: [Review] @provides(fields: "author { username }")
+        }
+
+        type Review {
+            body: String
+            author: User
+        }
+
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            username: String @external
+        }
+    "#;
+
+    #[test]
+    fn fetch_provided_fields_from_the_providing_service() {
+        let harness = PlanHarness::from_sdl([("users", USERS), ("reviews", REVIEWS)]).expect("valid schema");
+
+        let plan = harness
+            .plan(RequestData::new("{ topReviews { body author { username } } }"))
+            .expect("valid plan")
+            .to_string();
+        assert!(!plan.contains(r#""service":"users""#));
+        assert!(!plan.contains("_entities"));
+
+        let plan = harness
+            .plan(RequestData::new("{ topReviews { author { username email } } }"))
+            .expect("valid plan")
+            .to_string();
+        assert!(plan.contains(r#""service":"users""#));
+        assert!(plan.contains("... on User { email }"));
+        assert_eq!(plan.matches("username").count(), 1);
+    }
+
+    const PRODUCTS: &str = r#"
+        type Query {
+            topProducts: [Product]
+        }
+
+        type Product @key(fields: "upc") {
+            upc: String!
+            weight: Int
+        }
+    "#;
+
+    const PRICING: &str = r#"
+        extend type Product @key(fields: "upc") {
+            upc: String! @external
+            weight: Int @external
+            price: Int @requires(fields: "weight")
+        }
+    "#;
+
+    const SHIPPING: &str = r#"
+        extend type Product @key(fields: "upc") {
+            upc: String! @external
+            price: Int @external
+            shippingCost: Int @requires(fields: "price")
+        }
+    "#;
+
+    #[test]
+    fn fetch_required_fields_of_other_services_first() {
+        let harness = PlanHarness::from_sdl([("products", PRODUCTS), ("pricing", PRICING), ("shipping", SHIPPING)])
+            .expect("valid schema");
+
+        harness.assert_snapshot(
+            "src/planner/snapshots/fetch_required_fields_of_other_services_first.json",
+            "{ topProducts { shippingCost } }",
+        );
+    }
+
+    #[test]
+    fn select_nested_required_fields() {
+        let harness = PlanHarness::from_sdl([
+            (
+                "products",
+                r#"
+                type Query {
+                    topProducts: [Product]
+                }
+
+                type Product @key(fields: "upc") {
+                    upc: String!
+                    dimensions: Dimensions
+                }
+
+                type Dimensions @shareable {
+                    width: Int
+                    height: Int
+                }
+                "#,
+            ),
+            (
+                "shipping",
+                r#"
+                extend type Product @key(fields: "upc") {
+                    upc: String! @external
+                    dimensions: Dimensions @external
+                    shippingCost: Int @requires(fields: "dimensions { width height }")
+                }
+
+                type Dimensions @shareable {
+                    width: Int
+                    height: Int
+                }
+                "#,
+            ),
+        ])
+        .expect("valid schema");
+
+        harness.assert_snapshot(
+            "src/planner/snapshots/select_nested_required_fields.json",
+            "{ topProducts { shippingCost } }",
+        );
+    }
+
+    #[tokio::test]
+    async fn execute_chained_requires() {
+        let products = MockSource::new("products", PRODUCTS)
+            .query_value("topProducts", json!([{ "upc": "1", "weight": 10 }, { "upc": "2", "weight": 20 }]));
+        let pricing = MockSource::new("pricing", PRICING).entity("Product", |representation| {
+            let weight = representation["weight"]
+                .as_i64()
+                .ok_or_else(|| anyhow::anyhow!("weight is required"))?;
+            Ok(json!({ "price": weight * 2 }))
+        });
+        let shipping = MockSource::new("shipping", SHIPPING).entity("Product", |representation| {
+            let price = representation["price"]
+                .as_i64()
+                .ok_or_else(|| anyhow::anyhow!("price is required"))?;
+            Ok(json!({ "shippingCost": price + 5 }))
+        });
+        let server = GatewayServer::builder()
+            .with_middleware_source(products)
+            .with_middleware_source(pricing)
+            .with_middleware_source(shipping.clone())
+            .build();
+        server.update_schema().await.expect("composed schema");
+
+        let resp = server
+            .execute(RequestData::new("{ topProducts { upc shippingCost } }"), Default::default())
+            .await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        assert_eq!(
+            resp.data.and_then(|data| data.into_json().ok()),
+            Some(json!({ "topProducts": [{ "upc": "1", "shippingCost": 25 }, { "upc": "2", "shippingCost": 45 }] }))
+        );
+        assert_eq!(shipping.requests().len(), 1);
+    }
+
+    const SEARCH: &str = r#"
+        type Query {
+            search: [Media]
+        }
+
+        interface Media @key(fields: "id") {
+            id: ID!
+        }
+
+        type Book implements Media @key(fields: "id") {
+            id: ID!
+        }
+    "#;
+
+    const BOOKS: &str = r#"
+        interface Media @key(fields: "id") {
+            id: ID!
+        }
+
+        extend type Book implements Media @key(fields: "id") {
+            id: ID! @external
+            title: String
+        }
+    "#;
+
+    const PODCASTS: &str = r#"
+        interface Media @key(fields: "id") {
+            id: ID!
+        }
+
+        type Podcast implements Media @key(fields: "id") {
+            id: ID!
+            host: String
+        }
+    "#;
+
+    #[test]
+    fn dispatch_implementations_of_entity_interfaces() {
+        let harness = PlanHarness::from_sdl([("search", SEARCH), ("books", BOOKS), ("podcasts", PODCASTS)])
+            .expect("valid schema");
+
+        let plan = harness
+            .plan(RequestData::new("{ search { id ... on Book { title } ... on Podcast { host } } }"))
+            .expect("valid plan");
+        let services = plan["nodes"]
+            .as_array()
+            .expect("sequence")
+            .iter()
+            .map(|node| node["service"].as_str().unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(services, ["search", "books"]);
+
+        let plan = plan.to_string();
+        assert!(plan.contains("search { __typename ... on Book { id __key1___typename:__typename __key1_id:id } }"));
+        assert!(plan.contains("... on Book { title }"));
+        assert!(!plan.contains("Podcast"));
+    }
+
+    #[test]
+    fn choose_a_key_the_parent_fetch_can_select() {
+        let harness = PlanHarness::from_sdl([
+            (
+                "accounts",
+                r#"
+                type Query {
+                    me: User
+                }
+
+                type User @key(fields: "id") {
+                    id: ID!
+                    email: String!
+                }
+                "#,
+            ),
+            (
+                "reviews",
+                r#"
+                type Query {
+                    topReviews: [Review]
+                }
+
+                type Review {
+                    body: String
+                    author: User
+                }
+
+                extend type User @key(fields: "email") {
+                    email: String! @external
+                }
+                "#,
+            ),
+            (
+                "profiles",
+                r#"
+                extend type User @key(fields: "id") @key(fields: "email") {
+                    id: ID! @external
+                    email: String! @external
+                    avatar: String
+                }
+                "#,
+            ),
+        ])
+        .expect("valid schema");
+
+        let plan = harness
+            .plan(RequestData::new("{ topReviews { author { avatar } } }"))
+            .expect("valid plan")
+            .to_string();
+        assert!(plan.contains("author { __key1___typename:__typename __key1_email:email }"));
+
+        let plan = harness.plan(RequestData::new("{ me { avatar } }")).expect("valid plan").to_string();
+        assert!(plan.contains("me { __key1___typename:__typename __key1_id:id }"));
+    }
+
+    const MEMBERS: &str = r#"
+        type Query {
+            members: [Member]
+        }
+
+        type Organization {
+            id: ID!
+        }
+
+        type Member @key(fields: "id organization { id }") {
+            id: ID!
+            organization: Organization
+        }
+    "#;
+
+    const BADGES: &str = r#"
+        extend type Organization {
+            id: ID! @external
+        }
+
+        extend type Member @key(fields: "id organization { id }") {
+            id: ID! @external
+            organization: Organization @external
+            badge: String
+        }
+    "#;
+
+    #[test]
+    fn select_nested_keys() {
+        let harness = PlanHarness::from_sdl([("members", MEMBERS), ("badges", BADGES)]).expect("valid schema");
+        let plan = harness
+            .plan(RequestData::new("{ members { badge } }"))
+            .expect("valid plan")
+            .to_string();
+        assert!(plan.contains("__key1_id:id __key1_organization:organization{id}"));
+    }
+
+    const ACCOUNTS_MUTATIONS: &str = r#"
+        type Query {
+            me: User
+        }
+
+        type Mutation {
+            createUser(name: String!): User
+            renameUser(id: ID!, name: String!): User
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    const REVIEWS_MUTATIONS: &str = r#"
+        type Mutation {
+            addReview(body: String!): Review
+        }
+
+        type Review {
+            body: String
+        }
+
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            reviews: [Review]
+        }
+    "#;
+
+    const INTERLEAVED_MUTATION: &str = r#"
+        mutation {
+            a: createUser(name: "a") { id reviews { body } }
+            b: addReview(body: "b") { body }
+            c: renameUser(id: "1", name: "c") { name }
+        }
+    "#;
+
+    #[test]
+    fn plan_mutation_fields_in_document_order() {
+        let harness = PlanHarness::from_sdl([("accounts", ACCOUNTS_MUTATIONS), ("reviews", REVIEWS_MUTATIONS)])
+            .expect("valid schema");
+
+        let plan = harness.plan(RequestData::new(INTERLEAVED_MUTATION)).expect("valid plan");
+        let steps = plan["nodes"].as_array().expect("sequence");
+        assert_eq!(plan["type"], "sequence");
+        assert_eq!(steps.len(), 3);
+
+        // The entities of the first step are fetched before the second step.
+        assert_eq!(steps[0]["type"], "sequence");
+        assert_eq!(steps[0]["nodes"][0]["service"], "accounts");
+        assert_eq!(steps[0]["nodes"][1]["type"], "flatten");
+        assert_eq!(steps[0]["nodes"][1]["service"], "reviews");
+        assert_eq!(steps[1]["type"], "fetch");
+        assert_eq!(steps[1]["service"], "reviews");
+        assert_eq!(steps[2]["type"], "fetch");
+        assert_eq!(steps[2]["service"], "accounts");
+    }
+
+    #[tokio::test]
+    async fn abort_mutations_after_a_failure() {
+        let accounts = MockSource::new("accounts", ACCOUNTS_MUTATIONS)
+            .mutation("createUser", |_| Ok(json!({ "id": "1", "name": "a" })))
+            .mutation("renameUser", |_| Ok(json!({ "id": "1", "name": "c" })));
+        let reviews = MockSource::new("reviews", REVIEWS_MUTATIONS)
+            .mutation("addReview", |_| anyhow::bail!("Review rejected."))
+            .entities("User", vec![json!({ "id": "1", "reviews": [] })]);
+        let server = GatewayServer::builder()
+            .with_middleware_source(accounts.clone())
+            .with_middleware_source(reviews)
+            .with_abort_mutations_on_error(true)
+            .build();
+        server.update_schema().await.expect("composed schema");
+
+        let resp = server.execute(RequestData::new(INTERLEAVED_MUTATION), Default::default()).await;
+        let messages = resp.errors.iter().map(|err| err.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            ["Review rejected.", "Mutation field 'c' was not executed because 'b' failed."]
+        );
+        assert_eq!(resp.errors[1].path, vec![value::ConstValue::String("c".to_string())]);
+        assert_eq!(
+            resp.data.and_then(|data| data.into_json().ok()),
+            Some(json!({ "a": { "id": "1", "reviews": [] }, "b": null, "c": null }))
+        );
+        assert_eq!(accounts.requests().len(), 1);
+    }
+}
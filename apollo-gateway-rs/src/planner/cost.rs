@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::plan::{PlanNode, RootNode};
+
+/// Weights used to compare the plans of an operation whose fields can be resolved by several services.
+///
+/// The score of a plan is its expected latency plus `fetch_cost` for every request
+/// and `depth_cost` for every sequential step.
+#[derive(Debug, Clone)]
+pub struct CostModel {
+    /// Cost of a request to a subgraph, in milliseconds.
+    pub fetch_cost: f64,
+    /// Cost of a step which waits for the previous one, in milliseconds.
+    pub depth_cost: f64,
+    /// Latency assumed for services which have not been measured yet, in milliseconds.
+    pub default_latency: f64,
+    /// Average latency of the services in milliseconds, the gateway fills it from its fetch metrics.
+    pub latencies: HashMap<String, f64>,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            fetch_cost: 5.0,
+            depth_cost: 10.0,
+            default_latency: 50.0,
+            latencies: Default::default(),
+        }
+    }
+}
+
+/// Cost of a plan, see `CostModel`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanCost {
+    pub fetches: usize,
+    /// Length of the longest chain of sequential fetches.
+    pub depth: usize,
+    /// Expected latency of the slowest chain of fetches, in milliseconds.
+    pub latency: f64,
+}
+
+impl PlanCost {
+    fn then(self, next: PlanCost) -> Self {
+        Self {
+            fetches: self.fetches + next.fetches,
+            depth: self.depth + next.depth,
+            latency: self.latency + next.latency,
+        }
+    }
+
+    fn alongside(self, other: PlanCost) -> Self {
+        Self {
+            fetches: self.fetches + other.fetches,
+            depth: self.depth.max(other.depth),
+            latency: self.latency.max(other.latency),
+        }
+    }
+}
+
+impl CostModel {
+    pub fn latencies(self, latencies: HashMap<String, f64>) -> Self {
+        Self { latencies, ..self }
+    }
+
+    pub fn cost(&self, plan: &RootNode) -> PlanCost {
+        match plan {
+            RootNode::Query(node) => self.node_cost(node),
+            RootNode::Subscribe(subscribe) => {
+                let cost = subscribe
+                    .subscribe_nodes
+                    .iter()
+                    .map(|node| self.fetch_cost(node.service))
+                    .fold(PlanCost::default(), PlanCost::alongside);
+                match &subscribe.flatten_node {
+                    Some(node) => cost.then(self.node_cost(node)),
+                    None => cost,
+                }
+            }
+        }
+    }
+
+    pub fn score(&self, cost: &PlanCost) -> f64 {
+        cost.latency + self.fetch_cost * cost.fetches as f64 + self.depth_cost * cost.depth as f64
+    }
+
+    fn node_cost(&self, node: &PlanNode) -> PlanCost {
+        match node {
+            PlanNode::Sequence(sequence) => sequence
+                .nodes
+                .iter()
+                .map(|node| self.node_cost(node))
+                .fold(PlanCost::default(), PlanCost::then),
+            PlanNode::Parallel(parallel) => parallel
+                .nodes
+                .iter()
+                .map(|node| self.node_cost(node))
+                .fold(PlanCost::default(), PlanCost::alongside),
+            PlanNode::Introspection(_) => PlanCost::default(),
+            PlanNode::Fetch(fetch) => self.fetch_cost(fetch.service),
+            PlanNode::Flatten(flatten) => self.fetch_cost(flatten.service),
+            PlanNode::FlattenBatch(batch) => self.fetch_cost(batch.service),
+        }
+    }
+
+    fn fetch_cost(&self, service: &str) -> PlanCost {
+        PlanCost {
+            fetches: 1,
+            depth: 1,
+            latency: self.latencies.get(service).copied().unwrap_or(self.default_latency),
+        }
+    }
+}
+
+/// Service picked for a field which several services can resolve.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChosenAlternative {
+    /// `Type.field`
+    pub field: String,
+    pub service: String,
+    pub candidates: Vec<String>,
+}
+
+/// Outcome of the comparison of the alternative plans of an operation, shown in the plan output.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanAlternatives {
+    pub fields: Vec<ChosenAlternative>,
+    /// Number of plans which have been compared.
+    pub plans: usize,
+    pub cost: PlanCost,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::planner::RequestData;
+    use crate::testing::PlanHarness;
+
+    const ACCOUNTS: &str = r#"
+        type Query {
+            me: User
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    const REVIEWS: &str = r#"
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            nickname: String @shareable
+        }
+    "#;
+
+    const PROFILES: &str = r#"
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            nickname: String @shareable
+            avatar: String
+        }
+    "#;
+
+    #[test]
+    fn pick_the_cheapest_alternative() {
+        let harness = PlanHarness::from_sdl([("accounts", ACCOUNTS), ("reviews", REVIEWS), ("profiles", PROFILES)])
+            .expect("valid schema");
+
+        let plan = harness.plan(RequestData::new("{ me { nickname avatar } }")).expect("valid plan");
+        assert_eq!(
+            plan["alternatives"]["fields"],
+            json!([{ "field": "User.nickname", "service": "profiles", "candidates": ["reviews", "profiles"] }])
+        );
+        assert_eq!(plan["alternatives"]["cost"]["fetches"], 2);
+        assert!(!plan.to_string().contains(r#""service":"reviews""#));
+
+        // Without other fields of `profiles` the owner is as good as the alternative.
+        let plan = harness.plan(RequestData::new("{ me { nickname } }")).expect("valid plan");
+        assert_eq!(plan["alternatives"]["fields"][0]["service"], "reviews");
+    }
+
+    #[test]
+    fn reject_fields_which_are_not_shareable() {
+        let result = PlanHarness::from_sdl([
+            ("accounts", ACCOUNTS),
+            ("reviews", "extend type User @key(fields: \"id\") { id: ID! @external nickname: String }"),
+            ("profiles", PROFILES),
+        ]);
+        assert!(result.is_err());
+    }
+}
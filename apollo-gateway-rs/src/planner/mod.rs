@@ -1,14 +1,19 @@
 #![forbid(unsafe_code)]
 
 mod builder;
+mod cost;
+mod optimize;
+mod shaping;
 mod plan;
 mod request;
 mod response;
 mod types;
+mod usage;
 
-pub use builder::PlanBuilder;
+pub use builder::{is_skipped_by, plan_to_json, PlanBuilder};
+pub use cost::{ChosenAlternative, CostModel, PlanAlternatives, PlanCost};
 pub use plan::{
-    FetchNode, FlattenNode, IntrospectionDirective, IntrospectionField, IntrospectionNode,
+    FetchNode, FlattenBatchNode, FlattenNode, IntrospectionDirective, IntrospectionField, IntrospectionNode,
     IntrospectionSelectionSet, ParallelNode, PathSegment, PlanNode, ResponsePath, RootNode,
     SequenceNode, SubscribeNode,
 };
@@ -0,0 +1,113 @@
+use indexmap::IndexMap;
+
+use super::plan::{FlattenBatchNode, FlattenNode, ParallelNode, PlanNode, SequenceNode};
+
+/// Merge the flatten nodes of a parallel node which fetch entities from the same service,
+/// so every level of the plan sends at most one `_entities` request to a service.
+pub(crate) fn merge_entity_fetches(node: PlanNode<'_>) -> PlanNode<'_> {
+    match node {
+        PlanNode::Sequence(SequenceNode { nodes }) => PlanNode::Sequence(SequenceNode {
+            nodes: nodes.into_iter().map(merge_entity_fetches).collect(),
+        }),
+        PlanNode::Parallel(ParallelNode { nodes }) => {
+            let mut merged = Vec::with_capacity(nodes.len());
+            let mut flatten_nodes: IndexMap<&str, Vec<FlattenNode>> = IndexMap::new();
+            for node in nodes {
+                match node {
+                    PlanNode::Flatten(flatten) => flatten_nodes.entry(flatten.service).or_default().push(flatten),
+                    node => merged.push(merge_entity_fetches(node)),
+                }
+            }
+            for (service, mut nodes) in flatten_nodes {
+                if nodes.len() == 1 {
+                    merged.push(PlanNode::Flatten(nodes.remove(0)));
+                } else {
+                    merged.push(PlanNode::FlattenBatch(FlattenBatchNode { service, nodes }));
+                }
+            }
+            PlanNode::Parallel(ParallelNode { nodes: merged }).flatten()
+        }
+        node => node,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::planner::RequestData;
+    use crate::testing::{MockSource, PlanHarness};
+    use crate::GatewayServer;
+
+    const ACCOUNTS: &str = r#"
+        type Query {
+            me: User
+            admin: User
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    const REVIEWS: &str = r#"
+        type Review {
+            body: String
+        }
+
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            reviews: [Review]
+        }
+    "#;
+
+    #[test]
+    fn merge_sibling_flatten_nodes() {
+        let harness = PlanHarness::from_sdl([("accounts", ACCOUNTS), ("reviews", REVIEWS)]).expect("valid schema");
+        let plan = harness
+            .plan(RequestData::new("{ me { reviews { body } } admin { name reviews { body } } }"))
+            .expect("valid plan")
+            .to_string();
+        assert!(plan.contains(r#""type":"flattenBatch""#));
+        assert!(plan.contains(r#""paths":["me","admin"]"#));
+        assert!(plan.contains("_entities0:_entities(representations:$representations0)"));
+        assert!(plan.contains("_entities1:_entities(representations:$representations1)"));
+        assert!(!plan.contains(r#""type":"flatten""#));
+    }
+
+    #[tokio::test]
+    async fn execute_merged_flatten_nodes() {
+        let accounts = MockSource::new("accounts", ACCOUNTS)
+            .query_value("me", json!({ "id": "1", "name": "Ada" }))
+            .query_value("admin", json!({ "id": "2", "name": "Grace" }));
+        let reviews = MockSource::new("reviews", REVIEWS).entities(
+            "User",
+            vec![
+                json!({ "id": "1", "reviews": [{ "body": "Great" }] }),
+                json!({ "id": "2", "reviews": [{ "body": "Fine" }] }),
+            ],
+        );
+        let server = GatewayServer::builder()
+            .with_middleware_source(accounts)
+            .with_middleware_source(reviews.clone())
+            .build();
+        server.update_schema().await.expect("composed schema");
+
+        let resp = server
+            .execute(
+                RequestData::new("{ me { reviews { body } } admin { name reviews { body } } }"),
+                Default::default(),
+            )
+            .await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        assert_eq!(
+            resp.data.and_then(|data| data.into_json().ok()),
+            Some(json!({
+                "me": { "reviews": [{ "body": "Great" }] },
+                "admin": { "name": "Grace", "reviews": [{ "body": "Fine" }] },
+            }))
+        );
+        assert_eq!(reviews.requests().len(), 1);
+    }
+}
@@ -2,10 +2,12 @@ use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::ops::{Deref, DerefMut};
 
 use indexmap::IndexMap;
+use serde::ser::SerializeStruct;
 use serde::{Serialize, Serializer};
 use value::{ConstValue, Name, Variables};
 
-use super::types::{FetchQuery, VariablesRef};
+use super::types::{FetchQuery, VariableDefinitionsRef, VariablesRef};
+use crate::schema::KeyFields;
 use super::RequestData;
 
 #[derive(Debug, Serialize)]
@@ -16,6 +18,8 @@ pub enum PlanNode<'a> {
     Introspection(IntrospectionNode),
     Fetch(FetchNode<'a>),
     Flatten(FlattenNode<'a>),
+    #[serde(rename = "flattenBatch")]
+    FlattenBatch(FlattenBatchNode<'a>),
 }
 
 impl<'a> PlanNode<'a> {
@@ -150,6 +154,9 @@ impl<'a> FetchNode<'a> {
 pub struct FlattenNode<'a> {
     pub path: ResponsePath<'a>,
     pub prefix: usize,
+    /// Key fields of the representations, objects missing one of them are not fetched.
+    #[serde(skip)]
+    pub keys: &'a KeyFields,
     pub service: &'a str,
     #[serde(skip_serializing_if = "VariablesRef::is_empty")]
     pub variables: VariablesRef<'a>,
@@ -164,6 +171,98 @@ impl<'a> FlattenNode<'a> {
     }
 }
 
+/// Flatten nodes of one service merged into a single request.
+///
+/// Every node selects `_entities` under the alias `_entities{idx}` with its representations in `$representations{idx}`.
+#[derive(Debug)]
+pub struct FlattenBatchNode<'a> {
+    pub service: &'a str,
+    pub nodes: Vec<FlattenNode<'a>>,
+}
+
+impl<'a> FlattenBatchNode<'a> {
+    pub fn representations_variable(idx: usize) -> String {
+        format!("representations{}", idx)
+    }
+
+    pub fn response_key(idx: usize) -> String {
+        format!("_entities{}", idx)
+    }
+
+    /// Variables of the operation referenced by any of the nodes.
+    pub fn variables(&self) -> VariablesRef<'a> {
+        let mut variables = VariablesRef::default();
+        for node in &self.nodes {
+            variables.variables.extend(node.variables.variables.iter().map(|(name, value)| (*name, *value)));
+        }
+        variables
+    }
+
+    pub fn query(&self) -> String {
+        let mut variable_definitions = VariableDefinitionsRef::default();
+        for node in &self.nodes {
+            for variable in &node.query.variable_definitions.variables {
+                if !variable_definitions
+                    .variables
+                    .iter()
+                    .any(|defined| defined.name.node == variable.name.node)
+                {
+                    variable_definitions.variables.push(*variable);
+                }
+            }
+        }
+
+        let mut query = String::from("query(");
+        for idx in 0..self.nodes.len() {
+            if idx > 0 {
+                query.push_str(", ");
+            }
+            query.push_str(&format!("${}:[_Any!]!", Self::representations_variable(idx)));
+        }
+        if !variable_definitions.variables.is_empty() {
+            query.push_str(&format!(", {}", variable_definitions));
+        }
+        query.push_str(") {");
+        for (idx, node) in self.nodes.iter().enumerate() {
+            query.push_str(&format!(
+                " {}:_entities(representations:${}) {{ ... on {} {} }}",
+                Self::response_key(idx),
+                Self::representations_variable(idx),
+                node.query.entity_type.unwrap_or_default(),
+                node.query.selection_set
+            ));
+        }
+        query.push_str(" }");
+        query
+    }
+
+    pub fn to_request(&self, representations: Variables) -> RequestData {
+        RequestData::new(self.query())
+            .variables(representations)
+            .extend_variables(self.variables().to_variables())
+    }
+}
+
+impl<'a> Serialize for FlattenBatchNode<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("FlattenBatchNode", 4)?;
+        s.serialize_field("service", self.service)?;
+        s.serialize_field(
+            "paths",
+            &self.nodes.iter().map(|node| &node.path).collect::<Vec<_>>(),
+        )?;
+        let variables = self.variables();
+        if !variables.is_empty() {
+            s.serialize_field("variables", &variables)?;
+        }
+        s.serialize_field("query", &self.query())?;
+        s.end()
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscribeNode<'a> {
@@ -1,11 +1,28 @@
 use std::collections::HashMap;
+use parser::types::OperationType;
 use serde::{Deserialize, Serialize};
 use value::{ConstValue, Variables};
 
+/// A request to a subgraph, `GraphqlSourceMiddleware::will_send_request` can modify every part of it.
 #[derive(Debug)]
 pub struct Request {
     pub headers: HashMap<String, String>,
-    pub data: RequestData
+    pub data: RequestData,
+    /// Send the request to this URL instead of the URL of the source.
+    pub url: Option<String>,
+    /// Connect the websocket of a subscription to this URL instead of the subscription URL of the source.
+    pub subscription_url: Option<String>,
+}
+
+impl Request {
+    pub fn new(data: RequestData) -> Self {
+        Self {
+            headers: Default::default(),
+            data,
+            url: None,
+            subscription_url: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,6 +32,8 @@ pub struct RequestData {
     pub operation: Option<String>,
     #[serde(skip_serializing_if = "variables_is_empty", default)]
     pub variables: Variables,
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub extensions: HashMap<String, ConstValue>,
 }
 
 impl RequestData {
@@ -23,6 +42,7 @@ impl RequestData {
             query: query.into(),
             operation: None,
             variables: Default::default(),
+            extensions: Default::default(),
         }
     }
 
@@ -37,15 +57,78 @@ impl RequestData {
         Self { variables, ..self }
     }
 
+    pub fn extensions(self, extensions: HashMap<String, ConstValue>) -> Self {
+        Self { extensions, ..self }
+    }
+
     pub fn extend_variables(mut self, variables: Variables) -> Self {
         if let ConstValue::Object(obj) = variables.into_value() {
             self.variables.extend(obj);
         }
         self
     }
+
+    /// Parse the query string of a `GET` request, `variables` and `extensions` are JSON encoded.
+    /// Mutations are rejected, they must be sent with `POST`.
+    pub fn from_query_string(query_string: &str) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct Params {
+            query: String,
+            #[serde(rename = "operationName")]
+            operation: Option<String>,
+            variables: Option<String>,
+            extensions: Option<String>,
+        }
+
+        let params: Params = serde_urlencoded::from_str(query_string)?;
+        let variables = match params.variables {
+            Some(variables) => serde_json::from_str(&variables)?,
+            None => Variables::default(),
+        };
+        let extensions = match params.extensions {
+            Some(extensions) => serde_json::from_str(&extensions)?,
+            None => HashMap::default(),
+        };
+        // Syntax errors are reported by the execution of the request.
+        if let Ok(document) = parser::parse_query(&params.query) {
+            let is_mutation = document.operations.iter().any(|(name, operation)| {
+                operation.node.ty == OperationType::Mutation
+                    && (params.operation.is_none() || name.map(|name| name.as_str()) == params.operation.as_deref())
+            });
+            anyhow::ensure!(!is_mutation, "Mutations can only be sent with POST.");
+        }
+        Ok(Self {
+            query: params.query,
+            operation: params.operation,
+            variables,
+            extensions,
+        })
+    }
 }
 
 #[inline]
 fn variables_is_empty(variables: &Variables) -> bool {
     variables.is_empty()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_query_string() {
+        let request = RequestData::from_query_string(
+            "query=query%20User(%24id%3A%20ID!)%20%7B%20user(id%3A%20%24id)%20%7B%20name%20%7D%20%7D&operationName=User&variables=%7B%22id%22%3A%221%22%7D",
+        )
+        .unwrap();
+        assert_eq!(request.query, "query User($id: ID!) { user(id: $id) { name } }");
+        assert_eq!(request.operation.as_deref(), Some("User"));
+        assert_eq!(request.variables, Variables::from_json(serde_json::json!({ "id": "1" })));
+
+        let request = RequestData::from_query_string("query=%7B%20me%20%7B%20id%20%7D%20%7D&extensions=%7B%22trace%22%3Atrue%7D").unwrap();
+        assert_eq!(request.extensions.get("trace"), Some(&ConstValue::Boolean(true)));
+
+        assert!(RequestData::from_query_string("operationName=User").is_err());
+        assert!(RequestData::from_query_string("query=mutation%20%7B%20logout%20%7D").is_err());
+    }
+}
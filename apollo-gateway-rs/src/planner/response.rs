@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use http::HeaderMap;
 use parser::Pos;
 use serde::{Deserialize, Serialize};
 use value::ConstValue;
@@ -48,5 +49,21 @@ pub struct Response {
     pub extensions: HashMap<String, ConstValue>,
 
     #[serde(skip_serializing, skip_deserializing)]
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
+}
+
+impl Response {
+    /// Response of a request which could not be executed.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            errors: vec![ServerError::new(message)],
+            ..Default::default()
+        }
+    }
+
+    /// A response without data means that the request could not be executed at all,
+    /// HTTP adapters answer it with `400 Bad Request`.
+    pub fn is_request_error(&self) -> bool {
+        self.data.is_none() && !self.errors.is_empty()
+    }
 }
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+use parser::types::{
+    BaseType, Directive, ExecutableDocument, Field, FragmentDefinition, OperationType,
+    Selection, SelectionSet, Type, VariableDefinition,
+};
+use parser::Positioned;
+use value::{ConstValue, Name, Variables};
+
+use crate::schema::{ComposedSchema, MetaType};
+
+use super::builder::{get_operation, is_skipped};
+use super::{Response, ServerError};
+
+struct Context<'a> {
+    schema: &'a ComposedSchema,
+    fragments: &'a HashMap<Name, Positioned<FragmentDefinition>>,
+    variables: &'a Variables,
+    variable_definitions: &'a [Positioned<VariableDefinition>],
+    errors: Vec<ServerError>,
+}
+
+/// Shape the merged response of all fetches by the operation of the client.
+///
+/// Only the fields selected by the client are kept, in selection order and under their response keys,
+/// so the fields added by the planner for `@key` and `@requires` never leak.
+/// Fields which were not resolved become `null`, a `null` of a non-null field is propagated
+/// to the nearest nullable parent and a field error is added if no subgraph reported one for the path.
+pub fn shape_response(
+    schema: &ComposedSchema,
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    variables: &Variables,
+    resp: &mut Response,
+) {
+    if resp.data.is_none() && resp.errors.is_empty() {
+        return;
+    }
+    let operation = get_operation(document, operation_name);
+    let root_type = match operation.node.ty {
+        OperationType::Query => Some(schema.query_type()),
+        OperationType::Mutation => schema.mutation_type(),
+        OperationType::Subscription => schema.subscription_type(),
+    };
+    let root_type = match root_type.and_then(|name| schema.types.get(name)) {
+        Some(root_type) => root_type,
+        None => return,
+    };
+
+    let mut ctx = Context {
+        schema,
+        fragments: &document.fragments,
+        variables,
+        variable_definitions: &operation.node.variable_definitions,
+        errors: std::mem::take(&mut resp.errors),
+    };
+    let data = match resp.data.take() {
+        Some(ConstValue::Object(object)) => Some(object),
+        Some(_) => None,
+        None => Some(Default::default()),
+    };
+    resp.data = Some(
+        data.and_then(|object| {
+            ctx.complete_object(
+                root_type,
+                object,
+                &[&operation.node.selection_set.node],
+                &mut Vec::new(),
+            )
+            .ok()
+        })
+        .map(ConstValue::Object)
+        .unwrap_or(ConstValue::Null),
+    );
+    resp.errors = ctx.errors;
+}
+
+impl<'a> Context<'a> {
+    /// Returns `Err` if the object must be replaced with `null`.
+    fn complete_object(
+        &mut self,
+        parent_type: &'a MetaType,
+        mut object: IndexMap<Name, ConstValue>,
+        selection_sets: &[&'a SelectionSet],
+        path: &mut Vec<ConstValue>,
+    ) -> Result<IndexMap<Name, ConstValue>, ()> {
+        // The planner always selects `__typename` of abstract types, if it is still missing
+        // only the fields present in the response are kept.
+        let runtime_type = if parent_type.is_abstract() {
+            match object.get("__typename") {
+                Some(ConstValue::String(typename)) => self.schema.types.get(typename.as_str()),
+                _ => None,
+            }
+        } else {
+            Some(parent_type)
+        };
+
+        let mut fields = IndexMap::new();
+        for selection_set in selection_sets {
+            self.collect_fields(runtime_type, selection_set, &mut fields);
+        }
+
+        let mut shaped = IndexMap::with_capacity(fields.len());
+        for (response_key, fields) in fields {
+            let name = fields[0].name.node.as_str();
+            let value = object.shift_remove(response_key);
+            if name == "__typename" {
+                let typename = match runtime_type {
+                    Some(runtime_type) => Some(ConstValue::String(runtime_type.name.to_string())),
+                    None => object.get("__typename").cloned().or(value),
+                };
+                if let Some(typename) = typename {
+                    shaped.insert(Name::new(response_key), typename);
+                }
+                continue;
+            }
+            if name.starts_with("__") {
+                // Introspection fields are shaped by their resolver.
+                if let Some(value) = value {
+                    shaped.insert(Name::new(response_key), value);
+                }
+                continue;
+            }
+
+            let field_type = runtime_type.unwrap_or(parent_type);
+            let meta_field = match field_type.field_by_name(name) {
+                Some(meta_field) => meta_field,
+                None => continue,
+            };
+            let value = match value {
+                Some(value) => value,
+                None if runtime_type.is_some() => ConstValue::Null,
+                None => continue,
+            };
+
+            let label = format!("{}.{}", field_type.name, name);
+            let selection_sets = fields
+                .iter()
+                .map(|field| &field.selection_set.node)
+                .collect::<Vec<_>>();
+            path.push(ConstValue::String(response_key.to_string()));
+            let res = self.complete_value(&label, &meta_field.ty, value, &selection_sets, path);
+            path.pop();
+            shaped.insert(Name::new(response_key), res?);
+        }
+        Ok(shaped)
+    }
+
+    /// Returns `Err` if the value is `null` in a non-null position.
+    fn complete_value(
+        &mut self,
+        label: &str,
+        ty: &'a Type,
+        value: ConstValue,
+        selection_sets: &[&'a SelectionSet],
+        path: &mut Vec<ConstValue>,
+    ) -> Result<ConstValue, ()> {
+        let completed = match (value, &ty.base) {
+            (ConstValue::Null, _) => {
+                if !ty.nullable {
+                    self.add_non_null_error(label, path);
+                }
+                None
+            }
+            (ConstValue::List(items), BaseType::List(item_type)) => {
+                let mut completed = Vec::with_capacity(items.len());
+                let mut failed = false;
+                for (idx, item) in items.into_iter().enumerate() {
+                    path.push(ConstValue::Number(idx.into()));
+                    let item = self.complete_value(label, item_type, item, selection_sets, path);
+                    path.pop();
+                    match item {
+                        Ok(item) => completed.push(item),
+                        Err(()) => {
+                            failed = true;
+                            break;
+                        }
+                    }
+                }
+                (!failed).then_some(ConstValue::List(completed))
+            }
+            (ConstValue::Object(object), BaseType::Named(name)) => {
+                match self.schema.types.get(name) {
+                    Some(meta_type) if meta_type.is_composite() => self
+                        .complete_object(meta_type, object, selection_sets, path)
+                        .ok()
+                        .map(ConstValue::Object),
+                    _ => Some(ConstValue::Object(object)),
+                }
+            }
+            (value, _) => Some(value),
+        };
+
+        match completed {
+            Some(value) => Ok(value),
+            None if ty.nullable => Ok(ConstValue::Null),
+            None => Err(()),
+        }
+    }
+
+    fn add_non_null_error(&mut self, label: &str, path: &[ConstValue]) {
+        let reported = self.errors.iter().any(|err| {
+            !err.path.is_empty() && (err.path.starts_with(path) || path.starts_with(&err.path))
+        });
+        if !reported {
+            self.errors.push(ServerError {
+                message: format!("Cannot return null for non-nullable field {}.", label),
+                path: path.to_vec(),
+                locations: Default::default(),
+                extensions: Default::default(),
+            });
+        }
+    }
+
+    fn collect_fields(
+        &self,
+        runtime_type: Option<&MetaType>,
+        selection_set: &'a SelectionSet,
+        fields: &mut IndexMap<&'a str, Vec<&'a Field>>,
+    ) {
+        for selection in &selection_set.items {
+            match &selection.node {
+                Selection::Field(field) => {
+                    if !self.is_skipped(&field.node.directives) {
+                        fields
+                            .entry(field.node.response_key().node.as_str())
+                            .or_default()
+                            .push(&field.node);
+                    }
+                }
+                Selection::FragmentSpread(fragment_spread) => {
+                    if self.is_skipped(&fragment_spread.node.directives) {
+                        continue;
+                    }
+                    if let Some(fragment) =
+                        self.fragments.get(&fragment_spread.node.fragment_name.node)
+                    {
+                        let type_condition = &fragment.node.type_condition.node.on.node;
+                        if self.does_fragment_apply(runtime_type, Some(type_condition)) {
+                            self.collect_fields(runtime_type, &fragment.node.selection_set.node, fields);
+                        }
+                    }
+                }
+                Selection::InlineFragment(inline_fragment) => {
+                    if self.is_skipped(&inline_fragment.node.directives) {
+                        continue;
+                    }
+                    let type_condition = inline_fragment
+                        .node
+                        .type_condition
+                        .as_ref()
+                        .map(|type_condition| &type_condition.node.on.node);
+                    if self.does_fragment_apply(runtime_type, type_condition) {
+                        self.collect_fields(runtime_type, &inline_fragment.node.selection_set.node, fields);
+                    }
+                }
+            }
+        }
+    }
+
+    fn does_fragment_apply(&self, runtime_type: Option<&MetaType>, type_condition: Option<&Name>) -> bool {
+        match (runtime_type, type_condition) {
+            (Some(runtime_type), Some(type_condition)) => self
+                .schema
+                .types
+                .get(type_condition)
+                .is_some_and(|ty| ty.is_possible_type(&runtime_type.name)),
+            _ => true,
+        }
+    }
+
+    fn is_skipped(&self, directives: &[Positioned<Directive>]) -> bool {
+        is_skipped(directives, self.variables, self.variable_definitions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDL: &str = r#"
+        type Query {
+            me: User
+            users: [User!]
+            node: Node
+        }
+
+        interface Node {
+            id: ID!
+        }
+
+        type User implements Node {
+            id: ID!
+            name: String!
+            nickname: String
+        }
+    "#;
+
+    fn shape(query: &str, data: serde_json::Value, errors: Vec<ServerError>) -> Response {
+        let schema = ComposedSchema::combine([(
+            "users".to_string(),
+            parser::parse_schema(SDL).expect("valid schema"),
+        )])
+        .expect("valid schema");
+        let document = parser::parse_query(query).expect("valid query");
+        let mut resp = Response {
+            data: ConstValue::from_json(data).ok(),
+            errors,
+            ..Default::default()
+        };
+        shape_response(&schema, &document, None, &Default::default(), &mut resp);
+        resp
+    }
+
+    fn json(resp: &Response) -> String {
+        serde_json::to_string(&resp.data).unwrap_or_default()
+    }
+
+    #[test]
+    fn propagate_to_nullable_parent() {
+        let resp = shape(
+            "{ me { id name nickname } }",
+            serde_json::json!({ "me": { "id": "1", "name": null } }),
+            Vec::new(),
+        );
+        assert_eq!(json(&resp), r#"{"me":null}"#);
+        assert_eq!(resp.errors.len(), 1);
+        assert_eq!(resp.errors[0].path, vec![ConstValue::String("me".into()), ConstValue::String("name".into())]);
+    }
+
+    #[test]
+    fn propagate_through_non_null_list_items() {
+        let mut error = ServerError::new("failed");
+        error.path = vec![
+            ConstValue::String("users".into()),
+            ConstValue::Number(1.into()),
+        ];
+        let resp = shape(
+            "{ users { id ... on User @include(if: true) { name } } }",
+            serde_json::json!({ "users": [{ "id": "1", "name": "a" }, { "id": "2" }] }),
+            vec![error],
+        );
+        assert_eq!(json(&resp), r#"{"users":null}"#);
+        assert_eq!(resp.errors.len(), 1);
+    }
+
+    #[test]
+    fn skipped_fields_are_not_completed() {
+        let resp = shape(
+            "{ me { id name @skip(if: true) } }",
+            serde_json::json!({ "me": { "id": "1" } }),
+            Vec::new(),
+        );
+        assert_eq!(json(&resp), r#"{"me":{"id":"1"}}"#);
+        assert!(resp.errors.is_empty());
+    }
+
+    #[test]
+    fn keep_selected_fields_in_selection_order() {
+        let resp = shape(
+            "{ me { kind: __typename name userId: id } node { ... on User { name } } }",
+            serde_json::json!({
+                "me": { "userId": "1", "name": "a", "__key1_id": "1", "__key1___typename": "User" },
+                "node": { "__typename": "User", "name": "b", "id": "2" }
+            }),
+            Vec::new(),
+        );
+        assert_eq!(
+            json(&resp),
+            r#"{"me":{"kind":"User","name":"a","userId":"1"},"node":{"name":"b"}}"#
+        );
+    }
+}
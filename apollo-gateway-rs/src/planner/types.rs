@@ -20,7 +20,17 @@ pub struct FieldRef<'a> {
 pub struct RequiredRef<'a> {
     pub prefix: usize,
     pub fields: &'a KeyFields,
-    pub requires: Option<&'a KeyFields>,
+    /// Fields of `@requires` resolved by the same service as the keys, by name.
+    pub requires: Vec<(&'a str, &'a KeyFields)>,
+}
+
+/// A field of `@requires` fetched from the service which owns it,
+/// selected as `__key{prefix}_{name}` so it becomes part of the representations of a later fetch.
+#[derive(Debug, Clone, Copy)]
+pub struct RequiredField<'a> {
+    pub prefix: usize,
+    pub name: &'a str,
+    pub fields: &'a KeyFields,
 }
 
 #[derive(Debug)]
@@ -28,6 +38,7 @@ pub enum SelectionRef<'a> {
     FieldRef(FieldRef<'a>),
     IntrospectionTypename,
     RequiredRef(RequiredRef<'a>),
+    RequiredField(RequiredField<'a>),
     InlineFragment {
         type_condition: Option<&'a str>,
         selection_set: SelectionRefSet<'a>,
@@ -51,6 +62,32 @@ pub struct FetchQuery<'a> {
     pub selection_set: SelectionRefSet<'a>,
 }
 
+impl<'a> FetchQuery<'a> {
+    /// Response keys of the fields selected at the root of this query.
+    pub fn response_keys(&self) -> Vec<&str> {
+        fn collect<'a>(selection_set: &'a SelectionRefSet<'_>, keys: &mut Vec<&'a str>) {
+            for selection in &selection_set.0 {
+                match selection {
+                    SelectionRef::FieldRef(field) => {
+                        let key = field.field.response_key().node.as_str();
+                        if !keys.contains(&key) {
+                            keys.push(key);
+                        }
+                    }
+                    SelectionRef::InlineFragment { selection_set, .. } => collect(selection_set, keys),
+                    SelectionRef::IntrospectionTypename
+                    | SelectionRef::RequiredRef(_)
+                    | SelectionRef::RequiredField(_) => {}
+                }
+            }
+        }
+
+        let mut keys = Vec::new();
+        collect(&self.selection_set, &mut keys);
+        keys
+    }
+}
+
 impl<'a> Display for FetchQuery<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self.entity_type {
@@ -121,6 +158,19 @@ fn stringify_directives(f: &mut Formatter<'_>, directives: &[Positioned<Directiv
 }
 
 fn stringify_key_fields(f: &mut Formatter<'_>, prefix: usize, fields: &KeyFields) -> FmtResult {
+    for (field_name, children) in fields.iter() {
+        write!(f, " ")?;
+        stringify_required_field(f, prefix, field_name, children)?;
+    }
+    Ok(())
+}
+
+fn stringify_required_field(
+    f: &mut Formatter<'_>,
+    prefix: usize,
+    name: &str,
+    children: &KeyFields,
+) -> FmtResult {
     fn stringify_key_fields_no_prefix(f: &mut Formatter<'_>, fields: &KeyFields) -> FmtResult {
         if fields.is_empty() {
             return Ok(());
@@ -129,18 +179,15 @@ fn stringify_key_fields(f: &mut Formatter<'_>, prefix: usize, fields: &KeyFields
         for (idx, (field_name, children)) in fields.iter().enumerate() {
             if idx > 0 {
                 write!(f, " ")?;
-                write!(f, "{}", field_name)?;
-                stringify_key_fields_no_prefix(f, children)?;
             }
+            write!(f, "{}", field_name)?;
+            stringify_key_fields_no_prefix(f, children)?;
         }
         write!(f, "}}")
     }
 
-    for (field_name, children) in fields.iter() {
-        write!(f, " __key{}_{}:{}", prefix, field_name, field_name)?;
-        stringify_key_fields_no_prefix(f, children)?;
-    }
-    Ok(())
+    write!(f, "__key{}_{}:{}", prefix, name, name)?;
+    stringify_key_fields_no_prefix(f, children)
 }
 
 fn stringify_selection_ref_set_rec(
@@ -177,10 +224,14 @@ fn stringify_selection_ref_set_rec(
             SelectionRef::RequiredRef(require_ref) => {
                 write!(f, "__key{}___typename:__typename", require_ref.prefix,)?;
                 stringify_key_fields(f, require_ref.prefix, require_ref.fields)?;
-                if let Some(requires) = require_ref.requires {
-                    stringify_key_fields(f, require_ref.prefix, requires)?;
+                for (name, children) in &require_ref.requires {
+                    write!(f, " ")?;
+                    stringify_required_field(f, require_ref.prefix, name, children)?;
                 }
             }
+            SelectionRef::RequiredField(required) => {
+                stringify_required_field(f, required.prefix, required.name, required.fields)?;
+            }
             SelectionRef::InlineFragment {
                 type_condition,
                 selection_set,
@@ -238,13 +289,17 @@ impl<'a> RootGroup<'a> for MutationRootGroup<'a> {
     }
 }
 
+#[derive(Debug)]
 pub struct FetchEntity<'a> {
     pub parent_type: &'a MetaType,
     pub prefix: usize,
+    pub keys: &'a KeyFields,
     pub fields: Vec<&'a Field>,
+    /// Fields of `@requires` this fetch resolves for the representations of later fetches.
+    pub required: Vec<RequiredField<'a>>,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct FetchEntityKey<'a> {
     pub service: &'a str,
     pub path: ResponsePath<'a>,
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use parser::types::{Directive, ExecutableDocument, OperationType, Selection, SelectionSet, VariableDefinition};
+use parser::Positioned;
+use value::Variables;
+
+use crate::schema::{ComposedSchema, TypeExt};
+
+use super::builder::{get_operation, is_skipped};
+
+/// Count the fields of the operation by `Type.field`, fields of fragments are counted for the type condition of the fragment.
+///
+/// Introspection fields and selections excluded by `@skip` or `@include` are not counted, the document must have been validated.
+pub fn field_usage(
+    schema: &ComposedSchema,
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    variables: &Variables,
+) -> HashMap<String, u64> {
+    let operation = get_operation(document, operation_name);
+    let root_type = match operation.node.ty {
+        OperationType::Query => Some(schema.query_type()),
+        OperationType::Mutation => schema.mutation_type(),
+        OperationType::Subscription => schema.subscription_type(),
+    };
+    let mut usage = HashMap::new();
+    if let Some(root_type) = root_type {
+        let collector = Collector {
+            schema,
+            document,
+            variables,
+            variable_definitions: &operation.node.variable_definitions,
+        };
+        collector.collect_fields(root_type, &operation.node.selection_set, &mut usage);
+    }
+    usage
+}
+
+struct Collector<'a> {
+    schema: &'a ComposedSchema,
+    document: &'a ExecutableDocument,
+    variables: &'a Variables,
+    variable_definitions: &'a [Positioned<VariableDefinition>],
+}
+
+impl Collector<'_> {
+    fn collect_fields(&self, parent_type: &str, selection_set: &Positioned<SelectionSet>, usage: &mut HashMap<String, u64>) {
+        for selection in &selection_set.node.items {
+            match &selection.node {
+                Selection::Field(field) => {
+                    let name = field.node.name.node.as_str();
+                    if name.starts_with("__") || self.is_skipped(&field.node.directives) {
+                        continue;
+                    }
+                    *usage.entry(format!("{}.{}", parent_type, name)).or_default() += 1;
+                    let field_type = self
+                        .schema
+                        .types
+                        .get(parent_type)
+                        .and_then(|ty| ty.field_by_name(name))
+                        .map(|field| field.ty.concrete_typename());
+                    if let Some(field_type) = field_type {
+                        self.collect_fields(field_type, &field.node.selection_set, usage);
+                    }
+                }
+                Selection::FragmentSpread(fragment_spread) => {
+                    if self.is_skipped(&fragment_spread.node.directives) {
+                        continue;
+                    }
+                    if let Some(fragment) = self.document.fragments.get(&fragment_spread.node.fragment_name.node) {
+                        let type_condition = fragment.node.type_condition.node.on.node.as_str();
+                        self.collect_fields(type_condition, &fragment.node.selection_set, usage);
+                    }
+                }
+                Selection::InlineFragment(inline_fragment) => {
+                    if self.is_skipped(&inline_fragment.node.directives) {
+                        continue;
+                    }
+                    let type_condition = inline_fragment
+                        .node
+                        .type_condition
+                        .as_ref()
+                        .map(|type_condition| type_condition.node.on.node.as_str())
+                        .unwrap_or(parent_type);
+                    self.collect_fields(type_condition, &inline_fragment.node.selection_set, usage);
+                }
+            }
+        }
+    }
+
+    fn is_skipped(&self, directives: &[Positioned<Directive>]) -> bool {
+        is_skipped(directives, self.variables, self.variable_definitions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDL: &str = r#"
+        type Query {
+            me: User
+            node: Node
+        }
+
+        interface Node {
+            id: ID!
+        }
+
+        type User implements Node {
+            id: ID!
+            name: String!
+            legacyId: String @deprecated
+        }
+    "#;
+
+    #[test]
+    fn count_fields_of_fragments() {
+        let schema = ComposedSchema::combine([(
+            "users".to_string(),
+            parser::parse_schema(SDL).expect("valid schema"),
+        )])
+        .expect("valid schema");
+        let document = parser::parse_query(
+            "{ me { ...UserFields legacyId } node { __typename id ... on User { legacyId } } } fragment UserFields on User { id name }",
+        )
+        .expect("valid query");
+        let usage = field_usage(&schema, &document, None, &Variables::default());
+        assert_eq!(usage.get("Query.me"), Some(&1));
+        assert_eq!(usage.get("Query.node"), Some(&1));
+        assert_eq!(usage.get("User.legacyId"), Some(&2));
+        assert_eq!(usage.get("User.name"), Some(&1));
+        assert_eq!(usage.get("Node.id"), Some(&1));
+        assert!(!usage.keys().any(|field| field.contains("__typename")));
+    }
+
+    #[test]
+    fn skip_excluded_selections() {
+        let schema = ComposedSchema::combine([(
+            "users".to_string(),
+            parser::parse_schema(SDL).expect("valid schema"),
+        )])
+        .expect("valid schema");
+        let document = parser::parse_query(
+            "query($withName: Boolean!) { me { id name @include(if: $withName) ... on User @skip(if: true) { legacyId } ...UserFields @include(if: false) } } fragment UserFields on User { legacyId }",
+        )
+        .expect("valid query");
+        let usage = field_usage(&schema, &document, None, &Variables::from_json(serde_json::json!({ "withName": false })));
+        assert_eq!(usage.get("User.id"), Some(&1));
+        assert_eq!(usage.get("User.name"), None);
+        assert_eq!(usage.get("User.legacyId"), None);
+
+        let usage = field_usage(&schema, &document, None, &Variables::from_json(serde_json::json!({ "withName": true })));
+        assert_eq!(usage.get("User.name"), Some(&1));
+    }
+}
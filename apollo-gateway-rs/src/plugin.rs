@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use parser::types::ExecutableDocument;
+
+use crate::datasource::Context;
+use crate::planner::{RequestData, Response, RootNode};
+
+/// Implement Plugin to hook into every stage of an operation executed by the gateway.
+///
+/// Plugins are registered with `GatewayServerBuilder::with_plugin` and called in the order they were registered.
+/// Returning `Err(response)` from a stage short-circuits the operation, the response is sent to the client
+/// and later plugins are not called for that stage.
+#[async_trait::async_trait]
+pub trait Plugin: Send + Sync + 'static {
+    /// Called before the request is parsed.
+    #[allow(unused_variables)]
+    async fn request_received(&self, request: &mut RequestData, ctx: &Context) -> Result<(), Response> {
+        Ok(())
+    }
+    /// Called after the query has been parsed.
+    #[allow(unused_variables)]
+    async fn request_parsed(&self, document: &ExecutableDocument, ctx: &Context) -> Result<(), Response> {
+        Ok(())
+    }
+    /// Called after the query has been validated against the composed schema.
+    #[allow(unused_variables)]
+    async fn request_validated(&self, document: &ExecutableDocument, ctx: &Context) -> Result<(), Response> {
+        Ok(())
+    }
+    /// Called with the query plan before it is executed.
+    #[allow(unused_variables)]
+    async fn query_planned(&self, plan: &RootNode<'_>, ctx: &Context) -> Result<(), Response> {
+        Ok(())
+    }
+    /// Called before a request is sent to a subgraph, `Err(response)` is used as the response of the subgraph.
+    /// Requests of subscriptions to subgraphs are not passed to this hook.
+    #[allow(unused_variables)]
+    async fn will_fetch(&self, service: &str, request: &mut RequestData, ctx: &Context) -> Result<(), Response> {
+        Ok(())
+    }
+    /// Called with the result of a request to a subgraph.
+    #[allow(unused_variables)]
+    async fn did_fetch(&self, service: &str, response: &mut anyhow::Result<Response>, ctx: &Context) {}
+    /// Called with every response of queries and mutations before it is sent to the client.
+    #[allow(unused_variables)]
+    async fn response_ready(&self, response: &mut Response, ctx: &Context) {}
+    /// Called with every event of a subscription before it is sent to the client.
+    #[allow(unused_variables)]
+    async fn subscription_event(&self, response: &mut Response, ctx: &Context) {}
+}
+
+/// Registered plugins, each stage runs them in order.
+#[derive(Clone, Default)]
+pub(crate) struct Plugins(Arc<[Arc<dyn Plugin>]>);
+
+impl From<Vec<Arc<dyn Plugin>>> for Plugins {
+    fn from(plugins: Vec<Arc<dyn Plugin>>) -> Self {
+        Self(plugins.into())
+    }
+}
+
+impl Plugins {
+    pub async fn request_received(&self, request: &mut RequestData, ctx: &Context) -> Result<(), Response> {
+        for plugin in self.0.iter() {
+            plugin.request_received(request, ctx).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn request_parsed(&self, document: &ExecutableDocument, ctx: &Context) -> Result<(), Response> {
+        for plugin in self.0.iter() {
+            plugin.request_parsed(document, ctx).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn request_validated(&self, document: &ExecutableDocument, ctx: &Context) -> Result<(), Response> {
+        for plugin in self.0.iter() {
+            plugin.request_validated(document, ctx).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn query_planned(&self, plan: &RootNode<'_>, ctx: &Context) -> Result<(), Response> {
+        for plugin in self.0.iter() {
+            plugin.query_planned(plan, ctx).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn will_fetch(&self, service: &str, request: &mut RequestData, ctx: &Context) -> Result<(), Response> {
+        for plugin in self.0.iter() {
+            plugin.will_fetch(service, request, ctx).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn did_fetch(&self, service: &str, response: &mut anyhow::Result<Response>, ctx: &Context) {
+        for plugin in self.0.iter() {
+            plugin.did_fetch(service, response, ctx).await;
+        }
+    }
+
+    pub async fn response_ready(&self, response: &mut Response, ctx: &Context) {
+        for plugin in self.0.iter() {
+            plugin.response_ready(response, ctx).await;
+        }
+    }
+
+    pub async fn subscription_event(&self, response: &mut Response, ctx: &Context) {
+        for plugin in self.0.iter() {
+            plugin.subscription_event(response, ctx).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use http::HeaderMap;
+    use serde_json::json;
+
+    use crate::testing::MockSource;
+    use crate::{GatewayServer, ResponseCache};
+    use super::*;
+
+    struct Recorder {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        short_circuit: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Plugin for Recorder {
+        async fn request_received(&self, _: &mut RequestData, _: &Context) -> Result<(), Response> {
+            if let Ok(mut calls) = self.calls.lock() {
+                calls.push(self.name);
+            }
+            if self.short_circuit {
+                return Err(Response::error(self.name));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn short_circuit_skips_later_plugins() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let plugins = Plugins::from(
+            [("a", false), ("b", true), ("c", false)]
+                .into_iter()
+                .map(|(name, short_circuit)| {
+                    Arc::new(Recorder { name, calls: calls.clone(), short_circuit }) as Arc<dyn Plugin>
+                })
+                .collect::<Vec<_>>(),
+        );
+        let res = plugins
+            .request_received(&mut RequestData::new("{ a }"), &Context::default())
+            .await;
+        assert_eq!(res.err().map(|resp| resp.errors[0].message.clone()).as_deref(), Some("b"));
+        assert_eq!(*calls.lock().unwrap_or_else(std::sync::PoisonError::into_inner), vec!["a", "b"]);
+    }
+
+    /// Rejects operations planned for clients which send the `x-blocked` header.
+    struct Blocker;
+
+    #[async_trait::async_trait]
+    impl Plugin for Blocker {
+        async fn query_planned(&self, _: &RootNode<'_>, ctx: &Context) -> Result<(), Response> {
+            if ctx.headers().contains_key("x-blocked") {
+                return Err(Response::error("Blocked."));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_cached_operations() {
+        const ACCOUNTS: &str = r#"
+            type Query {
+                me: User @cacheControl(maxAge: 60)
+            }
+
+            type User {
+                name: String
+            }
+        "#;
+        let accounts = MockSource::new("accounts", ACCOUNTS).query_value("me", json!({ "name": "Ada" }));
+        let server = GatewayServer::builder()
+            .with_middleware_source(accounts.clone())
+            .with_response_cache(ResponseCache::in_memory(16))
+            .with_plugin(Blocker)
+            .build();
+        server.update_schema().await.expect("composed schema");
+
+        let resp = server.execute(RequestData::new("{ me { name } }"), Default::default()).await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        let resp = server.execute(RequestData::new("{ me { name } }"), Default::default()).await;
+        assert!(resp.headers.contains_key("age"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-blocked", "1".parse().unwrap());
+        let resp = server.execute(RequestData::new("{ me { name } }"), Context::new(headers)).await;
+        assert!(resp.data.is_none());
+        assert_eq!(resp.errors[0].message, "Blocked.");
+        assert_eq!(accounts.requests().len(), 1);
+    }
+}
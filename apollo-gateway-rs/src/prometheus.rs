@@ -0,0 +1,85 @@
+//! Prometheus exposition of the gateway metrics.
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::sdk::export::metrics::aggregation;
+use opentelemetry::sdk::metrics::{controllers, processors, selectors};
+use opentelemetry_prometheus::PrometheusExporter;
+use prometheus::{Encoder, Registry, TextEncoder};
+
+/// Bucket boundaries in seconds of the duration histograms.
+const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Exports the metrics of the gateway to a Prometheus registry.
+///
+/// Pass `meter_provider` to `GatewayServerBuilder::with_meter_provider` to export the metrics of the gateway.
+#[derive(Clone)]
+pub struct PrometheusMetrics {
+    exporter: PrometheusExporter,
+}
+
+impl PrometheusMetrics {
+    /// Install a global meter provider exporting to a new registry.
+    pub fn install() -> anyhow::Result<Self> {
+        Self::install_with_registry(Registry::new())
+    }
+
+    /// Install a global meter provider exporting to your registry.
+    pub fn install_with_registry(registry: Registry) -> anyhow::Result<Self> {
+        let controller = controllers::basic(processors::factory(
+            selectors::simple::histogram(DURATION_BUCKETS),
+            aggregation::cumulative_temporality_selector(),
+        ))
+        .build();
+        let exporter = opentelemetry_prometheus::exporter(controller)
+            .with_registry(registry)
+            .try_init()?;
+        Ok(Self { exporter })
+    }
+
+    /// Meter provider exporting to the registry
+    pub fn meter_provider(&self) -> anyhow::Result<impl MeterProvider> {
+        Ok(self.exporter.meter_provider()?)
+    }
+
+    pub fn registry(&self) -> &Registry {
+        self.exporter.registry()
+    }
+
+    /// Content type of `render`
+    pub fn content_type(&self) -> String {
+        TextEncoder::new().format_type().to_string()
+    }
+
+    /// Metrics in the Prometheus text format
+    pub fn render(&self) -> anyhow::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry().gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::Context;
+
+    use super::*;
+
+    #[test]
+    fn render_recorded_metrics() {
+        let metrics = PrometheusMetrics::install().unwrap();
+        let counter = metrics.meter_provider().unwrap().meter("test").u64_counter("test.requests").init();
+        counter.add(&Context::current(), 3, &[]);
+        let rendered = metrics.render().unwrap();
+        assert!(rendered.contains("test_requests_total{"), "{}", rendered);
+    }
+
+    #[tokio::test]
+    async fn render_gateway_metrics() {
+        let metrics = PrometheusMetrics::install().unwrap();
+        let server = crate::GatewayServer::builder()
+            .with_meter_provider(&metrics.meter_provider().unwrap())
+            .build();
+        server.execute(crate::RequestData::new("{ me { id } }"), crate::Context::new(Default::default())).await;
+        let rendered = metrics.render().unwrap();
+        assert!(rendered.contains("graphgate_requests_total{"), "{}", rendered);
+    }
+}
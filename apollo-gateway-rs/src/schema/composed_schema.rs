@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 
 use indexmap::{IndexMap, IndexSet};
@@ -8,6 +8,7 @@ use parser::types::{
     SelectionSet, ServiceDocument, Type, TypeDefinition, TypeSystemDefinition, UnionType,
 };
 use parser::{Positioned};
+use serde::{Deserialize, Serialize};
 use value::{ConstValue, Name};
 
 use crate::schema::{CombineError, TypeExt};
@@ -42,8 +43,44 @@ pub struct MetaField {
     pub deprecation: Deprecation,
 
     pub service: Option<String>,
+    /// Marked `@shareable`, the field may be resolved by several services.
+    pub shareable: bool,
+    /// Services other than `service` (or the owner of the type) which resolve the `@shareable` field.
+    pub alternatives: Vec<String>,
     pub requires: Option<KeyFields>,
     pub provides: Option<KeyFields>,
+    pub cache_control: Option<CacheControl>,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Serialize, Deserialize)]
+pub enum CacheScope {
+    Public,
+    Private,
+}
+
+/// Arguments of `@cacheControl(maxAge: Int, scope: CacheControlScope, inheritMaxAge: Boolean)`
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    pub scope: Option<CacheScope>,
+    pub inherit_max_age: bool,
+}
+
+impl CacheControl {
+    /// The most restrictive of two hints, used when several services annotate the same type.
+    pub fn restrict(self, other: CacheControl) -> CacheControl {
+        CacheControl {
+            max_age: match (self.max_age, other.max_age) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            scope: match (self.scope, other.scope) {
+                (Some(CacheScope::Private), _) | (_, Some(CacheScope::Private)) => Some(CacheScope::Private),
+                (a, b) => a.or(b),
+            },
+            inherit_max_age: self.inherit_max_age && other.inherit_max_age,
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -89,6 +126,9 @@ pub struct MetaType {
     pub kind: TypeKind,
     pub owner: Option<String>,
     pub keys: HashMap<String, Vec<KeyFields>>,
+    /// Services whose SDL defines or extends the type.
+    pub services: IndexSet<String>,
+    pub cache_control: Option<CacheControl>,
 
     pub implements: IndexSet<Name>,
     pub fields: IndexMap<Name, MetaField>,
@@ -170,6 +210,8 @@ pub struct ComposedSchema {
     pub subscription_type: Option<Name>,
     pub types: IndexMap<Name, MetaType>,
     pub directives: HashMap<Name, MetaDirective>,
+    /// Hash of the subgraph SDLs this schema was composed from.
+    pub version: Option<String>,
 }
 
 impl ComposedSchema {
@@ -189,6 +231,8 @@ impl ComposedSchema {
                     kind: TypeKind::Object,
                     owner: None,
                     keys: Default::default(),
+                    services: Default::default(),
+                    cache_control: None,
                     implements: Default::default(),
                     fields: Default::default(),
                     possible_types: Default::default(),
@@ -202,104 +246,132 @@ impl ComposedSchema {
         composed_schema.mutation_type = Some(Name::new("Mutation"));
         composed_schema.subscription_type = Some(Name::new("Subscription"));
 
+        // Values of enums and fields of input objects by service, reconciled once every service is merged.
+        let mut enum_values: HashMap<Name, IndexMap<String, IndexSet<Name>>> = HashMap::new();
+        let mut input_fields: HashMap<Name, IndexMap<String, IndexSet<Name>>> = HashMap::new();
+
         for (service, doc) in federation_sdl {
             for definition in doc.definitions {
-                match definition {
-                    TypeSystemDefinition::Type(type_definition) => {
-                        if let types::TypeKind::Object(ObjectType { implements, fields }) =
-                            type_definition.node.kind
-                        {
-                            let name = type_definition.node.name.node.clone();
-                            let description = type_definition
-                                .node
-                                .description
-                                .map(|description| description.node);
-                            let is_extend =
-                                type_definition.node.extend || root_objects.contains(&&*name);
-                            let meta_type = composed_schema
-                                .types
-                                .entry(name.clone())
-                                .or_insert_with(|| MetaType {
-                                    description,
-                                    name,
-                                    kind: TypeKind::Object,
-                                    owner: None,
-                                    keys: Default::default(),
-                                    implements: Default::default(),
-                                    fields: Default::default(),
-                                    possible_types: Default::default(),
-                                    enum_values: Default::default(),
-                                    input_fields: Default::default(),
-                                });
-
-                            if !is_extend {
-                                meta_type.owner = Some(service.clone());
-                            };
-
-                            for directive in type_definition.node.directives {
-                                if directive.node.name.node.as_str() == "key" {
-                                    if let Some(fields) =
-                                        get_argument_str(&directive.node.arguments, "fields")
-                                    {
-                                        if let Some(selection_set) =
-                                            parse_fields(fields.node).map(|selection_set| {
-                                                Positioned::new(selection_set, directive.pos)
-                                            })
-                                        {
-                                            meta_type
-                                                .keys
-                                                .entry(service.clone())
-                                                .or_default()
-                                                .push(convert_key_fields(selection_set.node));
-                                        }
-                                    }
-                                }
-                            }
+                if let TypeSystemDefinition::Type(type_definition) = definition {
+                    if let types::TypeKind::Object(ObjectType { implements, fields }) =
+                        type_definition.node.kind
+                    {
+                        let name = type_definition.node.name.node.clone();
+                        let description = type_definition
+                            .node
+                            .description
+                            .map(|description| description.node);
+                        let is_extend =
+                            type_definition.node.extend || root_objects.contains(&&*name);
+                        let meta_type = composed_schema
+                            .types
+                            .entry(name.clone())
+                            .or_insert_with(|| MetaType {
+                                description,
+                                name,
+                                kind: TypeKind::Object,
+                                owner: None,
+                                keys: Default::default(),
+                                services: Default::default(),
+                                cache_control: None,
+                                implements: Default::default(),
+                                fields: Default::default(),
+                                possible_types: Default::default(),
+                                enum_values: Default::default(),
+                                input_fields: Default::default(),
+                            });
+
+                        meta_type.services.insert(service.clone());
+
+                        // A `@shareable` type may be defined by several services, the first one owns it.
+                        let is_owner = !is_extend
+                            && *meta_type.owner.get_or_insert_with(|| service.clone()) == service;
+                        let type_shareable = has_directive(&type_definition.node.directives, "shareable");
+
+                        if let Some(cache_control) = get_cache_control(&type_definition.node.directives) {
+                            meta_type.cache_control = Some(match meta_type.cache_control {
+                                Some(current) => current.restrict(cache_control),
+                                None => cache_control,
+                            });
+                        }
 
-                            meta_type
-                                .implements
-                                .extend(implements.into_iter().map(|implement| implement.node));
-
-                            for field in fields {
-                                if is_extend {
-                                    let is_external =
-                                        has_directive(&field.node.directives, "external");
-                                    if is_external {
-                                        continue;
-                                    }
+                        let keys = convert_keys(&type_definition.node.directives);
+                        if !keys.is_empty() {
+                            meta_type.keys.entry(service.clone()).or_default().extend(keys);
+                        }
+
+                        meta_type
+                            .implements
+                            .extend(implements.into_iter().map(|implement| implement.node));
+
+                        for field in fields {
+                            if is_extend {
+                                let is_external =
+                                    has_directive(&field.node.directives, "external");
+                                if is_external {
+                                    continue;
                                 }
+                            }
 
-                                if meta_type.fields.contains_key(&field.node.name.node) {
+                            let mut meta_field = convert_field_definition(field.node);
+                            meta_field.shareable |= type_shareable;
+                            if let Some(existing) = meta_type.fields.get_mut(&meta_field.name) {
+                                if !existing.shareable || !meta_field.shareable || existing.ty != meta_field.ty {
                                     return Err(CombineError::FieldConflicted {
                                         type_name: type_definition.node.name.node.to_string(),
-                                        field_name: field.node.name.node.to_string(),
+                                        field_name: meta_field.name.to_string(),
                                     });
                                 }
-                                let mut meta_field = convert_field_definition(field.node);
-                                if is_extend {
-                                    meta_field.service = Some(service.clone());
-                                }
-                                meta_type.fields.insert(meta_field.name.clone(), meta_field);
+                                existing.alternatives.push(service.clone());
+                                continue;
                             }
-                        } else {
-                            let meta_type = convert_type_definition(type_definition.node);
-                            if let Some(meta_type2) = composed_schema.types.get(&meta_type.name) {
-                                if meta_type2 != &meta_type {
-                                    return Err(CombineError::DefinitionConflicted {
-                                        type_name: meta_type.name.to_string(),
-                                    });
+                            if !is_owner {
+                                meta_field.service = Some(service.clone());
+                            }
+                            meta_type.fields.insert(meta_field.name.clone(), meta_field);
+                        }
+                    } else {
+                        let keys = convert_keys(&type_definition.node.directives);
+                        let mut meta_type = convert_type_definition(type_definition.node);
+                        meta_type.services.insert(service.clone());
+                        if !keys.is_empty() {
+                            meta_type.keys.insert(service.clone(), keys);
+                        }
+                        match meta_type.kind {
+                            TypeKind::Enum => enum_values
+                                .entry(meta_type.name.clone())
+                                .or_default()
+                                .entry(service.clone())
+                                .or_default()
+                                .extend(meta_type.enum_values.keys().cloned()),
+                            TypeKind::InputObject => input_fields
+                                .entry(meta_type.name.clone())
+                                .or_default()
+                                .entry(service.clone())
+                                .or_default()
+                                .extend(meta_type.input_fields.keys().cloned()),
+                            _ => {}
+                        }
+
+                        match composed_schema.types.get_mut(&meta_type.name) {
+                            Some(existing) => merge_type_definition(existing, meta_type, &service)?,
+                            None => {
+                                if meta_type.kind == TypeKind::Interface && meta_type.owner.is_none() {
+                                    meta_type.owner = Some(service.clone());
                                 }
+                                composed_schema
+                                    .types
+                                    .insert(meta_type.name.clone(), meta_type);
                             }
-                            composed_schema
-                                .types
-                                .insert(meta_type.name.clone(), meta_type);
                         }
                     }
-                    _ => {}
                 }
             }
         }
 
+        merge_enum_values(&mut composed_schema, enum_values)?;
+        intersect_input_fields(&mut composed_schema, input_fields)?;
+
         if let Some(mutation) = composed_schema.types.get("Mutation") {
             if mutation.fields.is_empty() {
                 composed_schema.types.shift_remove("Mutation");
@@ -350,6 +422,146 @@ impl ComposedSchema {
     }
 }
 
+/// Merge the definition of an interface, union, enum, input object or scalar by another service into the composed type.
+///
+/// Interfaces and unions get the fields and members of every service, the values of enums and fields of input objects
+/// are reconciled by `merge_enum_values` and `intersect_input_fields` once every service is merged.
+fn merge_type_definition(existing: &mut MetaType, incoming: MetaType, service: &str) -> Result<(), CombineError> {
+    let type_name = incoming.name.to_string();
+    if existing.kind != incoming.kind {
+        return Err(CombineError::DefinitionConflicted { type_name });
+    }
+
+    existing.services.extend(incoming.services);
+    if existing.description.is_none() {
+        existing.description = incoming.description;
+    }
+    for (service, keys) in incoming.keys {
+        existing.keys.entry(service).or_default().extend(keys);
+    }
+
+    match incoming.kind {
+        TypeKind::Scalar | TypeKind::Object => {}
+        TypeKind::Interface => {
+            existing.implements.extend(incoming.implements);
+            for (name, mut field) in incoming.fields {
+                match existing.fields.get(&name) {
+                    Some(current) if current.ty != field.ty => {
+                        return Err(CombineError::FieldConflicted {
+                            type_name,
+                            field_name: name.to_string(),
+                        });
+                    }
+                    Some(_) => {}
+                    None => {
+                        field.service = Some(service.to_string());
+                        existing.fields.insert(name, field);
+                    }
+                }
+            }
+        }
+        TypeKind::Union => existing.possible_types.extend(incoming.possible_types),
+        TypeKind::Enum => {
+            for (name, value) in incoming.enum_values {
+                existing.enum_values.entry(name).or_insert(value);
+            }
+        }
+        TypeKind::InputObject => {
+            for (name, field) in incoming.input_fields {
+                match existing.input_fields.get(&name) {
+                    Some(current) if current.ty != field.ty => {
+                        return Err(CombineError::FieldConflicted {
+                            type_name,
+                            field_name: name.to_string(),
+                        });
+                    }
+                    Some(_) => {}
+                    None => {
+                        existing.input_fields.insert(name, field);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reconcile the values of enums defined by several services.
+///
+/// An enum only used by output fields gets the values of every service, an enum only used by arguments and input fields
+/// the values every service accepts, and an enum used by both must have the same values in every service.
+fn merge_enum_values(
+    composed_schema: &mut ComposedSchema,
+    values_by_service: HashMap<Name, IndexMap<String, IndexSet<Name>>>,
+) -> Result<(), CombineError> {
+    let mut inputs = HashSet::new();
+    let mut outputs = HashSet::new();
+    for ty in composed_schema.types.values() {
+        for field in ty.fields.values() {
+            outputs.insert(field.ty.concrete_typename().to_string());
+            for argument in field.arguments.values() {
+                inputs.insert(argument.ty.concrete_typename().to_string());
+            }
+        }
+        for field in ty.input_fields.values() {
+            inputs.insert(field.ty.concrete_typename().to_string());
+        }
+    }
+
+    for (type_name, services) in values_by_service {
+        if services.len() < 2 {
+            continue;
+        }
+        let ty = match composed_schema.types.get_mut(&type_name) {
+            Some(ty) => ty,
+            None => continue,
+        };
+        let in_all = |value: &Name| services.values().all(|values| values.contains(value));
+        match (inputs.contains(type_name.as_str()), outputs.contains(type_name.as_str())) {
+            (true, true) => {
+                if ty.enum_values.keys().any(|value| !in_all(value)) {
+                    return Err(CombineError::EnumValuesMismatch {
+                        type_name: type_name.to_string(),
+                    });
+                }
+            }
+            (true, false) => ty.enum_values.retain(|value, _| in_all(value)),
+            (false, _) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Keep the fields of input objects which every service defining them accepts,
+/// a required field which some of them do not define is an error.
+fn intersect_input_fields(
+    composed_schema: &mut ComposedSchema,
+    fields_by_service: HashMap<Name, IndexMap<String, IndexSet<Name>>>,
+) -> Result<(), CombineError> {
+    for (type_name, services) in fields_by_service {
+        if services.len() < 2 {
+            continue;
+        }
+        let ty = match composed_schema.types.get_mut(&type_name) {
+            Some(ty) => ty,
+            None => continue,
+        };
+        let in_all = |name: &Name| services.values().all(|fields| fields.contains(name));
+        if let Some((name, _)) = ty
+            .input_fields
+            .iter()
+            .find(|(name, field)| !in_all(name) && !field.ty.nullable && field.default_value.is_none())
+        {
+            return Err(CombineError::FieldConflicted {
+                type_name: type_name.to_string(),
+                field_name: name.to_string(),
+            });
+        }
+        ty.input_fields.retain(|name, _| in_all(name));
+    }
+    Ok(())
+}
+
 fn get_argument<'a>(
     arguments: &'a [(Positioned<Name>, Positioned<ConstValue>)],
     name: &str,
@@ -389,6 +601,8 @@ fn convert_type_definition(definition: TypeDefinition) -> MetaType {
         kind: TypeKind::Scalar,
         owner: None,
         keys: Default::default(),
+        services: Default::default(),
+        cache_control: get_cache_control(&definition.directives),
         implements: Default::default(),
         fields: Default::default(),
         possible_types: Default::default(),
@@ -505,8 +719,11 @@ fn convert_field_definition(definition: types::FieldDefinition) -> MetaField {
         ty: definition.ty.node,
         deprecation: get_deprecated(&definition.directives),
         service: None,
+        shareable: has_directive(&definition.directives, "shareable"),
+        alternatives: Vec::new(),
         requires: None,
         provides: None,
+        cache_control: get_cache_control(&definition.directives),
     };
 
     for directive in definition.directives {
@@ -533,6 +750,18 @@ fn convert_field_definition(definition: types::FieldDefinition) -> MetaField {
     field_definition
 }
 
+/// `@key(fields:)` directives of a type in the SDL of a service.
+fn convert_keys(directives: &[Positioned<ConstDirective>]) -> Vec<KeyFields> {
+    directives
+        .iter()
+        .filter(|directive| directive.node.name.node.as_str() == "key")
+        .filter(|directive| get_argument(&directive.node.arguments, "service").is_none())
+        .filter_map(|directive| get_argument_str(&directive.node.arguments, "fields"))
+        .filter_map(|fields| parse_fields(fields.node))
+        .map(convert_key_fields)
+        .collect()
+}
+
 fn convert_key_fields(selection_set: SelectionSet) -> KeyFields {
     KeyFields(
         selection_set
@@ -596,6 +825,28 @@ fn get_deprecated(directives: &[Positioned<ConstDirective>]) -> Deprecation {
         .unwrap_or(Deprecation::NoDeprecated)
 }
 
+fn get_cache_control(directives: &[Positioned<ConstDirective>]) -> Option<CacheControl> {
+    let directive = directives
+        .iter()
+        .find(|directive| directive.node.name.node.as_str() == "cacheControl")?;
+    let arguments = &directive.node.arguments;
+    Some(CacheControl {
+        max_age: match get_argument(arguments, "maxAge").map(|value| &value.node) {
+            Some(ConstValue::Number(max_age)) => max_age.as_u64(),
+            _ => None,
+        },
+        scope: match get_argument(arguments, "scope").map(|value| &value.node) {
+            Some(ConstValue::Enum(scope)) if scope.as_str() == "PRIVATE" => Some(CacheScope::Private),
+            Some(ConstValue::Enum(scope)) if scope.as_str() == "PUBLIC" => Some(CacheScope::Public),
+            _ => None,
+        },
+        inherit_max_age: matches!(
+            get_argument(arguments, "inheritMaxAge").map(|value| &value.node),
+            Some(ConstValue::Boolean(true))
+        ),
+    })
+}
+
 fn has_directive(directives: &[Positioned<ConstDirective>], name: &str) -> bool {
     directives
         .iter()
@@ -655,8 +906,11 @@ fn finish_schema(composed_schema: &mut ComposedSchema) {
                 ty: Type::new("__Type").unwrap(),
                 deprecation: Deprecation::NoDeprecated,
                 service: None,
+                shareable: false,
+                alternatives: Vec::new(),
                 requires: None,
                 provides: None,
+                cache_control: None,
             },
         );
 
@@ -670,8 +924,11 @@ fn finish_schema(composed_schema: &mut ComposedSchema) {
                 ty: Type::new("__Schema!").unwrap(),
                 deprecation: Deprecation::NoDeprecated,
                 service: None,
+                shareable: false,
+                alternatives: Vec::new(),
                 requires: None,
                 provides: None,
+                cache_control: None,
             },
         );
 
@@ -685,8 +942,11 @@ fn finish_schema(composed_schema: &mut ComposedSchema) {
                 ty: Type::new("String!").unwrap(),
                 deprecation: Deprecation::NoDeprecated,
                 service: None,
+                shareable: false,
+                alternatives: Vec::new(),
                 requires: None,
                 provides: None,
+                cache_control: None,
             },
         );
     }
@@ -708,3 +968,91 @@ fn finish_schema(composed_schema: &mut ComposedSchema) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combine(subgraphs: &[(&str, &str)]) -> Result<ComposedSchema, CombineError> {
+        ComposedSchema::combine(
+            subgraphs
+                .iter()
+                .map(|(service, sdl)| (service.to_string(), parser::parse_schema(sdl).unwrap())),
+        )
+    }
+
+    #[test]
+    fn merge_abstract_types() {
+        let schema = combine(&[
+            (
+                "books",
+                r#"
+                type Query { media: [Media] search: [Result] }
+                interface Media @key(fields: "id") { id: ID! }
+                type Book implements Media @key(fields: "id") { id: ID! title: String }
+                union Result = Book
+                "#,
+            ),
+            (
+                "movies",
+                r#"
+                interface Media @key(fields: "id") { id: ID! title: String }
+                type Movie implements Media @key(fields: "id") { id: ID! title: String }
+                union Result = Movie
+                "#,
+            ),
+        ])
+        .unwrap();
+
+        let media = &schema.types["Media"];
+        assert_eq!(media.fields.keys().map(Name::as_str).collect::<Vec<_>>(), ["id", "title"]);
+        assert_eq!(media.fields["title"].service.as_deref(), Some("movies"));
+        assert_eq!(media.possible_types.iter().map(Name::as_str).collect::<Vec<_>>(), ["Book", "Movie"]);
+        assert!(media.keys.contains_key("books") && media.keys.contains_key("movies"));
+        assert_eq!(schema.types["Result"].possible_types.len(), 2);
+        assert_eq!(schema.types["Movie"].services.iter().collect::<Vec<_>>(), ["movies"]);
+
+        let conflict = combine(&[
+            ("books", "interface Media { id: ID! }"),
+            ("movies", "interface Media { id: String }"),
+        ]);
+        assert!(matches!(conflict, Err(CombineError::FieldConflicted { .. })));
+    }
+
+    #[test]
+    fn merge_enum_values_by_usage() {
+        let schema = combine(&[
+            ("a", "type Query { status: Status byColor(color: Color): Int } enum Status { OPEN } enum Color { RED GREEN }"),
+            ("b", "enum Status { CLOSED } enum Color { RED BLUE }"),
+        ])
+        .unwrap();
+        let values = |name: &str| schema.types[name].enum_values.keys().map(Name::as_str).collect::<Vec<_>>();
+        assert_eq!(values("Status"), ["OPEN", "CLOSED"]);
+        assert_eq!(values("Color"), ["RED"]);
+
+        let conflict = combine(&[
+            ("a", "type Query { color(color: Color): Color } enum Color { RED GREEN }"),
+            ("b", "enum Color { RED }"),
+        ]);
+        assert!(matches!(conflict, Err(CombineError::EnumValuesMismatch { .. })));
+    }
+
+    #[test]
+    fn intersect_input_object_fields() {
+        let schema = combine(&[
+            ("a", "type Query { books(filter: Filter): Int } input Filter { title: String author: String }"),
+            ("b", "input Filter { title: String year: Int }"),
+        ])
+        .unwrap();
+        assert_eq!(
+            schema.types["Filter"].input_fields.keys().map(Name::as_str).collect::<Vec<_>>(),
+            ["title"]
+        );
+
+        let conflict = combine(&[
+            ("a", "input Filter { title: String author: String! }"),
+            ("b", "input Filter { title: String }"),
+        ]);
+        assert!(matches!(conflict, Err(CombineError::FieldConflicted { .. })));
+    }
+}
@@ -10,4 +10,7 @@ pub enum CombineError {
         type_name: String,
         field_name: String,
     },
+
+    #[error("Enum '{type_name}' is used by inputs and outputs, but its values differ between services.")]
+    EnumValuesMismatch { type_name: String },
 }
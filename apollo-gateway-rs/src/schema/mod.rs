@@ -6,8 +6,8 @@ mod type_ext;
 mod value_ext;
 
 pub use composed_schema::{
-    ComposedSchema, KeyFields, MetaEnumValue, MetaField, MetaInputValue, MetaType,
-    TypeKind,
+    CacheControl, CacheScope, ComposedSchema, KeyFields, MetaEnumValue, MetaField, MetaInputValue,
+    MetaType, TypeKind,
 };
 pub use error::CombineError;
 pub use type_ext::TypeExt;
@@ -0,0 +1,133 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tower::util::BoxCloneService;
+use tower::{Layer, Service, ServiceExt};
+
+use crate::datasource::Context;
+use crate::planner::{Request, RequestData, Response};
+
+/// Error of the services, any layer error can be converted into it.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Service executing operations of clients, layers added with `with_operation_layer` wrap it.
+pub type OperationService = BoxCloneService<OperationRequest, Response, BoxError>;
+
+/// Service sending a request to a subgraph, layers added with `with_subgraph_layer` wrap it.
+pub type SubgraphService = BoxCloneService<SubgraphRequest, Response, BoxError>;
+
+/// An operation of a client.
+pub struct OperationRequest {
+    pub request: RequestData,
+    pub context: Context,
+}
+
+/// A request to a subgraph, `GraphqlSourceMiddleware::will_send_request` has already been called for it.
+pub struct SubgraphRequest {
+    /// Name of the source
+    pub service: String,
+    pub request: Request,
+    /// Context of the client request which caused this subgraph request
+    pub context: Arc<Context>,
+}
+
+type BoxLayer<Req> = Arc<
+    dyn Fn(BoxCloneService<Req, Response, BoxError>) -> BoxCloneService<Req, Response, BoxError>
+        + Send
+        + Sync,
+>;
+
+/// Layers in the order they were added, the first one is the outermost.
+pub(crate) struct LayerStack<Req> {
+    layers: Vec<BoxLayer<Req>>,
+}
+
+impl<Req> Default for LayerStack<Req> {
+    fn default() -> Self {
+        Self { layers: Vec::new() }
+    }
+}
+
+impl<Req> Clone for LayerStack<Req> {
+    fn clone(&self) -> Self {
+        Self { layers: self.layers.clone() }
+    }
+}
+
+impl<Req: 'static> LayerStack<Req> {
+    pub fn push<L>(&mut self, layer: L)
+    where
+        L: Layer<BoxCloneService<Req, Response, BoxError>> + Send + Sync + 'static,
+        L::Service: Service<Req, Response = Response, Error = BoxError> + Clone + Send + 'static,
+        <L::Service as Service<Req>>::Future: Send + 'static,
+    {
+        self.layers
+            .push(Arc::new(move |service| BoxCloneService::new(layer.layer(service))));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn apply(&self, service: BoxCloneService<Req, Response, BoxError>) -> BoxCloneService<Req, Response, BoxError> {
+        self.layers.iter().rev().fold(service, |service, layer| layer(service))
+    }
+}
+
+/// A layered service which is built once and shared by all requests,
+/// so state of layers like concurrency limits is kept between requests.
+pub(crate) struct SharedService<Req>(Arc<Mutex<BoxCloneService<Req, Response, BoxError>>>);
+
+impl<Req> Clone for SharedService<Req> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<Req> SharedService<Req> {
+    pub fn new(service: BoxCloneService<Req, Response, BoxError>) -> Self {
+        Self(Arc::new(Mutex::new(service)))
+    }
+
+    pub async fn call(&self, request: Req) -> Result<Response, BoxError> {
+        let service = self.0.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        service.oneshot(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tower::layer::layer_fn;
+    use tower::service_fn;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn first_layer_is_outermost() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut stack = LayerStack::<RequestData>::default();
+        for name in ["outer", "inner"] {
+            let calls = calls.clone();
+            stack.push(layer_fn(move |service: BoxCloneService<RequestData, Response, BoxError>| {
+                let calls = calls.clone();
+                service_fn(move |request| {
+                    calls.lock().unwrap_or_else(PoisonError::into_inner).push(name);
+                    service.clone().oneshot(request)
+                })
+            }));
+        }
+        let count = Arc::new(AtomicUsize::new(0));
+        let service = SharedService::new(stack.apply(BoxCloneService::new(service_fn({
+            let count = count.clone();
+            move |_: RequestData| {
+                count.fetch_add(1, Ordering::Relaxed);
+                async { Ok::<_, BoxError>(Response::default()) }
+            }
+        }))));
+
+        assert!(service.call(RequestData::new("{ a }")).await.is_ok());
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert_eq!(*calls.lock().unwrap_or_else(PoisonError::into_inner), vec!["outer", "inner"]);
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use parser::types::{
+    DocumentOperations, ExecutableDocument, Field, FragmentDefinition, OperationType, Selection, SelectionSet,
+    TypeKind, TypeSystemDefinition,
+};
+use parser::Positioned;
+use serde_json::{Map, Value};
+use value::{ConstValue, Name, Variables};
+
+use crate::datasource::{GraphqlSourceMiddleware, RemoteGraphQLDataSource};
+use crate::planner::{Request, RequestData, Response, ServerError};
+use crate::schema::TypeExt;
+
+type Resolver = dyn Fn(&Value) -> anyhow::Result<Value> + Send + Sync;
+
+/// A subgraph which resolves requests in process, without touching the network.
+///
+/// The SDL is served to the gateway as `_service { sdl }`. Root fields are resolved by closures which get the arguments of the field,
+/// `_entities` by closures which get a representation. The selection set of the request is applied to the returned JSON,
+/// so resolvers can return more fields than requested. Register it with `GatewayServerBuilder::with_middleware_source`.
+/// ```rust
+/// use apollo_gateway_rs::testing::MockSource;
+/// use serde_json::json;
+///
+/// let accounts = MockSource::new("accounts", r#"
+///     type Query { me: User }
+///     type User @key(fields: "id") { id: ID! name: String }
+/// "#)
+/// .query_value("me", json!({ "id": "1", "name": "Ada" }))
+/// .entities("User", vec![json!({ "id": "1", "name": "Ada" })]);
+/// ```
+/// Subscriptions are not supported.
+#[derive(Clone)]
+pub struct MockSource {
+    name: String,
+    address: String,
+    sdl: String,
+    field_types: Arc<HashMap<String, HashMap<String, String>>>,
+    supertypes: Arc<HashMap<String, HashSet<String>>>,
+    query: HashMap<String, Arc<Resolver>>,
+    mutation: HashMap<String, Arc<Resolver>>,
+    entities: HashMap<String, Arc<Resolver>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockSource {
+    /// Panics if the SDL is invalid.
+    pub fn new(name: impl Into<String>, sdl: impl Into<String>) -> Self {
+        let name = name.into();
+        let sdl = sdl.into();
+        let document = parser::parse_schema(&sdl).unwrap_or_else(|err| panic!("Invalid SDL of '{}': {}", name, err));
+        let mut field_types: HashMap<String, HashMap<String, String>> = HashMap::new();
+        let mut supertypes: HashMap<String, HashSet<String>> = HashMap::new();
+        for definition in document.definitions {
+            let ty = match definition {
+                TypeSystemDefinition::Type(ty) => ty.node,
+                _ => continue,
+            };
+            let type_name = ty.name.node.to_string();
+            let (implements, fields) = match ty.kind {
+                TypeKind::Object(object) => (object.implements, object.fields),
+                TypeKind::Interface(interface) => (interface.implements, interface.fields),
+                TypeKind::Union(union) => {
+                    for member in union.members {
+                        supertypes.entry(member.node.to_string()).or_default().insert(type_name.clone());
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            for implement in implements {
+                supertypes.entry(type_name.clone()).or_default().insert(implement.node.to_string());
+            }
+            let types = field_types.entry(type_name).or_default();
+            for field in fields {
+                types.insert(field.node.name.node.to_string(), field.node.ty.node.concrete_typename().to_string());
+            }
+        }
+        Self {
+            address: format!("{}.mock", name),
+            name,
+            sdl,
+            field_types: Arc::new(field_types),
+            supertypes: Arc::new(supertypes),
+            query: HashMap::new(),
+            mutation: HashMap::new(),
+            entities: HashMap::new(),
+            requests: Default::default(),
+        }
+    }
+
+    /// Resolve a field of `Query` with a closure which gets the arguments as a JSON object.
+    pub fn query<F>(mut self, field: impl Into<String>, resolver: F) -> Self
+    where
+        F: Fn(&Value) -> anyhow::Result<Value> + Send + Sync + 'static,
+    {
+        self.query.insert(field.into(), Arc::new(resolver));
+        self
+    }
+
+    /// Resolve a field of `Query` to a canned value.
+    pub fn query_value(self, field: impl Into<String>, value: Value) -> Self {
+        self.query(field, move |_| Ok(value.clone()))
+    }
+
+    /// Resolve a field of `Mutation` with a closure which gets the arguments as a JSON object.
+    pub fn mutation<F>(mut self, field: impl Into<String>, resolver: F) -> Self
+    where
+        F: Fn(&Value) -> anyhow::Result<Value> + Send + Sync + 'static,
+    {
+        self.mutation.insert(field.into(), Arc::new(resolver));
+        self
+    }
+
+    /// Resolve entities of a type with a closure which gets the representation, `null` means the entity does not exist.
+    /// The resolved fields are added to the fields of the representation.
+    pub fn entity<F>(mut self, type_name: impl Into<String>, resolver: F) -> Self
+    where
+        F: Fn(&Value) -> anyhow::Result<Value> + Send + Sync + 'static,
+    {
+        self.entities.insert(type_name.into(), Arc::new(resolver));
+        self
+    }
+
+    /// Resolve entities of a type from fixtures, the fixture whose fields equal all key fields of the representation is used.
+    pub fn entities(self, type_name: impl Into<String>, fixtures: Vec<Value>) -> Self {
+        self.entity(type_name, move |representation| {
+            let keys = representation
+                .as_object()
+                .map(|fields| fields.iter().filter(|(name, _)| *name != "__typename").collect::<Vec<_>>())
+                .unwrap_or_default();
+            Ok(fixtures
+                .iter()
+                .find(|fixture| keys.iter().all(|(name, value)| fixture.get(name.as_str()) == Some(*value)))
+                .cloned()
+                .unwrap_or(Value::Null))
+        })
+    }
+
+    /// Queries received from the gateway in the order they arrived, schema polls are not included.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    fn execute(&self, request: &RequestData) -> Response {
+        let document = match parser::parse_query(&request.query) {
+            Ok(document) => document,
+            Err(err) => return Response::error(err.to_string()),
+        };
+        let operation = match &document.operations {
+            DocumentOperations::Single(operation) => Some(operation),
+            DocumentOperations::Multiple(operations) => match &request.operation {
+                Some(name) => operations.get(name.as_str()),
+                None => operations.values().next(),
+            },
+        };
+        let operation = match operation {
+            Some(operation) => &operation.node,
+            None => return Response::error("Unknown operation."),
+        };
+        let (root_type, resolvers) = match operation.ty {
+            OperationType::Query => ("Query", &self.query),
+            OperationType::Mutation => ("Mutation", &self.mutation),
+            OperationType::Subscription => return Response::error("Subscriptions are not supported by mock sources."),
+        };
+        let is_schema_poll = operation.selection_set.node.items.iter().all(|selection| {
+            matches!(&selection.node, Selection::Field(field) if field.node.name.node == "_service")
+        });
+        if !is_schema_poll {
+            self.requests
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(request.query.clone());
+        }
+
+        let mut data = Map::new();
+        let mut errors = Vec::new();
+        for selection in &operation.selection_set.node.items {
+            let field = match &selection.node {
+                Selection::Field(field) => &field.node,
+                _ => continue,
+            };
+            let response_key = field.response_key().node.to_string();
+            let value = match field.name.node.as_str() {
+                "__typename" => Value::String(root_type.to_string()),
+                "_service" => self.select(&document, "_Service", &serde_json::json!({ "sdl": self.sdl }), field),
+                "_entities" => self.resolve_entities(&document, field, &request.variables, &mut errors),
+                name => {
+                    let result = match resolvers.get(name) {
+                        Some(resolver) => resolver(&arguments(field, &request.variables)),
+                        None => Err(anyhow::anyhow!("No mock resolver for '{}.{}'.", root_type, name)),
+                    };
+                    match result {
+                        Ok(value) => self.select(&document, self.field_type(root_type, name), &value, field),
+                        Err(err) => {
+                            errors.push(error(err, vec![ConstValue::String(response_key.clone())]));
+                            Value::Null
+                        }
+                    }
+                }
+            };
+            data.insert(response_key, value);
+        }
+        Response {
+            data: ConstValue::from_json(Value::Object(data)).ok(),
+            errors,
+            ..Default::default()
+        }
+    }
+
+    fn resolve_entities(
+        &self,
+        document: &ExecutableDocument,
+        field: &Field,
+        variables: &Variables,
+        errors: &mut Vec<ServerError>,
+    ) -> Value {
+        let arguments = arguments(field, variables);
+        let representations = arguments["representations"].as_array().cloned().unwrap_or_default();
+        let response_key = field.response_key().node.to_string();
+        let entities = representations.iter().enumerate().map(|(index, representation)| {
+            let type_name = representation["__typename"].as_str().unwrap_or_default();
+            let result = match self.entities.get(type_name) {
+                Some(resolver) => resolver(representation),
+                None => Err(anyhow::anyhow!("No mock resolver for entities of '{}'.", type_name)),
+            };
+            match result {
+                Ok(Value::Object(fields)) => {
+                    let mut entity = representation.as_object().cloned().unwrap_or_default();
+                    entity.extend(fields);
+                    self.select(document, type_name, &Value::Object(entity), field)
+                }
+                Ok(_) => Value::Null,
+                Err(err) => {
+                    errors.push(error(
+                        err,
+                        vec![ConstValue::String(response_key.clone()), ConstValue::Number(index.into())],
+                    ));
+                    Value::Null
+                }
+            }
+        });
+        Value::Array(entities.collect())
+    }
+
+    fn field_type(&self, type_name: &str, field: &str) -> &str {
+        self.field_types
+            .get(type_name)
+            .and_then(|fields| fields.get(field))
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    fn is_possible_type(&self, type_name: &str, type_condition: &str) -> bool {
+        type_name == type_condition
+            || self
+                .supertypes
+                .get(type_name)
+                .is_some_and(|supertypes| supertypes.contains(type_condition))
+    }
+
+    /// Apply the selection set of the field to the resolved value.
+    fn select(&self, document: &ExecutableDocument, type_name: &str, value: &Value, field: &Field) -> Value {
+        self.select_set(document, type_name, value, &field.selection_set.node)
+    }
+
+    fn select_set(&self, document: &ExecutableDocument, type_name: &str, value: &Value, selection_set: &SelectionSet) -> Value {
+        if selection_set.items.is_empty() {
+            return value.clone();
+        }
+        match value {
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.select_set(document, type_name, item, selection_set))
+                    .collect(),
+            ),
+            Value::Object(fields) => {
+                let type_name = fields.get("__typename").and_then(Value::as_str).unwrap_or(type_name);
+                let mut output = Map::new();
+                self.collect_fields(document, type_name, fields, selection_set, &mut output);
+                Value::Object(output)
+            }
+            value => value.clone(),
+        }
+    }
+
+    fn collect_fields(
+        &self,
+        document: &ExecutableDocument,
+        type_name: &str,
+        fields: &Map<String, Value>,
+        selection_set: &SelectionSet,
+        output: &mut Map<String, Value>,
+    ) {
+        for selection in &selection_set.items {
+            match &selection.node {
+                Selection::Field(field) => {
+                    let name = field.node.name.node.as_str();
+                    let value = if name == "__typename" {
+                        Value::String(type_name.to_string())
+                    } else {
+                        self.select(
+                            document,
+                            self.field_type(type_name, name),
+                            fields.get(name).unwrap_or(&Value::Null),
+                            &field.node,
+                        )
+                    };
+                    merge(output, field.node.response_key().node.to_string(), value);
+                }
+                Selection::InlineFragment(inline_fragment) => {
+                    let matches = inline_fragment
+                        .node
+                        .type_condition
+                        .as_ref()
+                        .is_none_or(|type_condition| self.is_possible_type(type_name, &type_condition.node.on.node));
+                    if matches {
+                        self.collect_fields(document, type_name, fields, &inline_fragment.node.selection_set.node, output);
+                    }
+                }
+                Selection::FragmentSpread(fragment_spread) => {
+                    let fragment: Option<&Positioned<FragmentDefinition>> =
+                        document.fragments.get(&fragment_spread.node.fragment_name.node);
+                    if let Some(fragment) = fragment {
+                        if self.is_possible_type(type_name, &fragment.node.type_condition.node.on.node) {
+                            self.collect_fields(document, type_name, fields, &fragment.node.selection_set.node, output);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl RemoteGraphQLDataSource for MockSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+#[async_trait::async_trait]
+impl GraphqlSourceMiddleware for MockSource {
+    async fn fetch(&self, request: Request) -> anyhow::Result<Response> {
+        Ok(self.execute(&request.data))
+    }
+}
+
+/// Arguments of the field as a JSON object, variables are replaced by their values.
+fn arguments(field: &Field, variables: &Variables) -> Value {
+    let arguments = field
+        .arguments
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .node
+                .clone()
+                .into_const_with(|name: Name| Ok::<_, std::convert::Infallible>(variables.get(&name).cloned().unwrap_or(ConstValue::Null)))
+                .unwrap_or(ConstValue::Null);
+            (name.node.to_string(), value.into_json().unwrap_or(Value::Null))
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(arguments)
+}
+
+fn error(err: anyhow::Error, path: Vec<ConstValue>) -> ServerError {
+    ServerError {
+        message: err.to_string(),
+        path,
+        locations: Default::default(),
+        extensions: Default::default(),
+    }
+}
+
+/// Fields selected more than once, e.g. by fragments, are merged.
+fn merge(output: &mut Map<String, Value>, key: String, value: Value) {
+    match (output.get_mut(&key), value) {
+        (Some(Value::Object(existing)), Value::Object(fields)) => {
+            for (name, value) in fields {
+                merge(existing, name, value);
+            }
+        }
+        (_, value) => {
+            output.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::GatewayServer;
+
+    const ACCOUNTS: &str = r#"
+        type Query {
+            me: User
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    const REVIEWS: &str = r#"
+        type Review {
+            body: String
+        }
+
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            reviews: [Review]
+        }
+    "#;
+
+    #[tokio::test]
+    async fn execute_with_mock_sources() {
+        let accounts = MockSource::new("accounts", ACCOUNTS).query_value("me", json!({ "id": "1", "name": "Ada" }));
+        let reviews = MockSource::new("reviews", REVIEWS)
+            .entities("User", vec![json!({ "id": "1", "reviews": [{ "body": "Great" }] })]);
+        let server = GatewayServer::builder()
+            .with_middleware_source(accounts.clone())
+            .with_middleware_source(reviews.clone())
+            .build();
+        server.update_schema().await.expect("composed schema");
+
+        let resp = server
+            .execute(RequestData::new("{ me { name reviews { body } } }"), Default::default())
+            .await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        assert_eq!(
+            resp.data.and_then(|data| data.into_json().ok()),
+            Some(json!({ "me": { "name": "Ada", "reviews": [{ "body": "Great" }] } }))
+        );
+        assert_eq!(accounts.requests().len(), 1);
+        assert_eq!(reviews.requests().len(), 1);
+    }
+
+    #[test]
+    fn apply_selection_set() {
+        let source = MockSource::new("accounts", ACCOUNTS)
+            .query("me", |arguments| Ok(json!({ "id": arguments["id"], "name": "Ada", "email": "ada@example.com" })));
+        let resp = source.execute(
+            &RequestData::new("query($id: ID!) { user: me(id: $id) { id ... on User { name } __typename } }")
+                .variables(Variables::from_json(json!({ "id": "7" }))),
+        );
+        assert_eq!(
+            resp.data.and_then(|data| data.into_json().ok()),
+            Some(json!({ "user": { "id": "7", "name": "Ada", "__typename": "User" } }))
+        );
+    }
+}
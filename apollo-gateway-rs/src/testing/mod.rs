@@ -0,0 +1,163 @@
+//! Testing utilities: snapshot tests of query plans and in-process mock subgraphs.
+//!
+//! Compose the schema of your subgraphs once and compare the plans of your operations with stored snapshots,
+//! so changes of the planner, or of your subgraphs, which alter the plans show up in your tests.
+//!
+//! ```rust,ignore
+//! use apollo_gateway_rs::testing::PlanHarness;
+//!
+//! #[test]
+//! fn plan_of_top_reviews() {
+//!     // tests/subgraphs/accounts.graphql, tests/subgraphs/reviews.graphql, ...
+//!     let harness = PlanHarness::from_dir("tests/subgraphs").unwrap();
+//!     harness.assert_snapshot("tests/plans/top_reviews.json", "{ topReviews { body author { name } } }");
+//! }
+//! ```
+//!
+//! A snapshot which does not exist yet is written by the first run.
+//! Run the tests with `UPDATE_PLAN_SNAPSHOTS=1` to overwrite the snapshots of plans which have changed.
+//!
+//! `MockSource` replaces subgraph servers in tests of whole operations, see its documentation.
+mod mock;
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context as _;
+
+use crate::planner::{plan_to_json, CostModel, RequestData};
+use crate::schema::ComposedSchema;
+
+pub use mock::MockSource;
+
+/// Set this environment variable to `1` to overwrite snapshots which differ from the current plan.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_PLAN_SNAPSHOTS";
+
+/// A composed schema to plan operations with.
+pub struct PlanHarness {
+    schema: ComposedSchema,
+}
+
+impl PlanHarness {
+    /// Compose the schema from the SDL of every subgraph, given as service name and SDL.
+    pub fn from_sdl<N, S>(subgraphs: impl IntoIterator<Item = (N, S)>) -> anyhow::Result<Self>
+    where
+        N: Into<String>,
+        S: AsRef<str>,
+    {
+        let documents = subgraphs
+            .into_iter()
+            .map(|(service, sdl)| {
+                let service = service.into();
+                let document =
+                    parser::parse_schema(sdl).with_context(|| format!("Invalid SDL of '{}'.", service))?;
+                Ok((service, document))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let schema = ComposedSchema::combine(documents).context("Failed to compose the schema.")?;
+        Ok(Self { schema })
+    }
+
+    /// Compose the schema from every `.graphql` file of the directory, the file name without the extension is the service name.
+    pub fn from_dir(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let mut subgraphs = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("Failed to read '{}'.", dir.display()))? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("graphql") {
+                continue;
+            }
+            let service = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .with_context(|| format!("Invalid file name '{}'.", path.display()))?
+                .to_string();
+            let sdl = fs::read_to_string(&path).with_context(|| format!("Failed to read '{}'.", path.display()))?;
+            subgraphs.push((service, sdl));
+        }
+        subgraphs.sort();
+        Self::from_sdl(subgraphs)
+    }
+
+    /// The query plan of the operation as JSON.
+    pub fn plan(&self, request: RequestData) -> anyhow::Result<serde_json::Value> {
+        plan_to_json(&self.schema, request, CostModel::default()).map_err(|resp| {
+            let messages = resp.errors.into_iter().map(|err| err.message).collect::<Vec<_>>();
+            anyhow::anyhow!("Failed to plan the operation: {}", messages.join(" "))
+        })
+    }
+
+    /// Compare the plan of the operation with the snapshot file, see the module documentation for updating snapshots.
+    pub fn check_snapshot(&self, snapshot: impl AsRef<Path>, request: RequestData) -> anyhow::Result<()> {
+        let snapshot = snapshot.as_ref();
+        let actual = serde_json::to_string_pretty(&self.plan(request)?)? + "\n";
+        let update = std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|value| value == "1");
+        match fs::read_to_string(snapshot) {
+            Ok(expected) if expected == actual => Ok(()),
+            Ok(expected) if !update => anyhow::bail!(
+                "The plan differs from the snapshot '{}', run with {}=1 to update it.\n--- expected\n{}\n+++ actual\n{}",
+                snapshot.display(),
+                UPDATE_SNAPSHOTS_ENV,
+                expected,
+                actual
+            ),
+            _ => {
+                if let Some(parent) = snapshot.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(snapshot, actual).with_context(|| format!("Failed to write '{}'.", snapshot.display()))
+            }
+        }
+    }
+
+    /// Panics if the plan of the query differs from the snapshot file.
+    pub fn assert_snapshot(&self, snapshot: impl AsRef<Path>, query: &str) {
+        if let Err(err) = self.check_snapshot(snapshot, RequestData::new(query)) {
+            panic!("{:#}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNTS: &str = r#"
+        type Query {
+            me: User
+        }
+
+        type User @key(fields: "id") {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    const REVIEWS: &str = r#"
+        type Review {
+            body: String
+        }
+
+        extend type User @key(fields: "id") {
+            id: ID! @external
+            reviews: [Review]
+        }
+    "#;
+
+    #[test]
+    fn compare_with_snapshot() {
+        let harness = PlanHarness::from_sdl([("accounts", ACCOUNTS), ("reviews", REVIEWS)]).expect("valid schema");
+        let plan = harness.plan(RequestData::new("{ me { name reviews { body } } }")).expect("valid plan");
+        let plan = plan.to_string();
+        assert!(plan.contains(r#""service":"accounts""#));
+        assert!(plan.contains(r#""service":"reviews""#));
+
+        let snapshot = std::env::temp_dir().join(format!("plan-snapshot-{}.json", std::process::id()));
+        fs::remove_file(&snapshot).ok();
+        harness.assert_snapshot(&snapshot, "{ me { name reviews { body } } }");
+        harness.assert_snapshot(&snapshot, "{ me { name reviews { body } } }");
+        let changed = harness.check_snapshot(&snapshot, RequestData::new("{ me { name } }"));
+        fs::remove_file(&snapshot).ok();
+        assert!(changed.is_err());
+    }
+}
@@ -0,0 +1,277 @@
+#![forbid(unsafe_code)]
+
+mod sink;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::datasource::Context;
+use crate::handler::access_log::{CLIENT_NAME_HEADER, CLIENT_VERSION_HEADER};
+
+pub use sink::{JsonFileSink, UsageSink};
+
+/// Client of an operation, taken from the `apollographql-client-name` and `apollographql-client-version` headers.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub name: Option<String>,
+    pub version: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_context(ctx: &Context) -> Self {
+        let header = |name| {
+            ctx.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string)
+        };
+        Self {
+            name: header(CLIENT_NAME_HEADER),
+            version: header(CLIENT_VERSION_HEADER),
+        }
+    }
+}
+
+/// How often a client used a field between two flushes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    pub client: ClientInfo,
+    /// `Type.field`
+    pub field: String,
+    pub count: u64,
+}
+
+/// Usage of a field by one client, the answer of `UsageReporting::clients_using`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientUsage {
+    pub client: ClientInfo,
+    pub count: u64,
+    pub last_seen: DateTime<Utc>,
+}
+
+type Pending = HashMap<ClientInfo, HashMap<String, u64>>;
+
+/// Field usage statistics of the composed schema.
+///
+/// The fields of every planned query and mutation are counted per client in memory
+/// and written to the `UsageSink` every `flush_interval`. The usage counted since the last flush
+/// is written when the gateway and every clone of the reporting have been dropped.
+/// ```rust
+/// use std::time::Duration;
+/// use apollo_gateway_rs::{GatewayServer, JsonFileSink, UsageReporting};
+/// let builder = GatewayServer::builder()
+///     .with_usage_reporting(UsageReporting::new(JsonFileSink::new("usage.jsonl")).flush_interval(Duration::from_secs(60)));
+/// ```
+#[derive(Clone)]
+pub struct UsageReporting {
+    flush_interval: Duration,
+    reporter: Arc<Reporter>,
+}
+
+struct Reporter {
+    sink: Arc<dyn UsageSink>,
+    pending: Mutex<Pending>,
+}
+
+impl UsageReporting {
+    pub fn new(sink: impl UsageSink) -> Self {
+        Self {
+            flush_interval: Duration::from_secs(60),
+            reporter: Arc::new(Reporter {
+                sink: Arc::new(sink),
+                pending: Default::default(),
+            }),
+        }
+    }
+
+    /// Interval of writes to the sink, 60 seconds by default.
+    pub fn flush_interval(self, flush_interval: Duration) -> Self {
+        Self { flush_interval, ..self }
+    }
+
+    pub(crate) fn record(&self, client: ClientInfo, fields: HashMap<String, u64>) {
+        let mut pending = self.reporter.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let counts = pending.entry(client).or_default();
+        for (field, count) in fields {
+            *counts.entry(field).or_default() += count;
+        }
+    }
+
+    /// Write the usage counted since the last flush to the sink. If the write fails the usage is kept for the next flush.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        self.reporter.flush().await
+    }
+
+    /// Clients which used the field (`Type.field`) within the last `within`, the pending usage is flushed first.
+    pub async fn clients_using(&self, field: &str, within: Duration) -> anyhow::Result<Vec<ClientUsage>> {
+        self.flush().await?;
+        let since = Utc::now() - chrono::Duration::from_std(within)?;
+        self.reporter.sink.clients_using(field, since).await
+    }
+
+    /// Flush periodically until the gateway is dropped.
+    pub(crate) fn spawn_flush_loop(&self) {
+        let reporter = Arc::downgrade(&self.reporter);
+        let flush_interval = self.flush_interval;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + flush_interval, flush_interval);
+            loop {
+                interval.tick().await;
+                let reporter = match reporter.upgrade() {
+                    Some(reporter) => reporter,
+                    None => return,
+                };
+                if let Err(err) = reporter.flush().await {
+                    tracing::error!(error = %err, "Failed to flush field usage.");
+                }
+            }
+        });
+    }
+}
+
+impl Reporter {
+    fn take_records(&self) -> Vec<UsageRecord> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap_or_else(PoisonError::into_inner));
+        let timestamp = Utc::now();
+        pending
+            .into_iter()
+            .flat_map(|(client, fields)| {
+                fields.into_iter().map(move |(field, count)| UsageRecord {
+                    timestamp,
+                    client: client.clone(),
+                    field,
+                    count,
+                })
+            })
+            .collect()
+    }
+
+    async fn flush(&self) -> anyhow::Result<()> {
+        let records = self.take_records();
+        if records.is_empty() {
+            return Ok(());
+        }
+        if let Err(err) = self.sink.write(records.clone()).await {
+            // Keep the batch for the next flush, usage counted meanwhile is added to it.
+            let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+            for record in records {
+                *pending.entry(record.client).or_default().entry(record.field).or_default() += record.count;
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        let records = self.take_records();
+        if records.is_empty() {
+            return;
+        }
+        // The last write can only be spawned, it is lost if the runtime is shutting down.
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let sink = self.sink.clone();
+                handle.spawn(async move {
+                    if let Err(err) = sink.write(records).await {
+                        tracing::error!(error = %err, "Failed to flush field usage.");
+                    }
+                });
+            }
+            Err(_) => tracing::warn!("Field usage is lost, the gateway was dropped outside of a runtime."),
+        }
+    }
+}
+
+/// Sum up the records of a field by client.
+pub fn aggregate_clients<'a>(
+    records: impl IntoIterator<Item = &'a UsageRecord>,
+    field: &str,
+    since: DateTime<Utc>,
+) -> Vec<ClientUsage> {
+    let mut clients: HashMap<&ClientInfo, ClientUsage> = HashMap::new();
+    for record in records {
+        if record.field != field || record.timestamp < since {
+            continue;
+        }
+        let usage = clients.entry(&record.client).or_insert_with(|| ClientUsage {
+            client: record.client.clone(),
+            count: 0,
+            last_seen: record.timestamp,
+        });
+        usage.count += record.count;
+        usage.last_seen = usage.last_seen.max(record.timestamp);
+    }
+    let mut clients = clients.into_values().collect::<Vec<_>>();
+    clients.sort_by(|a, b| a.client.cmp(&b.client));
+    clients
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    /// Fails the first write and keeps the records of the later ones.
+    #[derive(Default)]
+    struct FlakySink {
+        failed: AtomicBool,
+        records: Mutex<Vec<UsageRecord>>,
+    }
+
+    #[async_trait::async_trait]
+    impl UsageSink for Arc<FlakySink> {
+        async fn write(&self, records: Vec<UsageRecord>) -> anyhow::Result<()> {
+            if !self.failed.swap(true, Ordering::SeqCst) {
+                anyhow::bail!("Sink is unavailable.");
+            }
+            self.records.lock().unwrap_or_else(PoisonError::into_inner).extend(records);
+            Ok(())
+        }
+
+        async fn clients_using(&self, field: &str, since: DateTime<Utc>) -> anyhow::Result<Vec<ClientUsage>> {
+            Ok(aggregate_clients(self.records.lock().unwrap_or_else(PoisonError::into_inner).iter(), field, since))
+        }
+    }
+
+    #[tokio::test]
+    async fn keep_usage_of_failed_writes() {
+        let sink = Arc::new(FlakySink::default());
+        let reporting = UsageReporting::new(sink.clone());
+        let client = ClientInfo {
+            name: Some("web".to_string()),
+            version: None,
+        };
+        reporting.record(client.clone(), HashMap::from([("User.name".to_string(), 2)]));
+        assert!(reporting.flush().await.is_err());
+
+        reporting.record(client.clone(), HashMap::from([("User.name".to_string(), 1)]));
+        reporting.flush().await.expect("flushed");
+        let records = sink.records.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].client, client);
+        assert_eq!(records[0].count, 3);
+    }
+
+    #[tokio::test]
+    async fn flush_on_drop() {
+        let sink = Arc::new(FlakySink::default());
+        sink.failed.store(true, Ordering::SeqCst);
+        let reporting = UsageReporting::new(sink.clone()).flush_interval(Duration::from_secs(3600));
+        reporting.spawn_flush_loop();
+        reporting.record(ClientInfo::default(), HashMap::from([("User.name".to_string(), 2)]));
+        drop(reporting);
+        tokio::task::yield_now().await;
+
+        let records = sink.records.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].count, 2);
+    }
+}
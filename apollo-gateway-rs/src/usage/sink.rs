@@ -0,0 +1,121 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use super::{aggregate_clients, ClientUsage, UsageRecord};
+
+/// Storage of field usage. Implement it if you want to keep usage statistics in a database.
+#[async_trait::async_trait]
+pub trait UsageSink: Send + Sync + 'static {
+    async fn write(&self, records: Vec<UsageRecord>) -> anyhow::Result<()>;
+    /// Clients which used the field (`Type.field`) since the given time.
+    async fn clients_using(&self, field: &str, since: DateTime<Utc>) -> anyhow::Result<Vec<ClientUsage>>;
+}
+
+/// Appends the usage records to a file, one JSON object per line.
+pub struct JsonFileSink {
+    path: Arc<PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl JsonFileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Arc::new(path.into()),
+            lock: Default::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl UsageSink for JsonFileSink {
+    async fn write(&self, records: Vec<UsageRecord>) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&*path)
+                .with_context(|| format!("Failed to open '{}'.", path.display()))?;
+            let mut writer = BufWriter::new(file);
+            for record in &records {
+                serde_json::to_writer(&mut writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn clients_using(&self, field: &str, since: DateTime<Utc>) -> anyhow::Result<Vec<ClientUsage>> {
+        let _guard = self.lock.lock().await;
+        let path = self.path.clone();
+        let field = field.to_string();
+        tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<ClientUsage>> {
+            let file = match File::open(&*path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(err) => return Err(err).with_context(|| format!("Failed to open '{}'.", path.display())),
+            };
+            let mut records = Vec::new();
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                let record: UsageRecord = serde_json::from_str(&line)
+                    .with_context(|| format!("Invalid usage record in '{}'.", path.display()))?;
+                if record.field == field {
+                    records.push(record);
+                }
+            }
+            Ok(aggregate_clients(&records, &field, since))
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use crate::usage::{ClientInfo, UsageReporting};
+    use super::*;
+
+    #[tokio::test]
+    async fn clients_using_field() {
+        let path = std::env::temp_dir().join(format!("usage-{}.jsonl", std::process::id()));
+        let reporting = UsageReporting::new(JsonFileSink::new(&path));
+        let client = |name: &str, version: &str| ClientInfo {
+            name: Some(name.to_string()),
+            version: Some(version.to_string()),
+        };
+        let fields = |fields: &[(&str, u64)]| {
+            fields
+                .iter()
+                .map(|(field, count)| (field.to_string(), *count))
+                .collect::<HashMap<_, _>>()
+        };
+        reporting.record(client("web", "1.0"), fields(&[("User.legacyId", 1), ("User.name", 1)]));
+        reporting.flush().await.expect("flushed");
+        reporting.record(client("web", "1.0"), fields(&[("User.legacyId", 2)]));
+        reporting.record(client("ios", "2.3"), fields(&[("User.name", 1)]));
+
+        let clients = reporting
+            .clients_using("User.legacyId", Duration::from_secs(86400))
+            .await
+            .expect("valid file");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].client, client("web", "1.0"));
+        assert_eq!(clients[0].count, 3);
+    }
+}
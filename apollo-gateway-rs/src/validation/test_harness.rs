@@ -2,14 +2,14 @@ use once_cell::sync::Lazy;
 use parser::types::ExecutableDocument;
 use value::Variables;
 
-use crate::visitor::{visit, Visitor, VisitorContext};
-use crate::RuleError;
 use crate::schema::ComposedSchema;
 use crate::validation::RuleError;
 use crate::validation::visitor::{visit, Visitor, VisitorContext};
 
-static SCHEMA: Lazy<ComposedSchema> =
-    Lazy::new(|| ComposedSchema::parse(include_str!("test_harness.graphql")).unwrap());
+static SCHEMA: Lazy<ComposedSchema> = Lazy::new(|| {
+    let document = parser::parse_schema(include_str!("test_harness.graphql")).unwrap();
+    ComposedSchema::combine([("test".to_string(), document)]).unwrap()
+});
 
 pub fn validate<'a, V, F>(
     doc: &'a ExecutableDocument,
@@ -53,7 +53,7 @@ macro_rules! expect_passes_rule {
     ($factory:expr, $query_source:literal $(,)?) => {
         let variables = value::Variables::default();
         let doc = parser::parse_query($query_source).expect("Parse error");
-        crate::test_harness::expect_passes_rule_(&doc, &variables, $factory);
+        crate::validation::test_harness::expect_passes_rule_(&doc, &variables, $factory);
     };
 }
 
@@ -74,6 +74,6 @@ macro_rules! expect_fails_rule {
     ($factory:expr, $query_source:literal $(,)?) => {
         let variables = value::Variables::default();
         let doc = parser::parse_query($query_source).expect("Parse error");
-        crate::test_harness::expect_fails_rule_(&doc, &variables, $factory);
+        crate::validation::test_harness::expect_fails_rule_(&doc, &variables, $factory);
     };
 }
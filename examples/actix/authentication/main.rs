@@ -1,14 +1,39 @@
 use actix_session::SessionMiddleware;
 use actix_session::storage::CookieSessionStore;
-use actix_web::{HttpServer, App, web::Data, HttpResponse};
+use actix_session::SessionExt;
+use actix_web::{HttpServer, App, web::Data, web::Json, web::Payload, HttpMessage, HttpRequest, HttpResponse};
 use actix_web::cookie::Key;
 use async_graphql::http::{GraphQLPlaygroundConfig, playground_source};
 use tracing_actix_web::TracingLogger;
-use apollo_gateway_rs::{GatewayServer, actix::{graphql_request, graphql_subscription}};
-use crate::auth_source::AuthSource;
-use crate::user_middleware::{UserMiddlewareFactory};
+use apollo_gateway_rs::{Context, GatewayServer, RequestData};
+use apollo_gateway_rs::actix::{context, into_http_response, start_subscription};
+use crate::auth_source::{AuthSource, AuthToken};
+use crate::user_middleware::{UserEmail, UserMiddlewareFactory};
 use crate::todo_source::TodoSource;
 
+fn user_context(req: &HttpRequest) -> Context {
+    let mut ctx = context(req);
+    if let Some(email) = req.extensions().get::<UserEmail>().cloned() {
+        ctx.extensions_mut().insert(email);
+    }
+    ctx
+}
+
+async fn graphql_request(server: Data<GatewayServer>, request: Json<RequestData>, req: HttpRequest) -> HttpResponse {
+    let token = AuthToken::default();
+    let ctx = user_context(&req).with_extension(token.clone());
+    let response = server.execute(request.into_inner(), ctx).await;
+    if let Some(jwt) = token.take() {
+        let _ = req.get_session().insert("auth", jwt);
+    }
+    into_http_response(response)
+}
+
+async fn graphql_subscription(server: Data<GatewayServer>, req: HttpRequest, payload: Payload) -> HttpResponse {
+    let ctx = user_context(&req);
+    start_subscription(&server, ctx, &req, payload).await
+}
+
 pub async fn playground() -> HttpResponse {
     let html = playground_source(GraphQLPlaygroundConfig::new("/").subscription_endpoint("/"));
     HttpResponse::Ok()
@@ -62,10 +87,20 @@ async fn main() -> std::io::Result<()> {
 }
 
 mod auth_source {
-    use actix_session::SessionExt;
+    use std::sync::{Arc, Mutex};
     use apollo_gateway_rs::{Context, GraphqlSourceMiddleware, RemoteGraphQLDataSource, Response};
     use crate::jwt::create_jwt;
 
+    /// Receives the token created by the auth source, the request handler stores it in the session.
+    #[derive(Clone, Default)]
+    pub struct AuthToken(Arc<Mutex<Option<String>>>);
+
+    impl AuthToken {
+        pub fn take(&self) -> Option<String> {
+            self.0.lock().ok().and_then(|mut token| token.take())
+        }
+    }
+
     pub struct AuthSource {
         pub(crate) name: String,
         pub(crate) addr: String,
@@ -92,10 +127,13 @@ mod auth_source {
     #[async_trait::async_trait]
     impl GraphqlSourceMiddleware for AuthSource {
         async fn did_receive_response(&self, response: &mut Response, ctx: &Context) -> anyhow::Result<()> {
-            let session = ctx.get_session();
-            if let Some(jwt) = response.headers.get("email")
-                .and_then(|email| create_jwt(email.clone()).ok()) {
-                let _ = session.insert("auth", jwt);
+            let token = ctx.extensions().get::<AuthToken>();
+            if let Some((token, jwt)) = token.zip(response.headers.get("email")
+                .and_then(|email| email.to_str().ok())
+                .and_then(|email| create_jwt(email.to_string()).ok())) {
+                if let Ok(mut token) = token.0.lock() {
+                    *token = Some(jwt);
+                }
             }
             Ok(())
         }
@@ -106,7 +144,7 @@ mod user_middleware {
     use actix_service::{Service, Transform};
     use actix_session::SessionExt;
     use actix_web::dev::{ServiceRequest, ServiceResponse};
-    use actix_web::{HttpMessage, Error, HttpRequest};
+    use actix_web::{HttpMessage, Error};
     use futures::future::{Ready, ready};
     use crate::jwt::decode_identity;
 
@@ -117,17 +155,6 @@ mod user_middleware {
     #[derive(Clone)]
     pub struct UserEmail(pub String);
 
-    pub trait UserExt {
-        fn user_email(&self) -> Option<UserEmail>;
-    }
-
-    impl UserExt for HttpRequest {
-        fn user_email(&self) -> Option<UserEmail> {
-            let ext = self.extensions();
-            ext.get::<UserEmail>().cloned()
-        }
-    }
-
     impl<S, B> Service<ServiceRequest> for UserMiddleware<S>
         where
             S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=Error> + 'static
@@ -207,10 +234,9 @@ mod jwt {
 }
 
 mod todo_source {
-    use std::collections::HashMap;
     use serde_json::Value;
-    use apollo_gateway_rs::{Context, GraphqlSourceMiddleware, RemoteGraphQLDataSource};
-    use crate::user_middleware::{UserExt, UserEmail};
+    use apollo_gateway_rs::{Context, GraphqlSourceMiddleware, RemoteGraphQLDataSource, Request};
+    use crate::user_middleware::UserEmail;
 
     pub struct TodoSource {
         pub(crate) name: String,
@@ -236,9 +262,9 @@ mod todo_source {
 
     #[async_trait::async_trait]
     impl GraphqlSourceMiddleware for TodoSource {
-        async fn will_send_request(&self, request: &mut HashMap<String, String>, ctx: &Context) -> anyhow::Result<()> {
-            if let Some(UserEmail(email)) = ctx.user_email() {
-                request.insert("email".to_string(), email);
+        async fn will_send_request(&self, request: &mut Request, ctx: &Context) -> anyhow::Result<()> {
+            if let Some(UserEmail(email)) = ctx.extensions().get::<UserEmail>() {
+                request.headers.insert("email".to_string(), email.clone());
             }
             Ok(())
         }
@@ -1,7 +1,7 @@
 use actix_web::{HttpServer, App, web::Data, HttpResponse};
 use async_graphql::http::{GraphQLPlaygroundConfig, playground_source};
 use tracing_actix_web::TracingLogger;
-use apollo_gateway_rs::{GatewayServer, actix::{graphql_request, graphql_subscription}};
+use apollo_gateway_rs::{GatewayServer, actix::{graphql_request, graphql_subscription, health, ready, status}};
 use crate::common_source::CommonSource;
 
 pub async fn playground() -> HttpResponse {
@@ -22,6 +22,10 @@ fn configure_api(config: &mut actix_web::web::ServiceConfig) {
             )
             .route(actix_web::web::get().to(playground)),
     );
+    config
+        .route("/health", actix_web::web::get().to(health))
+        .route("/ready", actix_web::web::get().to(ready))
+        .route("/status", actix_web::web::get().to(status));
 }
 
 fn init_tracing() {